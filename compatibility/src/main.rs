@@ -1,13 +1,37 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use decentnet_manifest::{
+    bundle::{apply_bundle, create_bundle},
+    file::PodFileRoot,
+    io::{load_toml, save_private_toml},
+    manifest::PodManifest,
+    store::PodStore,
+    threshold::{
+        identifier_from_hex,
+        recovery::{recover_share, repair_deltas, repair_sigma, RepairDelta, RepairSigma},
+        PublicKeyPackage, SecretShare,
+    },
+    zeronet::{
+        file::{ModifiedSource, SiteOptions},
+        migrate::migrate_site_with,
+    },
+    Error as ManifestError,
+};
 use serde_bytes::ByteBuf;
 use serde_json::Error as JsonError;
 use zerucontent::Content;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// ZeroNet Data Directory
     #[arg(short, long)]
     data_dir: Option<String>,
@@ -21,6 +45,136 @@ struct Args {
     print_missing: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Migrate ZeroNet site into DecentNet pod
+    Migrate {
+        /// ZeroNet Site Directory
+        src: PathBuf,
+        /// DecentNet Pod Directory
+        dst: PathBuf,
+        /// Take file modified times from disk instead of content.json
+        #[arg(long, default_value = "false")]
+        mtime: bool,
+    },
+    /// Recover lost FROST key share with help of other signers
+    Recover {
+        #[command(subcommand)]
+        step: RecoverStep,
+    },
+    /// Show changes between two versions of DecentNet pod
+    Diff {
+        /// Pod Directory of old version
+        old: PathBuf,
+        /// Pod Directory of new version
+        new: PathBuf,
+    },
+    /// Ship changes of DecentNet pod as update bundle
+    Bundle {
+        #[command(subcommand)]
+        op: BundleOp,
+    },
+    /// Keep and restore versions of DecentNet pod
+    Store {
+        /// DecentNet Pod Directory
+        pod: PathBuf,
+        #[command(subcommand)]
+        op: StoreOp,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BundleOp {
+    /// Write bundle of pod holding only files the old version lacks
+    Create {
+        /// DecentNet Pod Directory
+        pod: PathBuf,
+        /// files.toml of version held by receiver, whole pod if absent
+        #[arg(long)]
+        old: Option<PathBuf>,
+        /// Bundle file
+        out: PathBuf,
+    },
+    /// Verify bundle and update pod with it
+    Apply {
+        /// DecentNet Pod Directory
+        pod: PathBuf,
+        /// Address of pod, required when the pod has no manifest.toml yet
+        #[arg(long)]
+        address: Option<String>,
+        /// Bundle file
+        bundle: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StoreOp {
+    /// Store signed manifest.toml, files.toml and files of pod
+    Commit,
+    /// List stored versions, latest first
+    Log,
+    /// Restore pod to stored version
+    Checkout {
+        /// Digest of manifest, as listed by log
+        hash: String,
+        /// Discard changes not committed yet
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove stored objects no longer needed
+    Gc {
+        /// Number of latest versions to keep, all if absent
+        #[arg(long)]
+        keep: Option<usize>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RecoverStep {
+    /// Helper computes a delta file for every helper
+    Delta {
+        /// Key share of helper
+        #[arg(long)]
+        share: PathBuf,
+        /// Hex encoded identifier of participant who lost their share
+        #[arg(long)]
+        participant: String,
+        /// Hex encoded identifiers of all helpers
+        #[arg(long, num_args = 1.., required = true)]
+        helpers: Vec<String>,
+        /// Directory to write delta files into, one per helper
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Helper sums delta files addressed to it into a sigma file
+    Sigma {
+        /// Key share of helper
+        #[arg(long)]
+        share: PathBuf,
+        /// Sigma file for participant
+        #[arg(long)]
+        out: PathBuf,
+        /// Delta files received from every helper
+        #[arg(required = true)]
+        deltas: Vec<PathBuf>,
+    },
+    /// Participant combines sigma files of every helper into a new share
+    Combine {
+        /// Hex encoded identifier of participant
+        #[arg(long)]
+        participant: String,
+        /// Public key package of group
+        #[arg(long)]
+        pubkeys: PathBuf,
+        /// Recovered key share
+        #[arg(long)]
+        out: PathBuf,
+        /// Sigma files received from every helper
+        #[arg(required = true)]
+        sigmas: Vec<PathBuf>,
+    },
+}
+
 enum Error {
     MissingFile,
     Io(std::io::Error),
@@ -31,6 +185,65 @@ enum Error {
 fn main() {
     let args = Args::parse();
 
+    if let Some(Command::Recover { step }) = args.command {
+        match recover(step) {
+            Ok(message) => println!("{}", message),
+            Err(err) => fail(format!("Share recovery failed: {}", err)),
+        }
+        return;
+    }
+
+    if let Some(Command::Diff { old, new }) = args.command {
+        let diff = load_pod(&old).and_then(|old_pod| old_pod.diff(&load_pod(&new)?));
+        match diff {
+            Ok(diff) if diff.is_empty() => println!("No changes"),
+            Ok(diff) => print!("{}", diff),
+            Err(err) => fail(format!(
+                "Diff of {} and {} failed: {}",
+                old.display(),
+                new.display(),
+                err
+            )),
+        }
+        return;
+    }
+
+    if let Some(Command::Bundle { op }) = args.command {
+        match bundle(op) {
+            Ok(message) => println!("{}", message),
+            Err(err) => fail(format!("Bundle failed: {}", err)),
+        }
+        return;
+    }
+
+    if let Some(Command::Store { pod, op }) = args.command {
+        match store(&pod, op) {
+            Ok(message) => println!("{}", message),
+            Err(err) => fail(format!("Store of {} failed: {}", pod.display(), err)),
+        }
+        return;
+    }
+
+    if let Some(Command::Migrate { src, dst, mtime }) = args.command {
+        let options = SiteOptions {
+            modified: if mtime {
+                ModifiedSource::FileSystem
+            } else {
+                ModifiedSource::Content
+            },
+            ..Default::default()
+        };
+        match migrate_site_with(&src, &dst, &options) {
+            Ok(report) if report.is_complete() => print!("{}", report),
+            Ok(report) => {
+                print!("{}", report);
+                fail(format!("Migration of {} is incomplete", src.display()));
+            }
+            Err(err) => fail(format!("Migration of {} failed: {}", src.display(), err)),
+        }
+        return;
+    }
+
     if let Some(site_dir) = args.site_dir {
         let site_dir = PathBuf::from(site_dir);
         if !site_dir.is_dir() {
@@ -95,6 +308,137 @@ fn main() {
     }
 }
 
+/// Report failure on stderr and exit with non-zero status, so that scripts
+/// driving a subcommand can tell it failed
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+fn recover(step: RecoverStep) -> Result<String, ManifestError> {
+    match step {
+        RecoverStep::Delta {
+            share,
+            participant,
+            helpers,
+            out,
+        } => {
+            let share: SecretShare = load_toml(share)?;
+            let helpers = helpers
+                .iter()
+                .map(|helper| identifier_from_hex(helper))
+                .collect::<Result<Vec<_>, _>>()?;
+            let deltas = repair_deltas(
+                &share,
+                &helpers,
+                identifier_from_hex(&participant)?,
+                &mut rand::thread_rng(),
+            )?;
+            for delta in &deltas {
+                delta.save(out.join(format!("delta-{}.toml", delta.recipient)))?;
+            }
+            Ok(format!(
+                "Wrote {} delta files to {}, send each to its helper",
+                deltas.len(),
+                out.display()
+            ))
+        }
+        RecoverStep::Sigma { share, out, deltas } => {
+            let share: SecretShare = load_toml(share)?;
+            let deltas = deltas
+                .iter()
+                .map(RepairDelta::load)
+                .collect::<Result<Vec<_>, _>>()?;
+            repair_sigma(&share, &deltas)?.save(&out)?;
+            Ok(format!(
+                "Wrote {}, send it to recovering participant",
+                out.display()
+            ))
+        }
+        RecoverStep::Combine {
+            participant,
+            pubkeys,
+            out,
+            sigmas,
+        } => {
+            let pubkeys: PublicKeyPackage = load_toml(pubkeys)?;
+            let sigmas = sigmas
+                .iter()
+                .map(RepairSigma::load)
+                .collect::<Result<Vec<_>, _>>()?;
+            let share = recover_share(identifier_from_hex(&participant)?, &sigmas, &pubkeys)?;
+            save_private_toml(&share, &out)?;
+            Ok(format!("Recovered key share written to {}", out.display()))
+        }
+    }
+}
+
+/// Manifest of pod directory along with its files.toml, if present
+fn load_pod(dir: &Path) -> Result<PodManifest, ManifestError> {
+    let mut manifest: PodManifest = load_toml(dir.join("manifest.toml"))?;
+    if let Some(files) = &mut manifest.files {
+        let path = dir.join(&files.manifest);
+        if path.is_file() {
+            files.file_root = load_toml(path)?;
+        }
+    }
+    Ok(manifest)
+}
+
+fn bundle(op: BundleOp) -> Result<String, ManifestError> {
+    match op {
+        BundleOp::Create { pod, old, out } => {
+            let old: PodFileRoot = match old {
+                Some(old) => load_toml(old)?,
+                None => PodFileRoot::default(),
+            };
+            let file = File::create(&out).map_err(|err| ManifestError::io(&out, err))?;
+            let included = create_bundle(&pod, &old, file)?;
+            Ok(format!(
+                "Wrote {} with {} files",
+                out.display(),
+                included.len()
+            ))
+        }
+        BundleOp::Apply {
+            pod,
+            address,
+            bundle,
+        } => {
+            let file = File::open(&bundle).map_err(|err| ManifestError::io(&bundle, err))?;
+            let digest = apply_bundle(&pod, address.as_deref(), file)?;
+            Ok(format!("Updated {} to {}", pod.display(), digest))
+        }
+    }
+}
+
+fn store(pod: &Path, op: StoreOp) -> Result<String, ManifestError> {
+    let store = PodStore::open(pod)?;
+    match op {
+        StoreOp::Commit => Ok(format!("Committed {}", store.commit()?)),
+        StoreOp::Log => Ok(store
+            .log()?
+            .iter()
+            .map(|entry| {
+                let modified = entry
+                    .modified
+                    .map(|modified| modified.to_rfc3339())
+                    .unwrap_or_default();
+                format!("{} {} {}", entry.hash, modified, entry.signers.join(","))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        StoreOp::Checkout { hash, force } => {
+            store.checkout(&hash, force)?;
+            Ok(format!(
+                "Checked out {}, sign it as update of HEAD to publish it again",
+                hash
+            ))
+        }
+        StoreOp::Gc { keep } => Ok(format!("Removed {} objects", store.gc(keep)?.len())),
+    }
+}
+
 fn handle_error(site: &str, path: &str, error: &Error, print_missing: bool) -> Option<String> {
     if let Error::MissingFile = error {
         if !print_missing {
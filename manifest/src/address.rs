@@ -0,0 +1,192 @@
+use std::{fmt, str::FromStr};
+
+use bitcoin::{
+    base64::{engine::general_purpose::STANDARD, Engine},
+    hashes::Hash,
+    key::{Keypair, TapTweak, XOnlyPublicKey},
+    secp256k1::{rand, schnorr, Message, Secp256k1},
+    sign_message::{signed_msg_hash, MessageSignature},
+    AddressType, Network, PrivateKey,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+
+/// Kinds of pod addresses, each with its own signature scheme
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    /// Legacy base58 `1...` address of ZeroNet, signed with
+    /// ECDSA bitcoin message signatures
+    #[default]
+    P2pkh,
+    /// Taproot bech32m `bc1p...` address, signed with BIP-340
+    /// Schnorr signatures by its tweaked output key
+    P2tr,
+}
+
+/// Mainnet bitcoin address of a pod or signer, validated including checksum.
+/// Serialised as its string form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address(bitcoin::Address);
+
+impl Address {
+    /// Address of kind for private key
+    pub fn from_key(key: &PrivateKey, kind: AddressKind) -> Self {
+        let secp = Secp256k1::new();
+        let address = match kind {
+            AddressKind::P2pkh => bitcoin::Address::p2pkh(&key.public_key(&secp), Network::Bitcoin),
+            AddressKind::P2tr => {
+                let keypair = Keypair::from_secret_key(&secp, &key.inner);
+                let (internal_key, _) = keypair.x_only_public_key();
+                bitcoin::Address::p2tr(&secp, internal_key, None, Network::Bitcoin)
+            }
+        };
+        Address(address)
+    }
+
+    pub fn kind(&self) -> AddressKind {
+        match self.0.address_type() {
+            Some(AddressType::P2tr) => AddressKind::P2tr,
+            _ => AddressKind::P2pkh,
+        }
+    }
+
+    /// Verify base64 encoded signature of message, as bitcoin message
+    /// signature for P2PKH and as Schnorr signature for P2TR addresses
+    pub fn verify(&self, message: &str, sign: &str) -> bool {
+        let secp = Secp256k1::verification_only();
+        let msg_hash = signed_msg_hash(message);
+        match self.kind() {
+            AddressKind::P2pkh => {
+                let Ok(signature) = MessageSignature::from_base64(sign) else {
+                    return false;
+                };
+                signature
+                    .is_signed_by_address(&secp, &self.0, msg_hash)
+                    .unwrap_or(false)
+            }
+            AddressKind::P2tr => {
+                let Some(output_key) = self.output_key() else {
+                    return false;
+                };
+                let Ok(signature) = STANDARD
+                    .decode(sign)
+                    .map_err(|_| ())
+                    .and_then(|bytes| schnorr::Signature::from_slice(&bytes).map_err(|_| ()))
+                else {
+                    return false;
+                };
+                let msg = Message::from_digest(msg_hash.to_byte_array());
+                secp.verify_schnorr(&signature, &msg, &output_key).is_ok()
+            }
+        }
+    }
+
+    /// Tweaked output key committed to by a P2TR address
+    fn output_key(&self) -> Option<XOnlyPublicKey> {
+        match self.kind() {
+            AddressKind::P2tr => {
+                XOnlyPublicKey::from_slice(&self.0.script_pubkey().as_bytes()[2..]).ok()
+            }
+            AddressKind::P2pkh => None,
+        }
+    }
+}
+
+/// Sign message with BIP-340 Schnorr signature of tweaked key
+/// of P2TR address of key, base64 encoded
+pub fn sign_schnorr(key: &PrivateKey, message: &str) -> String {
+    let secp = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp, &key.inner)
+        .tap_tweak(&secp, None)
+        .to_inner();
+    let msg = Message::from_digest(signed_msg_hash(message).to_byte_array());
+    let signature = secp.sign_schnorr_with_rng(&msg, &keypair, &mut rand::thread_rng());
+    STANDARD.encode(signature.as_ref())
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    /// Parse address, rejecting bad checksums, other networks
+    /// and address types other than P2PKH and P2TR
+    fn from_str(address: &str) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidAddress {
+            address: address.to_string(),
+            message,
+        };
+        let parsed = bitcoin::Address::from_str(address)
+            .map_err(|err| invalid(err.to_string()))?
+            .require_network(Network::Bitcoin)
+            .map_err(|err| invalid(err.to_string()))?;
+        match parsed.address_type() {
+            Some(AddressType::P2pkh) | Some(AddressType::P2tr) => Ok(Address(parsed)),
+            Some(kind) => Err(invalid(format!("unsupported address type {}", kind))),
+            None => Err(invalid("unknown address type".to_string())),
+        }
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        Address::from_str(&address).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{sign_schnorr, Address, AddressKind};
+    use crate::{crypto::sign_message, test_utils::new_key};
+
+    #[test]
+    fn test_parse_address() {
+        let address = Address::from_str("1BwdniBeKJ7zaPP41K8FUuYAb4AU1JqxeZ").unwrap();
+        assert_eq!(address.kind(), AddressKind::P2pkh);
+        let address =
+            Address::from_str("bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297")
+                .unwrap();
+        assert_eq!(address.kind(), AddressKind::P2tr);
+
+        // bad checksum
+        assert!(Address::from_str("1BwdniBeKJ7zaPP41K8FUuYAb4AU1JqxeY").is_err());
+        // testnet
+        assert!(Address::from_str("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").is_err());
+        // segwit v0 is neither legacy nor taproot
+        assert!(Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_err());
+    }
+
+    #[test]
+    fn test_verify_by_kind() {
+        let key = new_key();
+        let legacy = Address::from_key(&key, AddressKind::P2pkh);
+        let taproot = Address::from_key(&key, AddressKind::P2tr);
+        assert!(taproot.to_string().starts_with("bc1p"));
+        assert_eq!(Address::from_str(&taproot.to_string()).unwrap(), taproot);
+
+        let ecdsa = sign_message(&key, "Hello World!");
+        let schnorr = sign_schnorr(&key, "Hello World!");
+        assert!(legacy.verify("Hello World!", &ecdsa));
+        assert!(taproot.verify("Hello World!", &schnorr));
+        assert!(!taproot.verify("Hello World?", &schnorr));
+
+        // signature scheme follows address kind
+        assert!(!legacy.verify("Hello World!", &schnorr));
+        assert!(!taproot.verify("Hello World!", &ecdsa));
+        assert!(!Address::from_key(&new_key(), AddressKind::P2tr).verify("Hello World!", &schnorr));
+    }
+}
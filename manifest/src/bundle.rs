@@ -0,0 +1,643 @@
+//! Update bundles, shipping a new version of a pod to a peer that holds an
+//! older one. A bundle is a tar of the pod directory holding manifest.toml,
+//! files.toml and only files whose content the old version lacks. Files that
+//! are unchanged or merely moved are taken from the pod being updated.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use tar::{Archive, Builder, EntryType};
+
+use crate::{
+    error::Error,
+    file::{check_path, PodFile, PodFileRoot, LOCAL_DIR},
+    history::History,
+    io::{copy_file, load_toml},
+    manifest::PodManifest,
+    verify::FileReport,
+};
+
+/// Write bundle updating a pod from old file list to the version in pod
+/// directory. Returns paths of files included, optional files absent on disk
+/// are left out.
+pub fn create_bundle(
+    pod: impl AsRef<Path>,
+    old: &PodFileRoot,
+    out: impl Write,
+) -> Result<Vec<String>, Error> {
+    let pod = pod.as_ref();
+    let manifest_path = pod.join("manifest.toml");
+    let manifest: PodManifest = load_toml(&manifest_path)?;
+    let mut builder = Builder::new(out);
+    append(&mut builder, &manifest_path, "manifest.toml")?;
+
+    let mut included = vec![];
+    if let Some(files) = &manifest.files {
+        check_path(&files.manifest)?;
+        let files_path = pod.join(&files.manifest);
+        check(FileReport {
+            path: files.manifest.clone(),
+            optional: false,
+            status: files.verify(pod)?,
+        })?;
+        append(&mut builder, &files_path, &files.manifest)?;
+
+        let file_root: PodFileRoot = load_toml(&files_path)?;
+        file_root.check_paths()?;
+        let old_hashes = by_hash(old);
+        for (file, optional) in listed(&file_root) {
+            if old_hashes.contains_key(&file.hash.to_string()) {
+                continue;
+            }
+            let report = FileReport {
+                path: file.path.clone(),
+                optional,
+                status: file.verify(pod)?,
+            };
+            check(report)?;
+            let path = pod.join(&file.path);
+            if path.is_file() {
+                append(&mut builder, &path, &file.path)?;
+                included.push(file.path.clone());
+            }
+        }
+    }
+    builder
+        .into_inner()
+        .and_then(|mut out| out.flush())
+        .map_err(|err| Error::io(pod, err))?;
+    Ok(included)
+}
+
+/// Apply bundle to pod directory, which may not exist yet. The manifest must
+/// be validly signed with the primary signer of the pod and continue the chain
+/// from the manifest of the pod, every file must match its hash and the
+/// bundle may carry nothing but manifest.toml, files.toml and listed files.
+/// The primary signer is taken from the manifest of the pod, or from address
+/// when given, which a first apply requires as there is nothing to trust yet.
+/// The new version is assembled next to the pod and swapped in only once
+/// complete. Only files the manifest of the pod lists are replaced, local
+/// state, user directories and any other unlisted content are kept.
+/// Returns digest of new manifest.
+pub fn apply_bundle(
+    pod: impl AsRef<Path>,
+    address: Option<&str>,
+    bundle: impl Read,
+) -> Result<String, Error> {
+    let pod = pod.as_ref();
+    let staging = sibling(pod, "bundle")?;
+    remove_dir(&staging)?;
+    let result = stage(pod, &staging, address, bundle);
+    if result.is_err() {
+        remove_dir(&staging)?;
+    }
+    let digest = result?;
+
+    // content the pod holds beyond its listed files moves along
+    let carried = tracked(pod).and_then(|tracked| carry_over(pod, &staging, &tracked));
+    let carried = match carried {
+        Ok(carried) => carried,
+        Err(err) => {
+            remove_dir(&staging)?;
+            return Err(err);
+        }
+    };
+    if let Err(err) = swap(pod, &staging) {
+        // pod is left as it was, hand its content back
+        move_back(pod, &staging, &carried)?;
+        remove_dir(&staging)?;
+        return Err(err);
+    }
+    Ok(digest)
+}
+
+/// Paths listed by manifest of pod, which the new version replaces
+fn tracked(pod: &Path) -> Result<BTreeSet<String>, Error> {
+    let mut tracked = BTreeSet::new();
+    let manifest_path = pod.join("manifest.toml");
+    if !manifest_path.is_file() {
+        return Ok(tracked);
+    }
+    tracked.insert("manifest.toml".to_string());
+    let manifest: PodManifest = load_toml(&manifest_path)?;
+    if let Some(files) = &manifest.files {
+        check_path(&files.manifest)?;
+        tracked.insert(files.manifest.clone());
+        let path = pod.join(&files.manifest);
+        if path.is_file() {
+            let file_root: PodFileRoot = load_toml(&path)?;
+            file_root.check_paths()?;
+            tracked.extend(listed(&file_root).map(|(file, _)| file.path.clone()));
+        }
+    }
+    Ok(tracked)
+}
+
+/// Move everything in pod directory but tracked paths into staging, like
+/// local state, user directories with their own manifests and untracked
+/// files. Paths the new version lists win. Returns paths moved, whole
+/// directories where nothing below is tracked or staged.
+fn carry_over(
+    pod: &Path,
+    staging: &Path,
+    tracked: &BTreeSet<String>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut carried = vec![];
+    if pod.is_dir() {
+        if let Err(err) = carry_dir(pod, staging, Path::new(""), tracked, &mut carried) {
+            move_back(pod, staging, &carried)?;
+            return Err(err);
+        }
+    }
+    Ok(carried)
+}
+
+fn carry_dir(
+    pod: &Path,
+    staging: &Path,
+    relative: &Path,
+    tracked: &BTreeSet<String>,
+    carried: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let dir = pod.join(relative);
+    let entries = fs::read_dir(&dir).map_err(|err| Error::io(&dir, err))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io(&dir, err))?;
+        let path = relative.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(|err| Error::io(entry.path(), err))?;
+        let staged = staging.join(&path);
+        if file_type.is_dir() {
+            let holds_tracked = tracked
+                .iter()
+                .any(|tracked| Path::new(tracked).starts_with(&path));
+            if holds_tracked || staged.exists() {
+                carry_dir(pod, staging, &path, tracked, carried)?;
+                continue;
+            }
+        } else if tracked.contains(path.to_string_lossy().as_ref()) || staged.exists() {
+            continue;
+        }
+        if let Some(parent) = staged.parent() {
+            fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+        }
+        fs::rename(pod.join(&path), &staged).map_err(|err| Error::io(&staged, err))?;
+        carried.push(path);
+    }
+    Ok(())
+}
+
+/// Return carried paths from staging to pod, latest first
+fn move_back(pod: &Path, staging: &Path, carried: &[PathBuf]) -> Result<(), Error> {
+    for path in carried.iter().rev() {
+        let to = pod.join(path);
+        fs::rename(staging.join(path), &to).map_err(|err| Error::io(&to, err))?;
+    }
+    Ok(())
+}
+
+/// Replace pod directory with staging directory, restoring pod if that fails
+fn swap(pod: &Path, staging: &Path) -> Result<(), Error> {
+    if !pod.exists() {
+        return fs::rename(staging, pod).map_err(|err| Error::io(pod, err));
+    }
+    let old = sibling(pod, "old")?;
+    remove_dir(&old)?;
+    fs::rename(pod, &old).map_err(|err| Error::io(pod, err))?;
+    if let Err(err) = fs::rename(staging, pod) {
+        fs::rename(&old, pod).map_err(|err| Error::io(pod, err))?;
+        return Err(Error::io(pod, err));
+    }
+    // new version is in place, a leftover is cleared by the next apply
+    let _ = remove_dir(&old);
+    Ok(())
+}
+
+/// Unpack bundle into staging directory, complete it from pod and verify it
+fn stage(
+    pod: &Path,
+    staging: &Path,
+    address: Option<&str>,
+    bundle: impl Read,
+) -> Result<String, Error> {
+    let current_path = pod.join("manifest.toml");
+    let current = match current_path.is_file() {
+        true => Some(load_toml::<PodManifest>(&current_path)?),
+        false => None,
+    };
+    if current.is_none() && address.is_none() {
+        return Err(bundle_error(
+            "pod has no manifest.toml yet, its address is needed to trust the bundle",
+        ));
+    }
+    let unpacked = unpack(staging, bundle)?;
+    let manifest: PodManifest = load_toml(staging.join("manifest.toml"))?;
+    if !manifest.verify()?.is_valid() {
+        return Err(bundle_error("manifest.toml is not validly signed"));
+    }
+    if let Some(address) = address {
+        let pod_address = manifest
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.pod.as_ref())
+            .map(|pod| pod.address.as_str());
+        if manifest.signature.primary != address
+            || pod_address.is_some_and(|pod_address| pod_address != address)
+        {
+            return Err(bundle_error(format!(
+                "manifest.toml is not of pod {}",
+                address
+            )));
+        }
+    }
+    if let Some(current) = &current {
+        if current.signature.primary != manifest.signature.primary {
+            return Err(bundle_error(format!(
+                "primary signer changed from {} to {}",
+                current.signature.primary, manifest.signature.primary
+            )));
+        }
+        if let Some(chain_break) = current.check_update(&manifest)? {
+            return Err(bundle_error(format!(
+                "does not update manifest of pod: {}",
+                chain_break
+            )));
+        }
+    }
+
+    let Some(files) = &manifest.files else {
+        check_unlisted(&unpacked, &BTreeSet::from(["manifest.toml"]))?;
+        return manifest.digest();
+    };
+    check_path(&files.manifest)?;
+    check(FileReport {
+        path: files.manifest.clone(),
+        optional: false,
+        status: files.verify(staging)?,
+    })?;
+    let file_root: PodFileRoot = load_toml(staging.join(&files.manifest))?;
+    file_root.check_paths()?;
+    let mut allowed = BTreeSet::from(["manifest.toml", files.manifest.as_str()]);
+    for (file, _) in listed(&file_root) {
+        if Path::new(&file.path).starts_with(LOCAL_DIR) {
+            return Err(bundle_error(format!("{} is local state", file.path)));
+        }
+        allowed.insert(file.path.as_str());
+    }
+    check_unlisted(&unpacked, &allowed)?;
+
+    // files of the pod by hash, to take unchanged and moved files from
+    let mut local = BTreeMap::new();
+    if let Some(current_files) = current.as_ref().and_then(|current| current.files.as_ref()) {
+        let path = pod.join(&current_files.manifest);
+        if path.is_file() {
+            let current_root: PodFileRoot = load_toml(&path)?;
+            current_root.check_paths()?;
+            for (hash, file) in by_hash(&current_root) {
+                local.insert(hash, file.path.clone());
+            }
+        }
+    }
+    for (file, optional) in listed(&file_root) {
+        let path = staging.join(&file.path);
+        if !path.is_file() {
+            if let Some(from) = local.get(&file.hash.to_string()) {
+                let from = pod.join(from);
+                if from.is_file() {
+                    copy_file(&from, &path)?;
+                }
+            }
+        }
+        check(FileReport {
+            path: file.path.clone(),
+            optional,
+            status: file.verify(staging)?,
+        })?;
+    }
+    manifest.digest()
+}
+
+/// Extract regular files of bundle, refusing paths leaving the directory.
+/// Returns paths extracted.
+fn unpack(staging: &Path, bundle: impl Read) -> Result<Vec<String>, Error> {
+    let mut unpacked = vec![];
+    let mut archive = Archive::new(bundle);
+    let entries = archive.entries().map_err(|err| Error::io(staging, err))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|err| Error::io(staging, err))?;
+        let relative = entry
+            .path()
+            .map_err(|err| Error::io(staging, err))?
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+        if entry_type == EntryType::Directory {
+            continue;
+        }
+        let safe = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if entry_type != EntryType::Regular || !safe || relative.starts_with(LOCAL_DIR) {
+            return Err(bundle_error(format!(
+                "unexpected entry {}",
+                relative.display()
+            )));
+        }
+        let path = staging.join(&relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+        }
+        let mut file = File::create(&path).map_err(|err| Error::io(&path, err))?;
+        io::copy(&mut entry, &mut file).map_err(|err| Error::io(&path, err))?;
+        unpacked.push(relative.to_string_lossy().to_string());
+    }
+    Ok(unpacked)
+}
+
+/// Refuse unpacked files the manifests do not cover, they would enter the pod unsigned
+fn check_unlisted(unpacked: &[String], allowed: &BTreeSet<&str>) -> Result<(), Error> {
+    match unpacked
+        .iter()
+        .find(|path| !allowed.contains(path.as_str()))
+    {
+        Some(path) => Err(bundle_error(format!("{} is not listed", path))),
+        None => Ok(()),
+    }
+}
+
+fn append(builder: &mut Builder<impl Write>, path: &Path, name: &str) -> Result<(), Error> {
+    builder
+        .append_path_with_name(path, name)
+        .map_err(|err| Error::io(path, err))
+}
+
+/// Listed files, along with whether they are optional
+fn listed(file_root: &PodFileRoot) -> impl Iterator<Item = (&PodFile, bool)> {
+    let files = file_root.files.iter().map(|file| (file, false));
+    files.chain(file_root.files_optional.iter().map(|file| (file, true)))
+}
+
+fn by_hash(file_root: &PodFileRoot) -> BTreeMap<String, &PodFile> {
+    listed(file_root)
+        .filter(|(file, _)| !file.hash.is_empty())
+        .map(|(file, _)| (file.hash.to_string(), file))
+        .collect()
+}
+
+fn check(report: FileReport) -> Result<(), Error> {
+    match report.is_ok() {
+        true => Ok(()),
+        false => Err(bundle_error(format!(
+            "{} is {:?}",
+            report.path, report.status
+        ))),
+    }
+}
+
+/// Hidden directory next to pod directory
+fn sibling(pod: &Path, suffix: &str) -> Result<PathBuf, Error> {
+    let name = pod
+        .file_name()
+        .ok_or_else(|| bundle_error(format!("{} is not a pod directory", pod.display())))?;
+    Ok(pod.with_file_name(format!(".{}.{}", name.to_string_lossy(), suffix)))
+}
+
+fn remove_dir(path: &Path) -> Result<(), Error> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(Error::io(path, err)),
+        _ => Ok(()),
+    }
+}
+
+fn bundle_error(reason: impl Into<String>) -> Error {
+    Error::Bundle(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use bitcoin::PrivateKey;
+    use tar::{Builder, Header};
+
+    use super::{apply_bundle, create_bundle};
+    use crate::{
+        crypto::Signer,
+        error::Error,
+        file::PodFileRoot,
+        io::{load_toml, write_file},
+        manifest::PodManifest,
+        test_utils::{new_key, pod_dir, publish, publish_root},
+    };
+
+    const TMP_BUNDLE: &str = "tests/tmp/bundle";
+
+    /// Sender at v1 and receiver holding a copy of it
+    fn setup(name: &str, key: &PrivateKey) -> (PathBuf, PathBuf, PodManifest) {
+        let sender = pod_dir(TMP_BUNDLE, &format!("{}-sender", name));
+        let receiver = pod_dir(TMP_BUNDLE, &format!("{}-receiver", name));
+        write_file(sender.join("index.html"), b"v1").unwrap();
+        write_file(sender.join("logo.png"), b"logo").unwrap();
+        write_file(sender.join("gone.js"), b"js").unwrap();
+        let v1 = publish(&sender, key, 0, None);
+        let mut bundle = vec![];
+        create_bundle(&sender, &PodFileRoot::default(), &mut bundle).unwrap();
+        apply_bundle(&receiver, Some(&key.address()), bundle.as_slice()).unwrap();
+        (sender, receiver, v1)
+    }
+
+    fn file_root(pod: &Path) -> PodFileRoot {
+        load_toml(pod.join("files.toml")).unwrap()
+    }
+
+    #[test]
+    fn test_bundle_changed_files_only() {
+        let key = new_key();
+        let (sender, receiver, v1) = setup("changed", &key);
+        write_file(receiver.join(".decentnet/HEAD"), b"local").unwrap();
+        let old = file_root(&receiver);
+
+        write_file(sender.join("index.html"), b"v2").unwrap();
+        write_file(sender.join("new.js"), b"new").unwrap();
+        write_file(sender.join("optional/big.bin"), b"big").unwrap();
+        fs::create_dir_all(sender.join("img")).unwrap();
+        fs::rename(sender.join("logo.png"), sender.join("img/logo.png")).unwrap();
+        fs::remove_file(sender.join("gone.js")).unwrap();
+        let v2 = publish(&sender, &key, 1, Some(&v1));
+
+        let mut bundle = vec![];
+        let included = create_bundle(&sender, &old, &mut bundle).unwrap();
+        assert_eq!(included, vec!["index.html", "new.js", "optional/big.bin"]);
+
+        let digest = apply_bundle(&receiver, None, bundle.as_slice()).unwrap();
+        assert_eq!(digest, v2.digest().unwrap());
+        let manifest: PodManifest = load_toml(receiver.join("manifest.toml")).unwrap();
+        assert!(manifest.verify_dir(&receiver).unwrap().is_ok());
+        assert_eq!(fs::read(receiver.join("img/logo.png")).unwrap(), b"logo");
+        assert!(!receiver.join("gone.js").exists());
+        assert_eq!(
+            fs::read(receiver.join(".decentnet/HEAD")).unwrap(),
+            b"local"
+        );
+    }
+
+    /// User directories and untracked files are not part of the pod's
+    /// manifest, an update leaves them in place
+    #[test]
+    fn test_bundle_keeps_unlisted_content() {
+        let key = new_key();
+        let (sender, receiver, v1) = setup("kept", &key);
+        let user = receiver.join("data/users/1User");
+        write_file(user.join("manifest.toml"), b"user manifest").unwrap();
+        write_file(user.join("data.json"), b"{}").unwrap();
+        write_file(receiver.join("notes.txt"), b"draft").unwrap();
+        let old = file_root(&receiver);
+
+        write_file(sender.join("index.html"), b"v2").unwrap();
+        write_file(sender.join("data/index.json"), b"[]").unwrap();
+        fs::remove_file(sender.join("gone.js")).unwrap();
+        publish(&sender, &key, 1, Some(&v1));
+        let mut bundle = vec![];
+        create_bundle(&sender, &old, &mut bundle).unwrap();
+        apply_bundle(&receiver, None, bundle.as_slice()).unwrap();
+
+        assert_eq!(fs::read(receiver.join("index.html")).unwrap(), b"v2");
+        assert_eq!(fs::read(receiver.join("data/index.json")).unwrap(), b"[]");
+        assert!(!receiver.join("gone.js").exists());
+        assert_eq!(
+            fs::read(user.join("manifest.toml")).unwrap(),
+            b"user manifest"
+        );
+        assert_eq!(fs::read(user.join("data.json")).unwrap(), b"{}");
+        assert_eq!(fs::read(receiver.join("notes.txt")).unwrap(), b"draft");
+    }
+
+    #[test]
+    fn test_bundle_rejected() {
+        let key = new_key();
+        let (sender, receiver, v1) = setup("rejected", &key);
+        let old = file_root(&receiver);
+        let unchanged = || fs::read(receiver.join("manifest.toml")).unwrap();
+        let before = unchanged();
+
+        // validly signed, but not linked to v1
+        write_file(sender.join("index.html"), b"v2").unwrap();
+        publish(&sender, &key, 1, None);
+        let mut bundle = vec![];
+        create_bundle(&sender, &old, &mut bundle).unwrap();
+        assert!(apply_bundle(&receiver, None, bundle.as_slice()).is_err());
+
+        // validly signed by someone else
+        publish(&sender, &new_key(), 1, Some(&v1));
+        let mut bundle = vec![];
+        create_bundle(&sender, &old, &mut bundle).unwrap();
+        assert!(apply_bundle(&receiver, None, bundle.as_slice()).is_err());
+
+        // file swapped in transit
+        publish(&sender, &key, 1, Some(&v1));
+        let mut builder = Builder::new(vec![]);
+        for name in ["manifest.toml", "files.toml"] {
+            builder
+                .append_path_with_name(sender.join(name), name)
+                .unwrap();
+        }
+        let mut header = Header::new_gnu();
+        header.set_size(2);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "index.html", &b"v3"[..])
+            .unwrap();
+        let bundle = builder.into_inner().unwrap();
+        assert!(apply_bundle(&receiver, None, bundle.as_slice()).is_err());
+
+        // paths outside of pod
+        let mut builder = Builder::new(vec![]);
+        let mut header = Header::new_gnu();
+        header.set_size(2);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, ".decentnet/HEAD", &b"v3"[..])
+            .unwrap();
+        let bundle = builder.into_inner().unwrap();
+        assert!(apply_bundle(&receiver, None, bundle.as_slice()).is_err());
+
+        assert_eq!(unchanged(), before);
+        assert!(!Path::new(TMP_BUNDLE)
+            .join(".rejected-receiver.bundle")
+            .exists());
+    }
+
+    /// Bundle of manifest.toml and files.toml of pod, followed by extra files
+    fn raw_bundle(pod: &Path, extra: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+        for name in ["manifest.toml", "files.toml"] {
+            builder.append_path_with_name(pod.join(name), name).unwrap();
+        }
+        for (name, content) in extra {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_bundle_rejects_unlisted_and_escaping() {
+        let key = new_key();
+        let (sender, receiver, v1) = setup("unlisted", &key);
+        let before = fs::read(receiver.join("manifest.toml")).unwrap();
+
+        // valid update carrying a file files.toml does not list
+        write_file(sender.join("index.html"), b"v2").unwrap();
+        publish(&sender, &key, 1, Some(&v1));
+        let bundle = raw_bundle(&sender, &[("index.html", b"v2"), ("evil.js", b"evil")]);
+        let err = apply_bundle(&receiver, None, bundle.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::Bundle(_)));
+        assert!(!receiver.join("evil.js").exists());
+
+        // the same on a first apply
+        let fresh = pod_dir(TMP_BUNDLE, "unlisted-fresh").join("pod");
+        assert!(apply_bundle(&fresh, Some(&key.address()), bundle.as_slice()).is_err());
+        assert!(!fresh.exists());
+
+        // signed listing reaching out of staging for a file held by the pod
+        let mut file_root = file_root(&receiver);
+        file_root.files[0].path = "../escape.html".to_string();
+        publish_root(&sender, &key, 1, Some(&v1), file_root);
+        let bundle = raw_bundle(&sender, &[]);
+        let err = apply_bundle(&receiver, None, bundle.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::InvalidPath { .. }));
+        assert!(!Path::new(TMP_BUNDLE).join("escape.html").exists());
+
+        assert_eq!(fs::read(receiver.join("manifest.toml")).unwrap(), before);
+    }
+
+    #[test]
+    fn test_first_apply_needs_address() {
+        let key = new_key();
+        let sender = pod_dir(TMP_BUNDLE, "first-sender");
+        write_file(sender.join("index.html"), b"v1").unwrap();
+        publish(&sender, &key, 0, None);
+        let mut bundle = vec![];
+        create_bundle(&sender, &PodFileRoot::default(), &mut bundle).unwrap();
+
+        let receiver = pod_dir(TMP_BUNDLE, "first-receiver").join("pod");
+        let err = apply_bundle(&receiver, None, bundle.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::Bundle(_)));
+        // validly self-signed, but by another key than the pod expected
+        let other = new_key().address();
+        assert!(apply_bundle(&receiver, Some(&other), bundle.as_slice()).is_err());
+        assert!(!receiver.exists());
+
+        apply_bundle(&receiver, Some(&key.address()), bundle.as_slice()).unwrap();
+        assert_eq!(fs::read(receiver.join("index.html")).unwrap(), b"v1");
+    }
+}
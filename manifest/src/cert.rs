@@ -0,0 +1,130 @@
+//! Certificates of user manifests, as issued by ZeroNet ID providers.
+//! An issuer signs `user_address#auth_type/name` for `cert_user_id`
+//! `name@domain`, and parents list issuer addresses by domain in
+//! `user_contents.cert_signers`.
+
+use std::collections::BTreeMap;
+
+use zerucontent::Cert;
+
+use crate::{
+    crypto::{verify_message, Signer},
+    error::Error,
+};
+
+/// Verification of certs
+pub trait CertExt {
+    /// Name and domain of `cert_user_id`, None unless it is `name@domain`
+    fn name_domain(&self) -> Option<(&str, &str)>;
+
+    /// Message signed by issuer for user address
+    fn message(&self, user_address: &str) -> Option<String>;
+
+    /// Whether cert of user is signed by an issuer listed
+    /// for its domain in `cert_signers`
+    fn verify(&self, user_address: &str, cert_signers: &BTreeMap<String, Vec<String>>) -> bool;
+
+    /// Whether cert of user is signed by any of issuers
+    fn verify_by(&self, user_address: &str, issuers: &[String]) -> bool;
+}
+
+impl CertExt for Cert {
+    fn name_domain(&self) -> Option<(&str, &str)> {
+        self.cert_user_id.split_once('@').filter(|(name, domain)| {
+            !name.is_empty() && !domain.is_empty() && !domain.contains('@')
+        })
+    }
+
+    fn message(&self, user_address: &str) -> Option<String> {
+        let (name, _) = self.name_domain()?;
+        Some(cert_message(user_address, &self.cert_auth_type, name))
+    }
+
+    fn verify(&self, user_address: &str, cert_signers: &BTreeMap<String, Vec<String>>) -> bool {
+        match self
+            .name_domain()
+            .and_then(|(_, domain)| cert_signers.get(domain))
+        {
+            Some(issuers) => self.verify_by(user_address, issuers),
+            None => false,
+        }
+    }
+
+    fn verify_by(&self, user_address: &str, issuers: &[String]) -> bool {
+        let Some(message) = self.message(user_address) else {
+            return false;
+        };
+        issuers
+            .iter()
+            .any(|issuer| verify_message(issuer, &message, &self.cert_sign))
+    }
+}
+
+/// Issue cert `name@domain` to user address, signed by issuer,
+/// e.g. a key held in keystore
+pub fn issue_cert(
+    issuer: &impl Signer,
+    user_address: &str,
+    auth_type: &str,
+    name: &str,
+    domain: &str,
+) -> Result<Cert, Error> {
+    if name.is_empty() || name.contains(['@', '/']) || domain.is_empty() || domain.contains('@') {
+        return Err(Error::Sign(format!(
+            "invalid cert user id `{}@{}`",
+            name, domain
+        )));
+    }
+    Ok(Cert {
+        cert_auth_type: auth_type.to_string(),
+        cert_sign: issuer.sign(&cert_message(user_address, auth_type, name))?,
+        cert_user_id: format!("{}@{}", name, domain),
+    })
+}
+
+fn cert_message(user_address: &str, auth_type: &str, name: &str) -> String {
+    format!("{}#{}/{}", user_address, auth_type, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{issue_cert, CertExt};
+    use crate::{
+        address::AddressKind,
+        keystore::{KdfParams, Keystore},
+        test_utils::new_key,
+    };
+
+    #[test]
+    fn test_issue_from_keystore() {
+        let mut rng = rand::thread_rng();
+        let kdf = KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let mut keystore = Keystore::create("hunter2", kdf, &mut rng).unwrap();
+        let wif = new_key().to_wif();
+        let issuer = keystore
+            .import_wif(&wif, AddressKind::P2pkh, &mut rng)
+            .unwrap();
+        let signer = keystore.signer(&issuer).unwrap();
+
+        let user = "1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7";
+        let cert = issue_cert(&signer, user, "web", "alice", "example.bit").unwrap();
+        assert_eq!(cert.cert_user_id, "alice@example.bit");
+        let cert_signers = BTreeMap::from([("example.bit".to_string(), vec![issuer])]);
+        assert!(cert.verify(user, &cert_signers));
+        // cert is bound to user and domain
+        assert!(!cert.verify("1TaLkFrMwvbNsooF4ioKAY9EuxTBTjipT", &cert_signers));
+        let other = BTreeMap::from([(
+            "zeroid.bit".to_string(),
+            cert_signers["example.bit"].clone(),
+        )]);
+        assert!(!cert.verify(user, &other));
+
+        assert!(issue_cert(&signer, user, "web", "al@ce", "example.bit").is_err());
+    }
+}
@@ -0,0 +1,499 @@
+//! Content-defined chunking of large optional files with FastCDC. Chunk
+//! boundaries follow content rather than offsets, so an edit only changes
+//! the chunks around it and the rest are shared between versions, and
+//! between pods hosting the same file. A chunked file lists its chunks in
+//! files.toml under a root hash over that list, and since the root is
+//! covered by `PodFileRoot::root_hash`, every chunk can be verified on its
+//! own before the whole file is present.
+
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use fastcdc::v2020::{
+    StreamCDC, AVERAGE_MAX, AVERAGE_MIN, MAXIMUM_MAX, MAXIMUM_MIN, MINIMUM_MAX, MINIMUM_MIN,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    file::PodFile,
+    hash::{FileHash, FileHasher, HashAlgorithm},
+    io::write_file,
+};
+
+/// Chunk size bounds in bytes, passed on to FastCDC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// No chunk is smaller, except the last one
+    pub min_size: u32,
+    /// Size chunks are normalised around
+    pub avg_size: u32,
+    /// No chunk is larger
+    pub max_size: u32,
+}
+
+impl Default for ChunkOptions {
+    /// 256 KiB to 4 MiB, averaging 1 MiB
+    fn default() -> Self {
+        ChunkOptions {
+            min_size: 256 * 1024,
+            avg_size: 1024 * 1024,
+            max_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl ChunkOptions {
+    /// Check sizes are ordered and within the bounds FastCDC accepts
+    pub fn validate(&self) -> Result<(), Error> {
+        let in_range = (MINIMUM_MIN..=MINIMUM_MAX).contains(&self.min_size)
+            && (AVERAGE_MIN..=AVERAGE_MAX).contains(&self.avg_size)
+            && (MAXIMUM_MIN..=MAXIMUM_MAX).contains(&self.max_size);
+        if !in_range || self.min_size > self.avg_size || self.avg_size > self.max_size {
+            return Err(Error::Chunk(format!(
+                "sizes {}/{}/{} are out of range",
+                self.min_size, self.avg_size, self.max_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether file at path is larger than a single chunk
+    pub fn should_chunk(&self, path: &Path) -> Result<bool, Error> {
+        let metadata = fs::metadata(path).map_err(|err| Error::io(path, err))?;
+        Ok(metadata.len() > self.max_size as u64)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
+pub struct PodChunk {
+    /// Hash of this chunk
+    pub hash: FileHash,
+    /// Size of this chunk in bytes
+    pub size: usize,
+}
+
+impl PodChunk {
+    /// Whether bytes are this chunk
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        bytes.len() == self.size && self.hash.verify(bytes)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
+pub struct PodChunks {
+    /// Hash over chunk list
+    pub root: FileHash,
+    /// Chunks in order of appearance in file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub list: Vec<PodChunk>,
+}
+
+impl PodChunks {
+    /// Split everything read from reader into chunks, hashing the whole of
+    /// it along the way. Returns hash and size of the whole as well.
+    pub fn from_reader(
+        algorithm: HashAlgorithm,
+        reader: impl Read,
+        options: &ChunkOptions,
+    ) -> io::Result<(FileHash, u64, Self)> {
+        options
+            .validate()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+        let mut reader = HashReader {
+            inner: reader,
+            hasher: algorithm.hasher(),
+        };
+        let chunker = StreamCDC::new(
+            &mut reader,
+            options.min_size,
+            options.avg_size,
+            options.max_size,
+        );
+        let mut list = vec![];
+        let mut size = 0;
+        for chunk in chunker {
+            let chunk = chunk?;
+            size += chunk.length as u64;
+            list.push(PodChunk {
+                hash: FileHash::digest(algorithm, &chunk.data),
+                size: chunk.length,
+            });
+        }
+        let hash = FileHash::from_multihash(reader.hasher.finalize());
+        let mut chunks = PodChunks {
+            root: FileHash::default(),
+            list,
+        };
+        chunks.root = chunks.root_hash(algorithm);
+        Ok((hash, size, chunks))
+    }
+
+    /// Hash over chunk list, in order
+    pub fn root_hash(&self, algorithm: HashAlgorithm) -> FileHash {
+        let mut list = String::new();
+        for chunk in &self.list {
+            list.push_str(&format!("{}\t{}\n", chunk.hash, chunk.size));
+        }
+        FileHash::digest(algorithm, list.as_bytes())
+    }
+
+    /// Whether root matches chunk list
+    pub fn verify_root(&self) -> bool {
+        match self.root.algorithm() {
+            Some(algorithm) => self.root == self.root_hash(algorithm),
+            None => false,
+        }
+    }
+
+    /// Size of file made up by chunks
+    pub fn size(&self) -> usize {
+        self.list.iter().map(|chunk| chunk.size).sum()
+    }
+
+    /// Chunks along with their offset in file
+    pub fn ranges(&self) -> impl Iterator<Item = (usize, &PodChunk)> {
+        self.list.iter().scan(0, |offset, chunk| {
+            let start = *offset;
+            *offset += chunk.size;
+            Some((start, chunk))
+        })
+    }
+
+    /// Whether bytes are the chunk at index
+    pub fn verify_chunk(&self, index: usize, bytes: &[u8]) -> bool {
+        self.list
+            .get(index)
+            .is_some_and(|chunk| chunk.verify(bytes))
+    }
+
+    /// Indexes of chunks not intact in content of reader, such as a
+    /// partially downloaded file. Chunks past its end count as not intact.
+    pub fn verify_reader(&self, mut reader: impl Read) -> io::Result<Vec<usize>> {
+        let mut broken = vec![];
+        let mut bytes = vec![];
+        for (index, chunk) in self.list.iter().enumerate() {
+            bytes.clear();
+            (&mut reader)
+                .take(chunk.size as u64)
+                .read_to_end(&mut bytes)?;
+            if !chunk.verify(&bytes) {
+                broken.push(index);
+            }
+        }
+        Ok(broken)
+    }
+}
+
+/// Reader feeding everything read through it to hasher
+struct HashReader<R> {
+    inner: R,
+    hasher: FileHasher,
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+/// Chunks stored by hash, shared by every version and pod using the same
+/// directory, so a chunk is kept and downloaded only once
+#[derive(Debug, Clone)]
+pub struct ChunkStore {
+    root: PathBuf,
+}
+
+impl ChunkStore {
+    /// Open chunk store in directory, creating it if absent
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|err| Error::io(&root, err))?;
+        Ok(ChunkStore { root })
+    }
+
+    fn chunk_path(&self, chunk: &PodChunk) -> PathBuf {
+        self.root.join(chunk.hash.to_string())
+    }
+
+    /// Whether chunk is stored
+    pub fn contains(&self, chunk: &PodChunk) -> bool {
+        !chunk.hash.is_empty() && self.chunk_path(chunk).is_file()
+    }
+
+    /// Chunks of list not stored yet, each listed once
+    pub fn missing<'a>(&self, chunks: &'a PodChunks) -> Vec<&'a PodChunk> {
+        let mut missing: Vec<&PodChunk> = vec![];
+        for chunk in &chunks.list {
+            if !self.contains(chunk) && !missing.contains(&chunk) {
+                missing.push(chunk);
+            }
+        }
+        missing
+    }
+
+    /// Store bytes of chunk after verifying them. Returns whether it was
+    /// not stored before.
+    pub fn put(&self, chunk: &PodChunk, bytes: &[u8]) -> Result<bool, Error> {
+        if !chunk.verify(bytes) {
+            return Err(Error::Chunk(format!(
+                "{} bytes do not match chunk {} of {} bytes",
+                bytes.len(),
+                chunk.hash,
+                chunk.size
+            )));
+        }
+        if self.contains(chunk) {
+            return Ok(false);
+        }
+        write_file(self.chunk_path(chunk), bytes)?;
+        Ok(true)
+    }
+
+    /// Bytes of stored chunk, verified against its hash
+    pub fn get(&self, chunk: &PodChunk) -> Result<Vec<u8>, Error> {
+        let path = self.chunk_path(chunk);
+        let bytes = fs::read(&path).map_err(|err| Error::io(&path, err))?;
+        if !chunk.verify(&bytes) {
+            return Err(Error::Chunk(format!(
+                "stored chunk {} is corrupt",
+                chunk.hash
+            )));
+        }
+        Ok(bytes)
+    }
+
+    /// Store chunks of file at path, every one of them verified.
+    /// Returns number of chunks not stored before.
+    pub fn import(&self, path: impl AsRef<Path>, chunks: &PodChunks) -> Result<usize, Error> {
+        let path = path.as_ref();
+        check_root(chunks)?;
+        let mut file = File::open(path).map_err(|err| Error::io(path, err))?;
+        let mut added = 0;
+        let mut bytes = vec![];
+        for chunk in &chunks.list {
+            bytes.clear();
+            (&mut file)
+                .take(chunk.size as u64)
+                .read_to_end(&mut bytes)
+                .map_err(|err| Error::io(path, err))?;
+            if self.put(chunk, &bytes)? {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Write chunked file to path from its chunks, all of them have to be
+    /// stored. The file is assembled next to path and only moved over it
+    /// once it matches its listed size and hash, so a failed assembly leaves
+    /// whatever was at path untouched.
+    pub fn assemble(&self, file: &PodFile, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let Some(chunks) = &file.chunks else {
+            return Err(Error::Chunk(format!("{} is not chunked", file.path)));
+        };
+        let Some(algorithm) = file.hash.algorithm() else {
+            return Err(Error::Chunk(format!("{} has no hash", file.path)));
+        };
+        check_root(chunks)?;
+        if chunks.size() != file.size {
+            return Err(Error::Chunk(format!(
+                "chunks of {} add up to {} bytes instead of {}",
+                file.path,
+                chunks.size(),
+                file.size
+            )));
+        }
+        if let Some(chunk) = self.missing(chunks).first() {
+            return Err(Error::Chunk(format!("chunk {} is not stored", chunk.hash)));
+        }
+        let Some(name) = path.file_name() else {
+            return Err(Error::Chunk(format!("{} is not a file", path.display())));
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+        }
+        let part = path.with_file_name(format!(".{}.part", name.to_string_lossy()));
+        let mut hasher = algorithm.hasher();
+        let result = self.write_chunks(chunks, &part, &mut hasher);
+        let result = result.and_then(|()| {
+            if FileHash::from_multihash(hasher.finalize()) != file.hash {
+                return Err(Error::Chunk(format!(
+                    "chunks of {} do not match its hash {}",
+                    file.path, file.hash
+                )));
+            }
+            fs::rename(&part, path).map_err(|err| Error::io(path, err))
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&part);
+        }
+        result
+    }
+
+    fn write_chunks(
+        &self,
+        chunks: &PodChunks,
+        path: &Path,
+        hasher: &mut FileHasher,
+    ) -> Result<(), Error> {
+        let mut file = File::create(path).map_err(|err| Error::io(path, err))?;
+        for chunk in &chunks.list {
+            let bytes = self.get(chunk)?;
+            hasher
+                .write_all(&bytes)
+                .and_then(|()| file.write_all(&bytes))
+                .map_err(|err| Error::io(path, err))?;
+        }
+        Ok(())
+    }
+}
+
+fn check_root(chunks: &PodChunks) -> Result<(), Error> {
+    if !chunks.verify_root() {
+        return Err(Error::Chunk(format!(
+            "chunk list does not match root {}",
+            chunks.root
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Utc;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    use super::{ChunkOptions, ChunkStore, PodChunks};
+    use crate::{
+        error::Error,
+        file::PodFile,
+        hash::{FileHash, HashAlgorithm},
+        io::write_file,
+    };
+
+    const OPTIONS: ChunkOptions = ChunkOptions {
+        min_size: 4 * 1024,
+        avg_size: 16 * 1024,
+        max_size: 64 * 1024,
+    };
+
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        StdRng::seed_from_u64(seed).fill_bytes(&mut bytes);
+        bytes
+    }
+
+    fn chunk(bytes: &[u8]) -> (FileHash, PodChunks) {
+        let (hash, size, chunks) =
+            PodChunks::from_reader(HashAlgorithm::Blake3, bytes, &OPTIONS).unwrap();
+        assert_eq!(size as usize, bytes.len());
+        (hash, chunks)
+    }
+
+    #[test]
+    fn test_chunk_verify() {
+        let mut data = random_bytes(1, 1024 * 1024);
+        let (hash, chunks) = chunk(&data);
+        assert_eq!(hash, FileHash::digest(HashAlgorithm::Blake3, &data));
+        assert!(chunks.list.len() > 1);
+        assert!(chunks
+            .list
+            .iter()
+            .all(|chunk| chunk.size <= OPTIONS.max_size as usize));
+        assert_eq!(chunks.size(), data.len());
+        assert!(chunks.verify_root());
+
+        let (offset, second) = chunks.ranges().nth(1).unwrap();
+        assert_eq!(offset, chunks.list[0].size);
+        let bytes = &data[offset..offset + second.size];
+        assert!(chunks.verify_chunk(1, bytes));
+        assert!(!chunks.verify_chunk(0, bytes));
+        assert!(chunks.verify_reader(&data[..]).unwrap().is_empty());
+
+        // one flipped byte breaks its chunk only, a cut file the chunks past its end
+        data[offset] ^= 1;
+        assert_eq!(chunks.verify_reader(&data[..]).unwrap(), vec![1]);
+        let last = chunks.list.len() - 1;
+        let (end, _) = chunks.ranges().last().unwrap();
+        assert_eq!(chunks.verify_reader(&data[..end]).unwrap(), vec![1, last]);
+
+        let mut forged = chunks.clone();
+        forged.list.swap(0, 1);
+        assert!(!forged.verify_root());
+
+        let err = PodChunks::from_reader(
+            HashAlgorithm::Blake3,
+            &data[..],
+            &ChunkOptions {
+                min_size: 1,
+                ..OPTIONS
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(ChunkOptions::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_dedup_across_versions() {
+        let dir = "tests/tmp/chunk_store";
+        let _ = fs::remove_dir_all(dir);
+        let v1 = random_bytes(2, 512 * 1024);
+        let mut v2 = v1.clone();
+        v2.splice(200_000..200_000, random_bytes(3, 1000));
+        let (_, chunks_v1) = chunk(&v1);
+        let (hash_v2, chunks_v2) = chunk(&v2);
+
+        let store = ChunkStore::open(format!("{}/chunks", dir)).unwrap();
+        write_file(format!("{}/v1", dir), &v1).unwrap();
+        let added = store.import(format!("{}/v1", dir), &chunks_v1).unwrap();
+        assert!(added > 0);
+        assert!(store.missing(&chunks_v1).is_empty());
+        assert_eq!(store.import(format!("{}/v1", dir), &chunks_v1).unwrap(), 0);
+
+        // insertion only changes the chunks around it
+        let missing = store.missing(&chunks_v2);
+        assert!(!missing.is_empty());
+        assert!(missing.len() * 4 < chunks_v2.list.len());
+
+        let path = format!("{}/v2", dir);
+        let mut file = PodFile {
+            path: "v2".to_string(),
+            hash: hash_v2.clone(),
+            size: v2.len(),
+            modified: Utc::now(),
+            chunks: Some(chunks_v2.clone()),
+        };
+        assert!(matches!(store.assemble(&file, &path), Err(Error::Chunk(_))));
+        for (offset, chunk) in chunks_v2.ranges() {
+            if missing.contains(&chunk) {
+                let bytes = &v2[offset..offset + chunk.size];
+                assert!(store.put(chunk, &bytes[1..]).is_err());
+                assert!(store.put(chunk, bytes).unwrap());
+            }
+        }
+        store.assemble(&file, &path).unwrap();
+        assert!(hash_v2.verify(&fs::read(&path).unwrap()));
+
+        // chunks all stored, but not the listed file, the good copy stays
+        file.hash = FileHash::digest(HashAlgorithm::Blake3, &v1);
+        assert!(matches!(store.assemble(&file, &path), Err(Error::Chunk(_))));
+        assert_eq!(fs::read(&path).unwrap(), v2);
+        assert!(fs::metadata(format!("{}/.v2.part", dir)).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(store.assemble(&file, &path), Err(Error::Chunk(_))));
+        assert!(fs::metadata(&path).is_err());
+        file.hash = hash_v2;
+        file.size += 1;
+        assert!(matches!(store.assemble(&file, &path), Err(Error::Chunk(_))));
+    }
+}
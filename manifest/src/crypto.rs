@@ -0,0 +1,117 @@
+use std::str::FromStr;
+
+use bitcoin::{
+    hashes::{sha256, sha512, Hash},
+    secp256k1::{Message, Secp256k1},
+    sign_message::{signed_msg_hash, MessageSignature},
+    PrivateKey,
+};
+
+use crate::{
+    address::{sign_schnorr, Address, AddressKind},
+    error::Error,
+};
+
+/// Produces signatures for an address, implemented by keys held
+/// in memory as well as external signers
+pub trait Signer {
+    /// Address signatures are verified against
+    fn address(&self) -> String;
+
+    /// Base64 encoded signature of message, of the scheme of address
+    fn sign(&self, message: &str) -> Result<String, Error>;
+}
+
+impl Signer for PrivateKey {
+    fn address(&self) -> String {
+        address_from_key(self)
+    }
+
+    fn sign(&self, message: &str) -> Result<String, Error> {
+        Ok(sign_message(self, message))
+    }
+}
+
+/// Private key signing for its Taproot address with Schnorr signatures,
+/// while the bare key keeps signing for its legacy address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaprootKey(pub PrivateKey);
+
+impl Signer for TaprootKey {
+    fn address(&self) -> String {
+        Address::from_key(&self.0, AddressKind::P2tr).to_string()
+    }
+
+    fn sign(&self, message: &str) -> Result<String, Error> {
+        Ok(sign_schnorr(&self.0, message))
+    }
+}
+
+/// Hex encoded sha256 of bytes
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    sha256::Hash::hash(bytes).to_string()
+}
+
+/// Hex encoded sha512 truncated to 256 bits, the file hash used by ZeroNet
+pub fn sha512t_hex(bytes: &[u8]) -> String {
+    sha512::Hash::hash(bytes).to_string()[..64].to_string()
+}
+
+/// Sign message with bitcoin message signature, base64 encoded
+pub fn sign_message(key: &PrivateKey, message: &str) -> String {
+    let secp = Secp256k1::signing_only();
+    let msg_hash = signed_msg_hash(message);
+    let msg = Message::from_digest(msg_hash.to_byte_array());
+    let signature = secp.sign_ecdsa_recoverable(&msg, &key.inner);
+    MessageSignature::new(signature, key.compressed).to_base64()
+}
+
+/// Verify base64 signature of message against address, with
+/// the signature scheme of the address kind
+pub fn verify_message(address: &str, message: &str, sign: &str) -> bool {
+    match Address::from_str(address) {
+        Ok(address) => address.verify(message, sign),
+        Err(_) => false,
+    }
+}
+
+/// Legacy P2PKH address of private key
+pub fn address_from_key(key: &PrivateKey) -> String {
+    Address::from_key(key, AddressKind::P2pkh).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{address_from_key, sign_message, verify_message, Signer, TaprootKey};
+    use crate::test_utils::new_key;
+
+    #[test]
+    fn test_sign_verify_message() {
+        let key = new_key();
+        let address = address_from_key(&key);
+        let sign = sign_message(&key, "Hello World!");
+        assert!(verify_message(&address, "Hello World!", &sign));
+        assert!(!verify_message(&address, "Hello World?", &sign));
+        assert!(!verify_message(&address, "Hello World!", "not a signature"));
+    }
+
+    #[test]
+    fn test_taproot_signer() {
+        let key = TaprootKey(new_key());
+        let sign = key.sign("Hello World!").unwrap();
+        assert!(verify_message(&key.address(), "Hello World!", &sign));
+        assert!(!verify_message(
+            &address_from_key(&key.0),
+            "Hello World!",
+            &sign
+        ));
+    }
+
+    #[test]
+    fn test_verify_zeronet_signers_sign() {
+        // signers_sign of tests/data/zeronet/bare/content.json
+        let sign = "GyEzInbeax/08eA58cWKoUEd/AuxcjlMkUq2rjumMRvdN9jdL/GXit4xsg4KHDpZwsx5dRBjim8DFpngdM2rcoQ=";
+        let address = "1BwdniBeKJ7zaPP41K8FUuYAb4AU1JqxeZ";
+        assert!(verify_message(address, &format!("1:{}", address), sign));
+    }
+}
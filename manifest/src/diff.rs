@@ -0,0 +1,555 @@
+//! Structured comparison of two versions of a pod, so reviewers can see what
+//! they are asked to co-sign. Files are matched by path, and a file removed
+//! at one path and added with the same hash at another is reported as moved.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    error::Error,
+    file::{PodFile, PodFileRoot},
+    manifest::{PodManifest, PodManifestRotation, SignatureScheme},
+};
+
+/// Changes of listed files, each sorted by path
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// Files only listed by new version
+    pub added: Vec<PodFile>,
+    /// Files only listed by old version
+    pub removed: Vec<PodFile>,
+    /// Old and new listing of files whose hash or size changed
+    pub modified: Vec<(PodFile, PodFile)>,
+    /// Old and new listing of files moved to another path unchanged
+    pub moved: Vec<(PodFile, PodFile)>,
+    /// New listing of files moved from `files` to `files_optional`
+    pub made_optional: Vec<PodFile>,
+    /// New listing of files moved from `files_optional` to `files`
+    pub made_required: Vec<PodFile>,
+}
+
+impl FileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.moved.is_empty()
+            && self.made_optional.is_empty()
+            && self.made_required.is_empty()
+    }
+
+    /// Record file listed by both versions if it changed list
+    fn listed_in(&mut self, file: &PodFile, old_optional: bool, new_optional: bool) {
+        match (old_optional, new_optional) {
+            (false, true) => self.made_optional.push(file.clone()),
+            (true, false) => self.made_required.push(file.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl PodFileRoot {
+    /// Changes from this file list to new one, optional files included,
+    /// along with files moved between required and optional.
+    /// Modified times alone are not a change.
+    pub fn diff(&self, new: &PodFileRoot) -> FileDiff {
+        let listed = |root: &PodFileRoot| -> BTreeMap<String, (PodFile, bool)> {
+            let files = root.files.iter().map(|file| (file, false));
+            let optional = root.files_optional.iter().map(|file| (file, true));
+            files
+                .chain(optional)
+                .map(|(file, optional)| (file.path.clone(), (file.clone(), optional)))
+                .collect()
+        };
+        let (old, mut new) = (listed(self), listed(new));
+
+        let mut diff = FileDiff::default();
+        let mut removed = vec![];
+        for (path, (old_file, old_optional)) in old {
+            match new.remove(&path) {
+                Some((new_file, new_optional)) => {
+                    diff.listed_in(&new_file, old_optional, new_optional);
+                    if new_file.hash != old_file.hash || new_file.size != old_file.size {
+                        diff.modified.push((old_file, new_file));
+                    }
+                }
+                None => removed.push((old_file, old_optional)),
+            }
+        }
+        let mut added: Vec<(PodFile, bool)> = new.into_values().collect();
+        for (old_file, old_optional) in removed {
+            let moved_to = added.iter().position(|(new_file, _)| {
+                !old_file.hash.is_empty()
+                    && new_file.hash == old_file.hash
+                    && new_file.size == old_file.size
+            });
+            match moved_to {
+                Some(index) => {
+                    let (new_file, new_optional) = added.remove(index);
+                    diff.listed_in(&new_file, old_optional, new_optional);
+                    diff.moved.push((old_file, new_file));
+                }
+                None => diff.removed.push(old_file),
+            }
+        }
+        diff.added = added.into_iter().map(|(file, _)| file).collect();
+        diff.made_optional.sort_by(|a, b| a.path.cmp(&b.path));
+        diff.made_required.sort_by(|a, b| a.path.cmp(&b.path));
+        diff
+    }
+}
+
+impl fmt::Display for FileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.added {
+            writeln!(f, "  + {} ({}B)", file.path, file.size)?;
+        }
+        for file in &self.removed {
+            writeln!(f, "  - {}", file.path)?;
+        }
+        for (old, new) in &self.modified {
+            writeln!(f, "  ~ {} ({}B -> {}B)", new.path, old.size, new.size)?;
+        }
+        for (old, new) in &self.moved {
+            writeln!(f, "  > {} -> {}", old.path, new.path)?;
+        }
+        for file in &self.made_optional {
+            writeln!(f, "  ~ {} required -> optional", file.path)?;
+        }
+        for file in &self.made_required {
+            writeln!(f, "  ~ {} optional -> required", file.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Change of a single field, values as JSON text, None where absent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    /// Dotted path of field
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (None, Some(new)) => write!(f, "+ {} {}", self.field, new),
+            (Some(old), None) => write!(f, "- {} {}", self.field, old),
+            (old, new) => write!(
+                f,
+                "~ {} {} -> {}",
+                self.field,
+                old.as_deref().unwrap_or_default(),
+                new.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Changes from one manifest to another, each None or empty if unchanged
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// Old and new primary signer
+    pub primary: Option<(String, String)>,
+    /// Old and new signature scheme
+    pub scheme: Option<(SignatureScheme, SignatureScheme)>,
+    /// Signers only listed by new manifest
+    pub signers_added: Vec<String>,
+    /// Signers only listed by old manifest
+    pub signers_removed: Vec<String>,
+    /// Old and new number of signatures required
+    pub signs_required: Option<(usize, usize)>,
+    /// Rotations only recorded by new manifest
+    pub rotations: Vec<PodManifestRotation>,
+    /// Signers and `signs_required` of `extensions.internal`, by path
+    pub internal_signers: Vec<FieldChange>,
+    /// Fields of `meta.pod` other than settings
+    pub pod: Vec<FieldChange>,
+    /// Entries of `meta.pod.settings`
+    pub settings: Vec<FieldChange>,
+    /// Fields of `extensions`
+    pub extensions: Vec<FieldChange>,
+    /// Path, hash and size of files.toml referenced by `files`
+    pub files_manifest: Vec<FieldChange>,
+    /// Listed files, as far as file roots of both are loaded
+    pub files: FileDiff,
+}
+
+impl ManifestDiff {
+    /// Whether who may sign or how many signatures are needed changed,
+    /// such changes need to be approved before co-signing
+    pub fn changes_signers(&self) -> bool {
+        self.primary.is_some()
+            || self.scheme.is_some()
+            || !self.signers_added.is_empty()
+            || !self.signers_removed.is_empty()
+            || self.signs_required.is_some()
+            || !self.rotations.is_empty()
+            || !self.internal_signers.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.changes_signers()
+            && self.pod.is_empty()
+            && self.settings.is_empty()
+            && self.extensions.is_empty()
+            && self.files_manifest.is_empty()
+            && self.files.is_empty()
+    }
+}
+
+impl PodManifest {
+    /// Changes from this manifest to new one
+    pub fn diff(&self, new: &PodManifest) -> Result<ManifestDiff, Error> {
+        let (old_sig, new_sig) = (&self.signature, &new.signature);
+
+        let pod = |manifest: &PodManifest| -> Result<(Value, Value), Error> {
+            let pod = manifest.meta.as_ref().and_then(|meta| meta.pod.as_ref());
+            let mut pod = serde_json::to_value(pod).map_err(Error::json)?;
+            let settings = match &mut pod {
+                Value::Object(fields) => fields.remove("settings").unwrap_or_default(),
+                _ => Value::Null,
+            };
+            Ok((pod, settings))
+        };
+        let (old_pod, old_settings) = pod(self)?;
+        let (new_pod, new_settings) = pod(new)?;
+        let old_extensions = serde_json::to_value(&self.extensions).map_err(Error::json)?;
+        let new_extensions = serde_json::to_value(&new.extensions).map_err(Error::json)?;
+
+        let internal_signers = |manifest: &PodManifest| -> Value {
+            let internal = manifest
+                .extensions
+                .iter()
+                .flat_map(|extensions| extensions.internal.iter().flatten());
+            internal
+                .map(|internal| {
+                    let signers = json!({
+                        "signers": internal.signers,
+                        "signs_required": internal.signs_required,
+                    });
+                    (internal.path.clone(), signers)
+                })
+                .collect()
+        };
+        let files_manifest = |manifest: &PodManifest| -> Value {
+            manifest.files.as_ref().map_or(Value::Null, |files| {
+                json!({
+                    "manifest": files.manifest,
+                    "hash": files.hash,
+                    "size": files.size,
+                })
+            })
+        };
+
+        let empty = PodFileRoot::default();
+        let old_files = self.files.as_ref().map_or(&empty, |files| &files.file_root);
+        let new_files = new.files.as_ref().map_or(&empty, |files| &files.file_root);
+
+        Ok(ManifestDiff {
+            primary: changed(&old_sig.primary, &new_sig.primary)
+                .map(|(old, new)| (old.clone(), new.clone())),
+            scheme: changed(old_sig.scheme, new_sig.scheme),
+            signers_added: not_in(&new_sig.signers, &old_sig.signers),
+            signers_removed: not_in(&old_sig.signers, &new_sig.signers),
+            signs_required: changed(old_sig.signs_required, new_sig.signs_required),
+            rotations: not_in(&new_sig.rotations, &old_sig.rotations),
+            internal_signers: field_changes(&internal_signers(self), &internal_signers(new)),
+            pod: field_changes(&old_pod, &new_pod),
+            settings: field_changes(&old_settings, &new_settings),
+            extensions: field_changes(&old_extensions, &new_extensions),
+            files_manifest: field_changes(&files_manifest(self), &files_manifest(new)),
+            files: old_files.diff(new_files),
+        })
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes_signers() {
+            writeln!(f, "signature: CHANGED, approve before co-signing")?;
+            if let Some((old, new)) = &self.primary {
+                writeln!(f, "  ~ primary {} -> {}", old, new)?;
+            }
+            if let Some((old, new)) = &self.scheme {
+                writeln!(f, "  ~ scheme {:?} -> {:?}", old, new)?;
+            }
+            for signer in &self.signers_added {
+                writeln!(f, "  + signer {}", signer)?;
+            }
+            for signer in &self.signers_removed {
+                writeln!(f, "  - signer {}", signer)?;
+            }
+            if let Some((old, new)) = &self.signs_required {
+                writeln!(f, "  ~ signs_required {} -> {}", old, new)?;
+            }
+            for rotation in &self.rotations {
+                writeln!(
+                    f,
+                    "  + rotation {}:{} > {}:{}",
+                    rotation.signs_required,
+                    rotation.signers.join(","),
+                    rotation.new_signs_required,
+                    rotation.new_signers.join(",")
+                )?;
+            }
+            for change in &self.internal_signers {
+                writeln!(f, "  {} (internal)", change)?;
+            }
+        }
+        let sections = [
+            ("pod", &self.pod),
+            ("settings", &self.settings),
+            ("extensions", &self.extensions),
+            ("files manifest", &self.files_manifest),
+        ];
+        for (name, changes) in sections {
+            if !changes.is_empty() {
+                writeln!(f, "{}:", name)?;
+                for change in changes {
+                    writeln!(f, "  {}", change)?;
+                }
+            }
+        }
+        if !self.files.is_empty() {
+            writeln!(f, "files:")?;
+            write!(f, "{}", self.files)?;
+        }
+        Ok(())
+    }
+}
+
+/// Old and new value if they differ
+fn changed<T: PartialEq>(old: T, new: T) -> Option<(T, T)> {
+    (old != new).then_some((old, new))
+}
+
+/// Items of list not in other, in order
+fn not_in<T: Clone + PartialEq>(list: &[T], other: &[T]) -> Vec<T> {
+    list.iter()
+        .filter(|item| !other.contains(item))
+        .cloned()
+        .collect()
+}
+
+/// Changed leaves of JSON values, objects are compared field by field
+fn field_changes(old: &Value, new: &Value) -> Vec<FieldChange> {
+    fn walk(field: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+        match (old, new) {
+            (Value::Object(old), Value::Object(new)) => {
+                let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+                for key in keys {
+                    let nested = match field {
+                        "" => key.clone(),
+                        _ => format!("{}.{}", field, key),
+                    };
+                    let value = |object: &serde_json::Map<String, Value>| {
+                        object.get(key).cloned().unwrap_or_default()
+                    };
+                    walk(&nested, &value(old), &value(new), changes);
+                }
+            }
+            _ if old != new => {
+                let text = |value: &Value| (!value.is_null()).then(|| value.to_string());
+                changes.push(FieldChange {
+                    field: field.to_string(),
+                    old: text(old),
+                    new: text(new),
+                });
+            }
+            _ => {}
+        }
+    }
+    let mut changes = vec![];
+    walk("", old, new, &mut changes);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::{
+        file::{PodFile, PodFileRoot},
+        hash::{FileHash, HashAlgorithm},
+        manifest::{
+            PodManifest, PodManifestExtension, PodManifestExtensionInternal, PodManifestFiles,
+            PodManifestMeta, PodManifestMetaPod, PodManifestSignature,
+        },
+    };
+
+    fn file(path: &str, content: &str) -> PodFile {
+        PodFile {
+            path: path.to_string(),
+            hash: FileHash::digest(HashAlgorithm::Blake3, content.as_bytes()),
+            size: content.len(),
+            modified: Utc::now(),
+            chunks: None,
+        }
+    }
+
+    fn file_root(files: Vec<PodFile>) -> PodFileRoot {
+        PodFileRoot {
+            files,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_file_root_diff() {
+        let old = file_root(vec![
+            file("index.html", "v1"),
+            file("old.css", "css"),
+            file("gone.js", "js"),
+            file("same.txt", "same"),
+        ]);
+        let mut new = file_root(vec![
+            file("index.html", "v2"),
+            file("style/new.css", "css"),
+            file("added.txt", "added"),
+            file("same.txt", "same"),
+        ]);
+        // touched, but unchanged
+        new.files[3].modified = Utc::now();
+
+        let diff = PodFileRoot::diff(&old, &new);
+        let paths = |files: &[PodFile]| files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&diff.added), vec!["added.txt"]);
+        assert_eq!(paths(&diff.removed), vec!["gone.js"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].1.path, "index.html");
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(
+            (diff.moved[0].0.path.as_str(), diff.moved[0].1.path.as_str()),
+            ("old.css", "style/new.css")
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_file_root_diff_optional() {
+        let old = PodFileRoot {
+            files: vec![file("big.bin", "big"), file("index.html", "v1")],
+            files_optional: vec![file("video.mp4", "video"), file("old.png", "png")],
+            ..Default::default()
+        };
+        let new = PodFileRoot {
+            files: vec![file("video.mp4", "video"), file("index.html", "v1")],
+            files_optional: vec![file("big.bin", "big"), file("img/new.png", "png")],
+            ..Default::default()
+        };
+
+        let diff = old.diff(&new);
+        assert!(!diff.is_empty());
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.modified.is_empty());
+        assert_eq!(diff.made_optional.len(), 1);
+        assert_eq!(diff.made_optional[0].path, "big.bin");
+        assert_eq!(diff.made_required.len(), 1);
+        assert_eq!(diff.made_required[0].path, "video.mp4");
+        // moved within optional files
+        assert_eq!(diff.moved.len(), 1);
+
+        let rendered = diff.to_string();
+        assert!(rendered.contains("  ~ big.bin required -> optional\n"));
+        assert!(rendered.contains("  ~ video.mp4 optional -> required\n"));
+    }
+
+    #[test]
+    fn test_manifest_diff() {
+        let manifest = |signers: Vec<&str>, signs_required, title: &str, theme: &str| PodManifest {
+            files: Some(PodManifestFiles {
+                file_root: file_root(vec![file("index.html", title)]),
+                ..Default::default()
+            }),
+            signature: PodManifestSignature {
+                primary: "1Primary".to_string(),
+                signs_required,
+                signers: signers.into_iter().map(String::from).collect(),
+                ..Default::default()
+            },
+            extensions: Some(PodManifestExtension {
+                internal: Some(vec![PodManifestExtensionInternal {
+                    path: "data/users".to_string(),
+                    signers: vec![],
+                    signs_required,
+                }]),
+                external: None,
+            }),
+            meta: Some(PodManifestMeta {
+                ignore: None,
+                prev: None,
+                client: None,
+                pod: Some(PodManifestMetaPod {
+                    title: title.to_string(),
+                    settings: Some(BTreeMap::from([("theme".to_string(), json!(theme))])),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        };
+        let old = manifest(vec!["1Primary", "1Alice"], 1, "Blog", "light");
+        assert!(old.diff(&old).unwrap().is_empty());
+
+        let new = manifest(vec!["1Primary", "1Bob"], 2, "My Blog", "dark");
+        let diff = old.diff(&new).unwrap();
+        assert!(diff.changes_signers());
+        assert_eq!(diff.signers_added, vec!["1Bob"]);
+        assert_eq!(diff.signers_removed, vec!["1Alice"]);
+        assert_eq!(diff.signs_required, Some((1, 2)));
+        assert_eq!(diff.pod.len(), 1);
+        assert_eq!(diff.pod[0].field, "title");
+        assert_eq!(diff.settings[0].field, "theme");
+        assert_eq!(diff.extensions[0].field, "internal");
+        assert_eq!(diff.files.modified.len(), 1);
+
+        let rendered = diff.to_string();
+        assert!(rendered.starts_with("signature: CHANGED"));
+        assert!(rendered.contains("  + signer 1Bob\n"));
+        assert!(rendered.contains("  - signer 1Alice\n"));
+        assert!(rendered.contains("  ~ signs_required 1 -> 2\n"));
+        assert!(rendered.contains("  ~ title \"Blog\" -> \"My Blog\"\n"));
+        assert!(rendered.contains("  ~ theme \"light\" -> \"dark\"\n"));
+        assert!(rendered.contains("  ~ index.html (4B -> 7B)\n"));
+
+        // content only changes need no signer approval
+        let new = manifest(vec!["1Primary", "1Alice"], 1, "My Blog", "light");
+        assert!(!old.diff(&new).unwrap().changes_signers());
+
+        // signers of user content
+        let mut new = manifest(vec!["1Primary", "1Alice"], 1, "Blog", "light");
+        let extensions = new.extensions.as_mut().unwrap();
+        extensions.internal.as_mut().unwrap()[0].signers = vec!["1Mallory".to_string()];
+        let diff = old.diff(&new).unwrap();
+        assert!(diff.changes_signers());
+        assert_eq!(diff.internal_signers.len(), 1);
+        assert_eq!(diff.internal_signers[0].field, "data/users.signers");
+        assert!(diff
+            .to_string()
+            .contains("  ~ data/users.signers [] -> [\"1Mallory\"] (internal)\n"));
+
+        // another files.toml, whose file list is not loaded
+        let mut new = manifest(vec!["1Primary", "1Alice"], 1, "Blog", "light");
+        let files = new.files.as_mut().unwrap();
+        files.hash = FileHash::digest(HashAlgorithm::Blake3, b"other files.toml");
+        files.size = 16;
+        let diff = old.diff(&new).unwrap();
+        assert!(!diff.is_empty());
+        assert!(!diff.changes_signers());
+        let fields: Vec<&str> = diff
+            .files_manifest
+            .iter()
+            .map(|change| change.field.as_str())
+            .collect();
+        assert_eq!(fields, vec!["hash", "size"]);
+        assert!(diff.files.is_empty());
+    }
+}
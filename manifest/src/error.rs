@@ -0,0 +1,290 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Errors raised while loading, saving or converting manifests
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io { path: PathBuf, source: io::Error },
+    /// TOML document could not be parsed
+    Toml {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// Value could not be serialised as TOML
+    TomlSerialize(toml::ser::Error),
+    /// JSON document could not be parsed
+    Json {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A field required for the conversion is absent
+    MissingField {
+        path: Option<PathBuf>,
+        field: String,
+    },
+    /// ZeroNet content could not be converted
+    ZeroNet {
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    /// Ignore or optional pattern is not a valid regex
+    InvalidPattern { pattern: String, message: String },
+    /// Hash is neither a multibase CID nor a legacy hex hash
+    InvalidHash { hash: String, message: String },
+    /// Listed path is absolute or leaves the directory it is relative to
+    InvalidPath { path: String },
+    /// Address is malformed, fails its checksum or is of an unsupported type
+    InvalidAddress { address: String, message: String },
+    /// Key could not be derived from seed
+    Key(String),
+    /// Versioned object store of pod is missing an object or refused a commit
+    Store(String),
+    /// Update bundle is malformed or does not apply to pod
+    Bundle(String),
+    /// Chunk does not match its listing or chunking options are out of range
+    Chunk(String),
+    /// Signer is not allowed to sign
+    Unauthorized { address: String, reason: String },
+    /// Signer failed to produce a signature
+    Sign(String),
+    /// FROST threshold signing protocol failed
+    Frost(frost_ristretto255::Error),
+}
+
+impl Error {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    /// Build a TOML parse error, resolving the byte span to line and column
+    pub fn toml(content: &str, error: toml::de::Error) -> Self {
+        let offset = error.span().map(|span| span.start).unwrap_or_default();
+        let (line, column) = line_column(content, offset);
+        Error::Toml {
+            path: None,
+            line,
+            column,
+            message: error.message().to_string(),
+        }
+    }
+
+    pub fn json(error: serde_json::Error) -> Self {
+        Error::Json {
+            path: None,
+            line: error.line(),
+            column: error.column(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn missing_field(field: impl Into<String>) -> Self {
+        Error::MissingField {
+            path: None,
+            field: field.into(),
+        }
+    }
+
+    pub fn zeronet(reason: impl Into<String>) -> Self {
+        Error::ZeroNet {
+            path: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unauthorized(address: impl Into<String>, reason: impl Into<String>) -> Self {
+        Error::Unauthorized {
+            address: address.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Attach the file this error originated from, if not already known
+    pub fn with_path(mut self, file: impl AsRef<Path>) -> Self {
+        match &mut self {
+            Error::Toml { path, .. }
+            | Error::Json { path, .. }
+            | Error::MissingField { path, .. }
+            | Error::ZeroNet { path, .. } => {
+                if path.is_none() {
+                    *path = Some(file.as_ref().to_path_buf());
+                }
+            }
+            Error::Io { .. }
+            | Error::TomlSerialize(_)
+            | Error::InvalidPattern { .. }
+            | Error::InvalidHash { .. }
+            | Error::InvalidPath { .. }
+            | Error::InvalidAddress { .. }
+            | Error::Key(_)
+            | Error::Store(_)
+            | Error::Bundle(_)
+            | Error::Chunk(_)
+            | Error::Unauthorized { .. }
+            | Error::Sign(_)
+            | Error::Frost(_) => {}
+        }
+        self
+    }
+
+    /// File this error originated from, if known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. } => Some(path),
+            Error::Toml { path, .. }
+            | Error::Json { path, .. }
+            | Error::MissingField { path, .. }
+            | Error::ZeroNet { path, .. } => path.as_deref(),
+            Error::TomlSerialize(_)
+            | Error::InvalidPattern { .. }
+            | Error::InvalidHash { .. }
+            | Error::InvalidPath { .. }
+            | Error::InvalidAddress { .. }
+            | Error::Key(_)
+            | Error::Store(_)
+            | Error::Bundle(_)
+            | Error::Chunk(_)
+            | Error::Unauthorized { .. }
+            | Error::Sign(_)
+            | Error::Frost(_) => None,
+        }
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(content.len());
+    let before = &content.as_bytes()[..offset];
+    let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map(|i| i + 1)
+        .unwrap_or_default();
+    (line, offset - line_start + 1)
+}
+
+fn fmt_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| format!("{}: ", path.display()))
+        .unwrap_or_default()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Toml {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{}invalid TOML at line {}, column {}: {}",
+                fmt_path(path),
+                line,
+                column,
+                message
+            ),
+            Error::TomlSerialize(err) => write!(f, "failed to serialise TOML: {}", err),
+            Error::Json {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{}invalid JSON at line {}, column {}: {}",
+                fmt_path(path),
+                line,
+                column,
+                message
+            ),
+            Error::MissingField { path, field } => {
+                write!(f, "{}missing required field `{}`", fmt_path(path), field)
+            }
+            Error::ZeroNet { path, reason } => {
+                write!(f, "{}ZeroNet conversion failed: {}", fmt_path(path), reason)
+            }
+            Error::InvalidPattern { pattern, message } => {
+                write!(f, "invalid pattern `{}`: {}", pattern, message)
+            }
+            Error::InvalidHash { hash, message } => {
+                write!(f, "invalid hash `{}`: {}", hash, message)
+            }
+            Error::InvalidPath { path } => {
+                write!(f, "invalid path `{}`: must stay inside pod directory", path)
+            }
+            Error::InvalidAddress { address, message } => {
+                write!(f, "invalid address `{}`: {}", address, message)
+            }
+            Error::Key(reason) => write!(f, "key management failed: {}", reason),
+            Error::Store(reason) => write!(f, "object store failed: {}", reason),
+            Error::Bundle(reason) => write!(f, "update bundle rejected: {}", reason),
+            Error::Chunk(reason) => write!(f, "chunk rejected: {}", reason),
+            Error::Unauthorized { address, reason } => {
+                write!(f, "{} is not allowed to sign: {}", address, reason)
+            }
+            Error::Sign(reason) => write!(f, "signing failed: {}", reason),
+            Error::Frost(err) => write!(f, "threshold signing failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::TomlSerialize(err) => Some(err),
+            Error::Frost(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Self {
+        Error::TomlSerialize(err)
+    }
+}
+
+impl From<frost_ristretto255::Error> for Error {
+    fn from(err: frost_ristretto255::Error) -> Self {
+        Error::Frost(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_column, Error};
+
+    #[test]
+    fn test_line_column() {
+        let content = "a = 1\nb = \nc = 3";
+        assert_eq!(line_column(content, 0), (1, 1));
+        assert_eq!(line_column(content, 10), (2, 5));
+        assert_eq!(line_column(content, 11), (3, 1));
+    }
+
+    #[test]
+    fn test_with_path_keeps_first_path() {
+        let err = Error::missing_field("title")
+            .with_path("a/content.json")
+            .with_path("b/content.json");
+        assert_eq!(err.path().unwrap().to_str().unwrap(), "a/content.json");
+        assert_eq!(
+            err.to_string(),
+            "a/content.json: missing required field `title`"
+        );
+    }
+}
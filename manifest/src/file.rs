@@ -1,43 +1,337 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::ErrorKind,
+    path::{Component, Path},
+};
+
 use chrono::{DateTime, Utc};
+use fancy_regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    chunk::{ChunkOptions, PodChunks},
+    crypto::{verify_message, Signer},
+    error::Error,
+    hash::{FileHash, HashAlgorithm},
+};
+
+/// Files managed by manifests themselves, never listed in files.toml
+pub(crate) const MANIFEST_FILES: [&str; 3] = ["content.json", "manifest.toml", "files.toml"];
+
+/// Whether relative path names a manifest file, at pod root or in a
+/// nested pod like a user directory
+pub(crate) fn is_manifest_file(relative: &str) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    MANIFEST_FILES.contains(&name)
+}
+
+/// Directory of local pod state like the object store, never listed in files.toml
+pub(crate) const LOCAL_DIR: &str = ".decentnet";
+
 #[derive(Default, Debug, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
 pub struct PodFileRoot {
     /// Root Hash of all files
-    pub hash: String,
+    pub hash: FileHash,
     /// Sign of this file content
     pub sign: String,
     /// optional file pattern
     pub optional: Option<String>,
     /// Files in this pod
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub files: Vec<PodFile>,
     /// Optional files in this pod
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub files_optional: Vec<PodFile>,
 }
 
 impl PodFileRoot {
     /// Parse PodFileRoot from string
-    pub fn from_string(content: &str) -> Option<Self> {
-        if let Ok(file) = toml::from_str::<PodFileRoot>(content) {
-            Some(file)
+    pub fn from_string(content: &str) -> Result<Self, Error> {
+        toml::from_str::<PodFileRoot>(content).map_err(|err| Error::toml(content, err))
+    }
+
+    /// Build PodFileRoot by walking pod directory and hashing every file
+    pub fn from_dir(path: impl AsRef<Path>, options: &FromDirOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let ignore = pattern(options.ignore.as_deref())?;
+        let optional = pattern(options.optional.as_deref())?;
+
+        let mut paths = vec![];
+        walk_dir(path, "", &mut paths)?;
+        paths.sort();
+
+        let mut root = PodFileRoot {
+            optional: options.optional.clone(),
+            ..Default::default()
+        };
+        for relative in paths {
+            if is_manifest_file(&relative) || is_match(&ignore, &relative)? {
+                continue;
+            }
+            if !is_match(&optional, &relative)? {
+                root.files
+                    .push(PodFile::from_path(path, relative, options.algorithm)?);
+                continue;
+            }
+            let file = match &options.chunk {
+                Some(chunk) if chunk.should_chunk(&path.join(&relative))? => {
+                    PodFile::from_path_chunked(path, relative, options.algorithm, chunk)?
+                }
+                _ => PodFile::from_path(path, relative, options.algorithm)?,
+            };
+            root.files_optional.push(file);
+        }
+        root.hash = root.root_hash(options.algorithm);
+        Ok(root)
+    }
+
+    /// Hash over sorted file list, independent of modified times.
+    /// Chunk roots of chunked files are covered as well.
+    pub fn root_hash(&self, algorithm: HashAlgorithm) -> FileHash {
+        let mut files: Vec<&PodFile> = self.files.iter().collect();
+        let mut files_optional: Vec<&PodFile> = self.files_optional.iter().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files_optional.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut list = String::from("files\n");
+        for file in files {
+            list.push_str(&file.list_line());
+        }
+        list.push_str("files_optional\n");
+        for file in files_optional {
+            list.push_str(&file.list_line());
+        }
+        FileHash::digest(algorithm, list.as_bytes())
+    }
+
+    /// Take modified times from files in pod directory, files absent on disk keep
+    /// theirs. Paths leaving the directory are refused.
+    pub fn modified_from_dir(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        self.check_paths()?;
+        for file in self.files.iter_mut().chain(self.files_optional.iter_mut()) {
+            let file_path = path.join(&file.path);
+            let modified = match fs::metadata(&file_path) {
+                Ok(metadata) => metadata.modified(),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => Err(err),
+            };
+            file.modified = modified.map_err(|err| Error::io(&file_path, err))?.into();
+        }
+        Ok(())
+    }
+
+    /// Carry forward modified times of previous file list where hash is unchanged
+    pub fn carry_modified(&mut self, previous: &PodFileRoot) {
+        let previous: BTreeMap<&str, &PodFile> = previous
+            .files
+            .iter()
+            .chain(previous.files_optional.iter())
+            .map(|file| (file.path.as_str(), file))
+            .collect();
+        for file in self.files.iter_mut().chain(self.files_optional.iter_mut()) {
+            if let Some(prev) = previous.get(file.path.as_str()) {
+                if prev.hash == file.hash && !file.hash.is_empty() {
+                    file.modified = prev.modified;
+                }
+            }
+        }
+    }
+
+    /// Sign root hash, computing it first if absent
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), Error> {
+        if self.hash.is_empty() {
+            self.hash = self.root_hash(HashAlgorithm::default());
+        }
+        self.sign = signer.sign(&self.hash.to_string())?;
+        Ok(())
+    }
+
+    /// Check every listed path stays inside pod directory
+    pub(crate) fn check_paths(&self) -> Result<(), Error> {
+        for file in self.files.iter().chain(self.files_optional.iter()) {
+            check_path(&file.path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether root hash matches file list and is signed by address,
+    /// along with chunk lists matching their signed chunk roots and
+    /// adding up to the size of their file
+    pub fn verify_sign(&self, address: &str) -> bool {
+        let chunks_intact = self
+            .files
+            .iter()
+            .chain(self.files_optional.iter())
+            .filter_map(|file| Some((file, file.chunks.as_ref()?)))
+            .all(|(file, chunks)| chunks.verify_root() && chunks.size() == file.size);
+        match self.hash.algorithm() {
+            Some(algorithm) => {
+                chunks_intact
+                    && self.hash == self.root_hash(algorithm)
+                    && verify_message(address, &self.hash.to_string(), &self.sign)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Options for `PodFileRoot::from_dir`
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FromDirOptions {
+    /// Files matching this pattern are skipped, same as `meta.ignore`
+    pub ignore: Option<String>,
+    /// Files matching this pattern are listed in `files_optional`
+    pub optional: Option<String>,
+    /// Algorithm used to hash files
+    pub algorithm: HashAlgorithm,
+    /// Chunk optional files larger than the maximum chunk size, None to keep them whole
+    pub chunk: Option<ChunkOptions>,
+}
+
+/// Compile ZeroNet style pattern, matched from start of relative path
+pub(crate) fn pattern(pattern: Option<&str>) -> Result<Option<Regex>, Error> {
+    let Some(pattern) = pattern.filter(|pattern| !pattern.is_empty()) else {
+        return Ok(None);
+    };
+    Regex::new(&format!("^(?:{})", pattern))
+        .map(Some)
+        .map_err(|err| Error::InvalidPattern {
+            pattern: pattern.to_string(),
+            message: err.to_string(),
+        })
+}
+
+pub(crate) fn is_match(regex: &Option<Regex>, path: &str) -> Result<bool, Error> {
+    match regex {
+        Some(regex) => regex.is_match(path).map_err(|err| Error::InvalidPattern {
+            pattern: regex.as_str().to_string(),
+            message: err.to_string(),
+        }),
+        None => Ok(false),
+    }
+}
+
+/// Check path taken from a manifest is relative and made of plain names only,
+/// so joining it onto a directory cannot leave that directory
+pub(crate) fn check_path(path: &str) -> Result<(), Error> {
+    let safe = !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    match safe {
+        true => Ok(()),
+        false => Err(Error::InvalidPath {
+            path: path.to_string(),
+        }),
+    }
+}
+
+/// Collect relative paths of files under dir, separated by `/`
+pub(crate) fn walk_dir(root: &Path, prefix: &str, paths: &mut Vec<String>) -> Result<(), Error> {
+    let dir = root.join(prefix);
+    let entries = fs::read_dir(&dir).map_err(|err| Error::io(&dir, err))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io(&dir, err))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|err| Error::io(entry.path(), err))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if prefix.is_empty() && name == LOCAL_DIR {
+            continue;
+        }
+        let relative = if prefix.is_empty() {
+            name
         } else {
-            None
+            format!("{}/{}", prefix, name)
+        };
+        if file_type.is_dir() {
+            walk_dir(root, &relative, paths)?;
+        } else if file_type.is_file() {
+            paths.push(relative);
         }
     }
+    Ok(())
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
 pub struct PodFile {
     /// Path of this file, relative to manifest.toml
     pub path: String,
     /// Hash of this file
-    pub hash: String,
+    pub hash: FileHash,
     /// Size of this file in bytes
     pub size: usize,
     /// Last modified time of this file
     pub modified: DateTime<Utc>,
+    /// Content-defined chunks of this file, listed for large optional files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<PodChunks>,
+}
+
+impl PodFile {
+    /// Hash file at relative path of pod directory
+    pub fn from_path(
+        root: impl AsRef<Path>,
+        relative: impl Into<String>,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, Error> {
+        let relative = relative.into();
+        let path = root.as_ref().join(&relative);
+        let file = File::open(&path).map_err(|err| Error::io(&path, err))?;
+        let modified = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| Error::io(&path, err))?;
+        let (hash, size) =
+            FileHash::from_reader(algorithm, file).map_err(|err| Error::io(&path, err))?;
+        Ok(PodFile {
+            path: relative,
+            hash,
+            size: size as usize,
+            modified: modified.into(),
+            chunks: None,
+        })
+    }
+
+    /// Hash file at relative path of pod directory and split it into
+    /// content-defined chunks, reading it once for both
+    pub fn from_path_chunked(
+        root: impl AsRef<Path>,
+        relative: impl Into<String>,
+        algorithm: HashAlgorithm,
+        options: &ChunkOptions,
+    ) -> Result<Self, Error> {
+        options.validate()?;
+        let relative = relative.into();
+        let path = root.as_ref().join(&relative);
+        let file = File::open(&path).map_err(|err| Error::io(&path, err))?;
+        let modified = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| Error::io(&path, err))?;
+        let (hash, size, chunks) = PodChunks::from_reader(algorithm, file, options)
+            .map_err(|err| Error::io(&path, err))?;
+        Ok(PodFile {
+            path: relative,
+            hash,
+            size: size as usize,
+            modified: modified.into(),
+            chunks: Some(chunks),
+        })
+    }
+
+    /// Line of this file in list hashed by `PodFileRoot::root_hash`
+    fn list_line(&self) -> String {
+        match &self.chunks {
+            Some(chunks) => format!(
+                "{}\t{}\t{}\t{}\n",
+                self.path, self.hash, self.size, chunks.root
+            ),
+            None => format!("{}\t{}\t{}\n", self.path, self.hash, self.size),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -47,14 +341,22 @@ mod tests {
         io::{read_to_string, Write},
     };
 
-    use crate::file::PodFileRoot;
+    use crate::{
+        chunk::ChunkOptions,
+        crypto::address_from_key,
+        error::Error,
+        file::{FromDirOptions, PodFileRoot},
+        hash::HashAlgorithm,
+        io::write_file,
+        test_utils::new_key,
+    };
 
     #[test]
     fn test_deserialize() {
         let file = File::open("tests/files.toml").unwrap();
         let content = read_to_string(file).unwrap();
         let pod_file = PodFileRoot::from_string(&content);
-        assert!(pod_file.is_some());
+        assert!(pod_file.is_ok());
     }
 
     #[test]
@@ -67,4 +369,115 @@ mod tests {
         let mut file = File::create("tests/tmp/files.toml").unwrap();
         file.write_all(content.as_bytes()).unwrap();
     }
+
+    #[test]
+    fn test_from_dir_matches_zeronet_hash() {
+        let root = PodFileRoot::from_dir("tests/data/zeronet/empty", &Default::default()).unwrap();
+        assert_eq!(root.files.len(), 1);
+        assert_eq!(root.files[0].path, "index.html");
+        assert_eq!(
+            root.files[0].hash.to_sha512t_hex().unwrap(),
+            "f4877c354fe32ce49ca8926956c664e8a30e54d70a2823f411daf5c75a2088fd"
+        );
+        assert_eq!(root.files[0].size, 41);
+        assert_eq!(root.hash, root.root_hash(HashAlgorithm::Sha512Trunc256));
+    }
+
+    #[test]
+    fn test_from_dir_ignore_optional() {
+        let dir = "tests/tmp/from_dir";
+        for path in [
+            "index.html",
+            "js/all.js",
+            "js/lib.js",
+            "img/photo.jpg",
+            "avatar.jpg",
+            "data/users/content.json",
+            "content.json",
+            "blog/manifest.toml",
+            "blog/files.toml",
+            "blog/post.md",
+        ] {
+            write_file(format!("{}/{}", dir, path), path.as_bytes()).unwrap();
+        }
+        let options = FromDirOptions {
+            ignore: Some("(js|css)/(?!all.(js|css))|data/users/.*".to_string()),
+            optional: Some("(?!avatar).*jpg".to_string()),
+            algorithm: HashAlgorithm::Blake3,
+            chunk: None,
+        };
+        let root = PodFileRoot::from_dir(dir, &options).unwrap();
+        let files: Vec<&str> = root.files.iter().map(|f| f.path.as_str()).collect();
+        let optional: Vec<&str> = root
+            .files_optional
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect();
+        // manifest files of nested pods are skipped like the root ones
+        assert_eq!(
+            files,
+            vec!["avatar.jpg", "blog/post.md", "index.html", "js/all.js"]
+        );
+        assert_eq!(optional, vec!["img/photo.jpg"]);
+        assert_eq!(root.optional, options.optional);
+        assert!(root.files[0].hash.verify(b"avatar.jpg"));
+        assert_eq!(root.hash.algorithm(), Some(HashAlgorithm::Blake3));
+
+        let options = FromDirOptions {
+            ignore: Some("(".to_string()),
+            ..Default::default()
+        };
+        let err = PodFileRoot::from_dir(dir, &options).unwrap_err();
+        assert!(matches!(err, Error::InvalidPattern { .. }));
+    }
+
+    #[test]
+    fn test_from_dir_chunks_large_optional() {
+        let dir = "tests/tmp/from_dir_chunked";
+        let video: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i * 7919).to_le_bytes())
+            .collect();
+        write_file(format!("{}/video.mp4", dir), &video).unwrap();
+        write_file(format!("{}/image.png", dir), b"image").unwrap();
+        write_file(format!("{}/index.md", dir), &video).unwrap();
+        let options = FromDirOptions {
+            optional: Some(".*(mp4|png)".to_string()),
+            algorithm: HashAlgorithm::Blake3,
+            chunk: Some(ChunkOptions {
+                min_size: 16 * 1024,
+                avg_size: 64 * 1024,
+                max_size: 256 * 1024,
+            }),
+            ..Default::default()
+        };
+        let root = PodFileRoot::from_dir(dir, &options).unwrap();
+        // only optional files spanning several chunks are chunked
+        assert!(root.files[0].chunks.is_none());
+        assert!(root.files_optional[0].chunks.is_none());
+        let chunked = &root.files_optional[1];
+        let chunks = chunked.chunks.as_ref().unwrap();
+        assert!(chunks.list.len() > 1);
+        assert_eq!(chunks.size(), chunked.size);
+        assert!(chunked.hash.verify(&video));
+
+        // chunk list survives files.toml and is covered by root hash
+        let content = toml::to_string(&root).unwrap();
+        assert_eq!(PodFileRoot::from_string(&content).unwrap(), root);
+        let mut unchunked = PodFileRoot::from_string(&content).unwrap();
+        unchunked.files_optional[1].chunks = None;
+        assert_ne!(
+            unchunked.root_hash(HashAlgorithm::Blake3),
+            root.root_hash(HashAlgorithm::Blake3)
+        );
+
+        // signed chunk list must add up to its file
+        let key = new_key();
+        let mut signed = PodFileRoot::from_string(&content).unwrap();
+        signed.sign(&key).unwrap();
+        assert!(signed.verify_sign(&address_from_key(&key)));
+        signed.files_optional[1].size += 1;
+        signed.hash = Default::default();
+        signed.sign(&key).unwrap();
+        assert!(!signed.verify_sign(&address_from_key(&key)));
+    }
 }
@@ -0,0 +1,262 @@
+use std::{fmt, io, str::FromStr};
+
+use bitcoin::hashes::{sha512, Hash, HashEngine};
+use cid::{
+    multihash::{Blake3_256, Hasher, Multihash},
+    Cid,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+
+/// Multicodec of raw binary content
+const RAW: u64 = 0x55;
+/// Multihash code of sha2-512, truncated to 32 bytes for legacy hashes
+const SHA2_512: u64 = 0x13;
+/// Multihash code of blake3
+const BLAKE3: u64 = 0x1e;
+
+/// Hash algorithms supported for file hashes
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// sha512 truncated to 256 bits, used by ZeroNet
+    #[default]
+    Sha512Trunc256,
+    /// blake3 with 256 bit output
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Algorithm of multihash, if supported
+    pub fn from_multihash(hash: &Multihash) -> Option<Self> {
+        match (hash.code(), hash.size()) {
+            (SHA2_512, 32) => Some(HashAlgorithm::Sha512Trunc256),
+            (BLAKE3, 32) => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn hasher(&self) -> FileHasher {
+        match self {
+            HashAlgorithm::Sha512Trunc256 => FileHasher::Sha512(sha512::Hash::engine()),
+            HashAlgorithm::Blake3 => FileHasher::Blake3(Box::default()),
+        }
+    }
+}
+
+/// Incremental hasher of algorithm, fed through `io::Write`
+pub(crate) enum FileHasher {
+    Sha512(sha512::HashEngine),
+    Blake3(Box<Blake3_256>),
+}
+
+impl FileHasher {
+    pub(crate) fn finalize(self) -> Multihash {
+        let (code, digest) = match self {
+            FileHasher::Sha512(engine) => {
+                let digest = sha512::Hash::from_engine(engine).to_byte_array();
+                (SHA2_512, digest[..32].to_vec())
+            }
+            FileHasher::Blake3(mut hasher) => (BLAKE3, hasher.finalize().to_vec()),
+        };
+        Multihash::wrap(code, &digest).expect("digest fits in multihash")
+    }
+}
+
+impl io::Write for FileHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileHasher::Sha512(engine) => engine.input(buf),
+            FileHasher::Blake3(hasher) => hasher.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of file content as raw CIDv1, serialised as multibase string.
+/// Empty string stands for unknown hash, and 64 hex chars are read
+/// as legacy ZeroNet sha512 truncated to 256 bits.
+#[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileHash(Option<Cid>);
+
+impl FileHash {
+    /// Hash bytes with algorithm
+    pub fn digest(algorithm: HashAlgorithm, bytes: &[u8]) -> Self {
+        let mut hasher = algorithm.hasher();
+        io::Write::write_all(&mut hasher, bytes).expect("hashing in memory never fails");
+        FileHash::from_multihash(hasher.finalize())
+    }
+
+    /// Hash everything read from reader, along with number of bytes read
+    pub fn from_reader(
+        algorithm: HashAlgorithm,
+        mut reader: impl io::Read,
+    ) -> io::Result<(Self, u64)> {
+        let mut hasher = algorithm.hasher();
+        let size = io::copy(&mut reader, &mut hasher)?;
+        Ok((FileHash::from_multihash(hasher.finalize()), size))
+    }
+
+    pub fn from_multihash(hash: Multihash) -> Self {
+        FileHash(Some(Cid::new_v1(RAW, hash)))
+    }
+
+    /// Parse legacy ZeroNet hex hash
+    pub fn from_sha512t_hex(hex: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidHash {
+            hash: hex.to_string(),
+            message: "expected 64 hex chars of truncated sha512".to_string(),
+        };
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let hash = Multihash::wrap(SHA2_512, &digest).map_err(|_| invalid())?;
+        Ok(FileHash::from_multihash(hash))
+    }
+
+    /// Legacy ZeroNet hex hash, if hashed with sha512 truncated
+    pub fn to_sha512t_hex(&self) -> Option<String> {
+        match self.algorithm()? {
+            HashAlgorithm::Sha512Trunc256 => Some(
+                self.0?
+                    .hash()
+                    .digest()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect(),
+            ),
+            HashAlgorithm::Blake3 => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn cid(&self) -> Option<&Cid> {
+        self.0.as_ref()
+    }
+
+    /// Algorithm embedded in hash, None if empty or unsupported
+    pub fn algorithm(&self) -> Option<HashAlgorithm> {
+        HashAlgorithm::from_multihash(self.0.as_ref()?.hash())
+    }
+
+    /// Check bytes against hash using its embedded algorithm
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        match self.algorithm() {
+            Some(algorithm) => &FileHash::digest(algorithm, bytes) == self,
+            None => false,
+        }
+    }
+
+    /// Check content of reader against hash using its embedded algorithm
+    pub fn verify_reader(&self, reader: impl io::Read) -> io::Result<bool> {
+        match self.algorithm() {
+            Some(algorithm) => Ok(&FileHash::from_reader(algorithm, reader)?.0 == self),
+            None => Ok(false),
+        }
+    }
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(cid) => write!(f, "{}", cid),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for FileHash {
+    type Err = Error;
+
+    fn from_str(hash: &str) -> Result<Self, Error> {
+        if hash.is_empty() {
+            return Ok(FileHash(None));
+        }
+        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return FileHash::from_sha512t_hex(hash);
+        }
+        let cid = Cid::from_str(hash).map_err(|err| Error::InvalidHash {
+            hash: hash.to_string(),
+            message: err.to_string(),
+        })?;
+        Ok(FileHash(Some(cid)))
+    }
+}
+
+impl Serialize for FileHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for FileHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hash = String::deserialize(deserializer)?;
+        FileHash::from_str(&hash).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{FileHash, HashAlgorithm};
+    use crate::crypto::sha512t_hex;
+
+    #[test]
+    fn test_legacy_hash_roundtrip() {
+        let hex = sha512t_hex(b"Hello World!");
+        let hash = FileHash::from_str(&hex).unwrap();
+        assert_eq!(hash.algorithm(), Some(HashAlgorithm::Sha512Trunc256));
+        assert_eq!(hash.to_sha512t_hex().unwrap(), hex);
+        assert_eq!(
+            hash,
+            FileHash::digest(HashAlgorithm::Sha512Trunc256, b"Hello World!")
+        );
+
+        let multibase = hash.to_string();
+        assert!(multibase.starts_with('b'));
+        assert_eq!(FileHash::from_str(&multibase).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_verify_dispatches_on_algorithm() {
+        let data = b"Hello 1NGmnrtExYz4TApULNuiv4VpWS3rjKvnYX!";
+        for algorithm in [HashAlgorithm::Sha512Trunc256, HashAlgorithm::Blake3] {
+            let hash = FileHash::digest(algorithm, data);
+            assert_eq!(hash.algorithm(), Some(algorithm));
+            assert!(hash.verify(data));
+            assert!(hash.verify_reader(&data[..]).unwrap());
+            assert!(!hash.verify(b"tampered"));
+        }
+        assert!(FileHash::digest(HashAlgorithm::Blake3, data)
+            .to_sha512t_hex()
+            .is_none());
+        assert!(!FileHash::default().verify(data));
+    }
+
+    #[test]
+    fn test_serde() {
+        let hash = FileHash::digest(HashAlgorithm::Blake3, b"");
+        let value = toml::Value::try_from(&hash).unwrap();
+        assert_eq!(value.as_str().unwrap(), hash.to_string());
+        let parsed: FileHash = toml::Value::String(hash.to_string()).try_into().unwrap();
+        assert_eq!(parsed, hash);
+
+        let empty: FileHash = toml::Value::String(String::new()).try_into().unwrap();
+        assert!(empty.is_empty());
+        assert!(FileHash::from_str("not a hash").is_err());
+    }
+}
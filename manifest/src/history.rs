@@ -0,0 +1,406 @@
+//! Version history of manifests through `meta.prev`. Each version links the
+//! one it replaces by digest and signature, and since `prev` is part of the
+//! signed manifest, signers vouch for the whole chain. A receiver accepts an
+//! update only if it links the version it holds, so validly signed forks and
+//! replays of older versions are rejected. Signers of an update must be
+//! approved by the signers of the version it replaces.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    crypto::Signer,
+    error::Error,
+    internal::{PodInternalManifest, PodInternalManifestMeta},
+    manifest::{PodManifest, PodManifestMeta, PodManifestMetaPrev, SignatureScheme},
+};
+
+/// Link of a version to the previous one broken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainBreak {
+    /// Version has no `prev` though it replaces another version
+    MissingPrev,
+    /// `prev.hash` is not the digest of the previous version
+    Fork { expected: String, found: String },
+    /// `prev.sign` is not a signature of the previous version
+    UnknownSign { sign: String },
+    /// Version is not newer than the previous version
+    Rollback {
+        modified: DateTime<Utc>,
+        prev_modified: DateTime<Utc>,
+    },
+    /// Signers changed without approval of previous signers
+    UnapprovedSigners { reason: String },
+}
+
+impl fmt::Display for ChainBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainBreak::MissingPrev => write!(f, "previous version is not linked"),
+            ChainBreak::Fork { expected, found } => {
+                write!(f, "forked from {} instead of {}", found, expected)
+            }
+            ChainBreak::UnknownSign { sign } => {
+                write!(f, "`{}` is not a signature of previous version", sign)
+            }
+            ChainBreak::Rollback {
+                modified,
+                prev_modified,
+            } => write!(
+                f,
+                "modified {} is not after previous version {}",
+                modified, prev_modified
+            ),
+            ChainBreak::UnapprovedSigners { reason } => {
+                write!(
+                    f,
+                    "signers are not approved by previous version: {}",
+                    reason
+                )
+            }
+        }
+    }
+}
+
+/// Manifests linked to their previous version by `meta.prev`
+pub trait History {
+    /// Hex encoded digest signed by signers
+    fn digest(&self) -> Result<String, Error>;
+
+    /// Last modified time of version
+    fn modified(&self) -> Option<DateTime<Utc>>;
+
+    /// Signatures of version, any of them identifies it in `prev.sign`
+    fn signs(&self) -> Vec<&str>;
+
+    /// Link to the version this one replaces
+    fn prev(&self) -> Option<&PodManifestMetaPrev>;
+
+    /// Set link to the version this one replaces
+    fn set_prev(&mut self, prev: PodManifestMetaPrev);
+
+    /// Why signers of update are not approved by signers of this version,
+    /// None if they are
+    fn check_signers(&self, _update: &Self) -> Option<String> {
+        None
+    }
+
+    /// Link to this version for the version replacing it
+    fn link(&self) -> Result<PodManifestMetaPrev, Error> {
+        let sign = self
+            .signs()
+            .first()
+            .map(|sign| sign.to_string())
+            .ok_or_else(|| Error::missing_field("signatures"))?;
+        Ok(PodManifestMetaPrev {
+            modified: self
+                .modified()
+                .ok_or_else(|| Error::missing_field("meta.pod.modified"))?,
+            sign,
+            hash: self.digest()?,
+        })
+    }
+
+    /// Fill `prev` from the outgoing version before signing,
+    /// signatures made before over another `prev` turn invalid
+    fn chain_to(&mut self, outgoing: &Self) -> Result<(), Error> {
+        let prev = outgoing.link()?;
+        if self.prev() != Some(&prev) {
+            self.set_prev(prev);
+        }
+        Ok(())
+    }
+
+    /// Why update does not continue the chain from this version,
+    /// None if it does or is this very version
+    fn check_update(&self, update: &Self) -> Result<Option<ChainBreak>, Error> {
+        let digest = self.digest()?;
+        if update.digest()? == digest {
+            return Ok(None);
+        }
+        let (modified, prev_modified) = (update.modified(), self.modified());
+        if modified <= prev_modified {
+            return Ok(Some(ChainBreak::Rollback {
+                modified: modified.unwrap_or_default(),
+                prev_modified: prev_modified.unwrap_or_default(),
+            }));
+        }
+        let Some(prev) = update.prev() else {
+            return Ok(Some(ChainBreak::MissingPrev));
+        };
+        if prev.hash != digest {
+            return Ok(Some(ChainBreak::Fork {
+                expected: digest,
+                found: prev.hash.clone(),
+            }));
+        }
+        if !self.signs().contains(&prev.sign.as_str()) {
+            return Ok(Some(ChainBreak::UnknownSign {
+                sign: prev.sign.clone(),
+            }));
+        }
+        Ok(self
+            .check_signers(update)
+            .map(|reason| ChainBreak::UnapprovedSigners { reason }))
+    }
+}
+
+/// Verify versions, oldest first, form an unbroken chain. Returns the index
+/// of the first version not continuing the chain along with the reason.
+/// Signatures of each version still need to be verified on their own.
+pub fn verify_chain<'a, M: History + 'a>(
+    versions: impl IntoIterator<Item = &'a M>,
+) -> Result<Option<(usize, ChainBreak)>, Error> {
+    let mut versions = versions.into_iter();
+    let Some(mut prev) = versions.next() else {
+        return Ok(None);
+    };
+    for (index, version) in versions.enumerate() {
+        if let Some(chain_break) = prev.check_update(version)? {
+            return Ok(Some((index + 1, chain_break)));
+        }
+        prev = version;
+    }
+    Ok(None)
+}
+
+impl History for PodManifest {
+    fn digest(&self) -> Result<String, Error> {
+        PodManifest::digest(self)
+    }
+
+    fn modified(&self) -> Option<DateTime<Utc>> {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.pod.as_ref())
+            .map(|pod| pod.modified)
+            .or_else(|| self.files.as_ref().map(|files| files.modified))
+    }
+
+    /// Group signature under FROST, signatures of signers otherwise
+    fn signs(&self) -> Vec<&str> {
+        match self.signature.scheme {
+            SignatureScheme::Frost => vec![self.signature.root_sign.as_str()],
+            SignatureScheme::Bitcoin => self
+                .signatures
+                .iter()
+                .map(|sign| sign.sign.as_str())
+                .collect(),
+        }
+    }
+
+    fn prev(&self) -> Option<&PodManifestMetaPrev> {
+        self.meta.as_ref()?.prev.as_ref()
+    }
+
+    fn set_prev(&mut self, prev: PodManifestMetaPrev) {
+        self.meta
+            .get_or_insert(PodManifestMeta {
+                ignore: None,
+                prev: None,
+                client: None,
+                pod: None,
+            })
+            .prev = Some(prev);
+    }
+
+    /// Under FROST, a change of signers needs a rotation approved by
+    /// previous signers
+    fn check_signers(&self, update: &Self) -> Option<String> {
+        self.signature.check_signers(&update.signature)
+    }
+}
+
+impl History for PodInternalManifest {
+    fn digest(&self) -> Result<String, Error> {
+        PodInternalManifest::digest(self)
+    }
+
+    fn modified(&self) -> Option<DateTime<Utc>> {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.pod.as_ref())
+            .map(|pod| pod.modified)
+            .or_else(|| self.files.as_ref().map(|files| files.modified))
+    }
+
+    fn signs(&self) -> Vec<&str> {
+        self.signatures
+            .iter()
+            .map(|sign| sign.sign.as_str())
+            .collect()
+    }
+
+    fn prev(&self) -> Option<&PodManifestMetaPrev> {
+        self.meta.as_ref()?.prev.as_ref()
+    }
+
+    fn set_prev(&mut self, prev: PodManifestMetaPrev) {
+        self.meta
+            .get_or_insert(PodInternalManifestMeta {
+                ignore: None,
+                cert: None,
+                prev: None,
+                pod: None,
+                user_contents: None,
+                #[cfg(feature = "zeronet")]
+                user: None,
+            })
+            .prev = Some(prev);
+    }
+}
+
+impl PodManifest {
+    /// Sign manifest replacing outgoing version, linking it as `prev`
+    pub fn sign_update(
+        &mut self,
+        outgoing: &PodManifest,
+        signer: &impl Signer,
+    ) -> Result<(), Error> {
+        self.chain_to(outgoing)?;
+        self.sign(signer)
+    }
+}
+
+impl PodInternalManifest {
+    /// Sign manifest replacing outgoing version, linking it as `prev`
+    pub fn sign_update(
+        &mut self,
+        outgoing: &PodInternalManifest,
+        signer: &impl Signer,
+    ) -> Result<(), Error> {
+        self.chain_to(outgoing)?;
+        self.sign(signer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::PrivateKey;
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::{verify_chain, ChainBreak, History};
+    use crate::{
+        crypto::Signer,
+        manifest::{PodManifest, PodManifestMeta, PodManifestMetaPod, PodManifestSignature},
+        test_utils::new_key,
+    };
+
+    fn version(key: &PrivateKey, title: &str, modified: DateTime<Utc>) -> PodManifest {
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: key.address(),
+                signs_required: 1,
+                signers: vec![key.address()],
+                ..Default::default()
+            },
+            meta: Some(PodManifestMeta {
+                ignore: None,
+                prev: None,
+                client: None,
+                pod: Some(PodManifestMetaPod {
+                    address: key.address(),
+                    title: title.to_string(),
+                    modified,
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        };
+        manifest.sign_root(key).unwrap();
+        manifest
+    }
+
+    /// Three versions, each signed as update of the one before
+    fn chain(key: &PrivateKey) -> Vec<PodManifest> {
+        let now = Utc::now();
+        let mut first = version(key, "v1", now);
+        first.sign(key).unwrap();
+        let mut versions = vec![first];
+        for (i, title) in ["v2", "v3"].into_iter().enumerate() {
+            let mut next = version(key, title, now + Duration::seconds(i as i64 + 1));
+            next.sign_update(versions.last().unwrap(), key).unwrap();
+            versions.push(next);
+        }
+        versions
+    }
+
+    #[test]
+    fn test_sign_update_links_prev() {
+        let key = new_key();
+        let versions = chain(&key);
+        let prev = versions[2].prev().unwrap();
+        assert_eq!(prev.hash, versions[1].digest().unwrap());
+        assert_eq!(prev.sign, versions[1].signatures[0].sign);
+        assert_eq!(prev.modified, versions[1].modified().unwrap());
+        // prev is covered by signatures
+        assert!(versions[2].verify().unwrap().is_valid());
+        assert_eq!(verify_chain(&versions).unwrap(), None);
+        assert_eq!(versions[2].check_update(&versions[2]).unwrap(), None);
+
+        // unsigned outgoing version cannot be linked
+        let mut next = version(&key, "v4", Utc::now());
+        assert!(next
+            .sign_update(&version(&key, "v3", Utc::now()), &key)
+            .is_err());
+    }
+
+    #[test]
+    fn test_reject_fork_and_rollback() {
+        let key = new_key();
+        let versions = chain(&key);
+
+        // validly signed, but branching off v1
+        let mut fork = version(&key, "v3'", Utc::now() + Duration::seconds(10));
+        fork.sign_update(&versions[0], &key).unwrap();
+        assert!(fork.verify().unwrap().is_valid());
+        assert!(matches!(
+            versions[1].check_update(&fork).unwrap(),
+            Some(ChainBreak::Fork { .. })
+        ));
+
+        // replay of an older version
+        assert!(matches!(
+            versions[2].check_update(&versions[1]).unwrap(),
+            Some(ChainBreak::Rollback { .. })
+        ));
+        let chain_break = verify_chain([&versions[0], &versions[2], &versions[1]]).unwrap();
+        assert!(matches!(chain_break, Some((1, ChainBreak::Fork { .. }))));
+
+        // newer version without link
+        let mut unlinked = version(&key, "v4", Utc::now() + Duration::seconds(10));
+        unlinked.sign(&key).unwrap();
+        assert_eq!(
+            versions[2].check_update(&unlinked).unwrap(),
+            Some(ChainBreak::MissingPrev)
+        );
+
+        // link naming a signature the previous version does not carry
+        let mut forged = version(&key, "v4", Utc::now() + Duration::seconds(10));
+        let mut prev = versions[2].link().unwrap();
+        prev.sign = versions[0].signatures[0].sign.clone();
+        forged.set_prev(prev);
+        forged.sign(&key).unwrap();
+        assert!(matches!(
+            versions[2].check_update(&forged).unwrap(),
+            Some(ChainBreak::UnknownSign { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reject_unapproved_signers() {
+        let key = new_key();
+        let versions = chain(&key);
+
+        // validly linked, but signed by another primary taking over
+        let other = new_key();
+        let mut takeover = version(&other, "v4", Utc::now() + Duration::seconds(10));
+        takeover.sign_update(&versions[2], &other).unwrap();
+        assert!(takeover.verify().unwrap().is_valid());
+        assert!(matches!(
+            versions[2].check_update(&takeover).unwrap(),
+            Some(ChainBreak::UnapprovedSigners { .. })
+        ));
+    }
+}
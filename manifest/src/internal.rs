@@ -1,10 +1,15 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "zeronet")]
 use serde_json::Value;
 use zerucontent::{Cert, UserContents};
 
-use crate::manifest::{PodManifestFiles, PodManifestMetaPrev, PodManifestSigns};
+use crate::{
+    crypto::{sha256_hex, Signer},
+    error::Error,
+    manifest::{is_default, PodManifestFiles, PodManifestMetaPrev, PodManifestSigns},
+};
 
 #[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PodInternalManifest {
@@ -15,12 +20,35 @@ pub struct PodInternalManifest {
 }
 
 impl PodInternalManifest {
-    pub fn from_string(content: &str) -> Option<Self> {
-        if let Ok(file) = toml::from_str::<PodInternalManifest>(content) {
-            Some(file)
-        } else {
-            None
+    pub fn from_string(content: &str) -> Result<Self, Error> {
+        toml::from_str::<PodInternalManifest>(content).map_err(|err| Error::toml(content, err))
+    }
+
+    /// Canonical bytes covered by signatures, the manifest with `signatures` excluded
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut value = toml::Value::try_from(self)?;
+        if let toml::Value::Table(table) = &mut value {
+            table.remove("signatures");
         }
+        Ok(toml::to_string(&value)?.into_bytes())
+    }
+
+    /// Hex encoded sha256 of canonical bytes, this is the message signers sign
+    pub fn digest(&self) -> Result<String, Error> {
+        Ok(sha256_hex(&self.canonical_bytes()?))
+    }
+
+    /// Sign digest of manifest, replacing any previous signature of signer
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), Error> {
+        let address = signer.address();
+        let sign = signer.sign(&self.digest()?)?;
+        self.signatures.retain(|signs| signs.address != address);
+        self.signatures.push(PodManifestSigns {
+            address,
+            sign,
+            instant: Utc::now(),
+        });
+        Ok(())
     }
 }
 
@@ -41,6 +69,10 @@ pub struct PodInternalManifestMetaPod {
     /// pod address
     pub address: String,
 
+    /// index of address
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub address_index: usize,
+
     /// pod last modified
     pub modified: DateTime<Utc>,
 
@@ -49,4 +81,10 @@ pub struct PodInternalManifestMetaPod {
 
     /// user contents has optional where value can be null
     pub user_contents_optional_null: bool,
+
+    /// additional zeronet site specific data
+    pub data: Option<BTreeMap<String, Value>>,
+
+    /// typed export of fields kept in `data`, as it was at conversion
+    pub data_typed: Option<BTreeMap<String, Value>>,
 }
@@ -1,11 +1,87 @@
-use std::path::Path;
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Error;
 
 pub trait IO {
     type Item;
 
-    fn load(content: &str) -> Option<Self::Item>;
+    fn load(content: &str) -> Result<Self::Item, Error>;
+
+    fn load_from_path(path: impl AsRef<Path>) -> Result<Self::Item, Error>;
+
+    fn save(&self, path: impl AsRef<Path> + Clone) -> Result<(), Error>;
+}
+
+/// Write bytes to a file, creating parent directories as needed
+pub fn write_file(path: impl AsRef<Path>, content: &[u8]) -> Result<(), Error> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+    }
+    fs::write(path, content).map_err(|err| Error::io(path, err))
+}
+
+/// Write bytes to a new file readable by owner only on Unix, for secrets.
+/// Any file at path is replaced.
+pub fn write_private_file(path: impl AsRef<Path>, content: &[u8]) -> Result<(), Error> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+    }
+    // mode is only applied when the file is created
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(Error::io(path, err));
+        }
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content))
+        .map_err(|err| Error::io(path, err))
+}
+
+/// Serialise secret value as TOML file readable by owner only on Unix,
+/// replacing any file at path in one step
+pub fn save_private_toml<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    write_private_file(&tmp, toml::to_string(value)?.as_bytes())?;
+    fs::rename(&tmp, path).map_err(|err| Error::io(path, err))
+}
+
+/// Copy a file, creating parent directories of destination as needed
+pub fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64, Error> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+    }
+    fs::copy(from, to).map_err(|err| Error::io(from, err))
+}
+
+/// Parse TOML document into value
+pub fn from_toml<T: DeserializeOwned>(content: &str) -> Result<T, Error> {
+    toml::from_str::<T>(content).map_err(|err| Error::toml(content, err))
+}
 
-    fn load_from_path(path: impl AsRef<Path>) -> Option<Self::Item>;
+/// Read and parse TOML file
+pub fn load_toml<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, Error> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    from_toml(&content).map_err(|err| err.with_path(path))
+}
 
-    fn save(&self, path: impl AsRef<Path> + Clone) -> Option<bool>;
+/// Serialise value as TOML file, creating parent directories as needed
+pub fn save_toml<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<(), Error> {
+    write_file(path, toml::to_string(value)?.as_bytes())
 }
@@ -0,0 +1,159 @@
+//! Deterministic pod keys, derived from a single master seed like ZeroNet's
+//! master seed scheme: the ASCII seed is a BIP32 master key and each pod key
+//! is its normal child at `address_index`, so backing up the seed is enough
+//! to regenerate every pod key.
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use bitcoin::{
+    bip32::{ChildNumber, Xpriv},
+    hex::DisplayHex,
+    secp256k1::Secp256k1,
+    Network, PrivateKey,
+};
+use rand::{CryptoRng, Rng, RngCore};
+
+use crate::{
+    address::{Address, AddressKind},
+    error::Error,
+    manifest::PodManifestMetaPod,
+};
+
+/// Child indexes wrap around this, as in ZeroNet
+pub const ADDRESS_INDEX_LIMIT: u32 = 100_000_000;
+
+/// Master seed of a user, 64 hex chars as stored by ZeroNet
+#[derive(Clone, PartialEq, Eq)]
+pub struct MasterSeed(String);
+
+impl MasterSeed {
+    /// New random seed
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        MasterSeed(bytes.to_lower_hex_string())
+    }
+
+    /// Seed in its backup form
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Private key of pod at address index
+    pub fn derive(&self, address_index: u32) -> Result<PrivateKey, Error> {
+        let secp = Secp256k1::signing_only();
+        let master = Xpriv::new_master(Network::Bitcoin, self.0.as_bytes()).map_err(key_error)?;
+        let child =
+            ChildNumber::from_normal_idx(address_index % ADDRESS_INDEX_LIMIT).map_err(key_error)?;
+        Ok(master
+            .derive_priv(&secp, &[child])
+            .map_err(key_error)?
+            .to_priv())
+    }
+
+    /// Address of kind of pod at address index
+    pub fn address(&self, address_index: u32, kind: AddressKind) -> Result<Address, Error> {
+        Ok(Address::from_key(&self.derive(address_index)?, kind))
+    }
+
+    /// Key and random address index for a new pod, as ZeroNet picks them.
+    /// Index 0 is the master address, which never becomes a pod.
+    pub fn new_pod<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<(u32, PrivateKey), Error> {
+        let address_index = rng.gen_range(1..ADDRESS_INDEX_LIMIT);
+        Ok((address_index, self.derive(address_index)?))
+    }
+
+    /// Key of pod described by meta, checked against its address
+    pub fn pod_key(&self, pod: &PodManifestMetaPod) -> Result<PrivateKey, Error> {
+        let address = Address::from_str(&pod.address)?;
+        let address_index = u32::try_from(pod.address_index)
+            .map_err(|_| key_error(format!("address index {} out of range", pod.address_index)))?;
+        let key = self.derive(address_index)?;
+        if Address::from_key(&key, address.kind()) != address {
+            return Err(key_error(format!(
+                "{} is not derived from seed at index {}",
+                address, address_index
+            )));
+        }
+        Ok(key)
+    }
+
+    /// Address index of address by scanning indexes in range,
+    /// None if address is not derived from seed within range
+    pub fn find_index(&self, address: &str, indexes: Range<u32>) -> Result<Option<u32>, Error> {
+        let address = Address::from_str(address)?;
+        for address_index in indexes {
+            if self.address(address_index, address.kind())? == address {
+                return Ok(Some(address_index));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FromStr for MasterSeed {
+    type Err = Error;
+
+    fn from_str(seed: &str) -> Result<Self, Error> {
+        if seed.len() != 64 || !seed.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(key_error("master seed must be 64 hex chars"));
+        }
+        Ok(MasterSeed(seed.to_string()))
+    }
+}
+
+/// Seed is never printed by accident
+impl fmt::Debug for MasterSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterSeed(..)")
+    }
+}
+
+fn key_error(reason: impl fmt::Display) -> Error {
+    Error::Key(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rand::thread_rng;
+
+    use super::MasterSeed;
+    use crate::{address::AddressKind, manifest::PodManifestMetaPod};
+
+    const SEED: &str = "024ba10f8ee1d6fd36fd1a7e3b17f52c4c0b6cfbb8f4afe8a2b4de47ae5a1a8d";
+
+    #[test]
+    fn test_derive_deterministic() {
+        let seed = MasterSeed::from_str(SEED).unwrap();
+        assert_eq!(seed.derive(7).unwrap(), seed.derive(7).unwrap());
+        assert_ne!(seed.derive(7).unwrap(), seed.derive(8).unwrap());
+        // indexes wrap around like ZeroNet's
+        assert_eq!(seed.derive(7).unwrap(), seed.derive(100_000_007).unwrap());
+
+        let other = MasterSeed::new(&mut thread_rng());
+        assert_ne!(seed.derive(7).unwrap(), other.derive(7).unwrap());
+        assert!(MasterSeed::from_str("not a seed").is_err());
+        assert_eq!(format!("{:?}", seed), "MasterSeed(..)");
+    }
+
+    #[test]
+    fn test_find_index_and_pod_key() {
+        let seed = MasterSeed::from_str(SEED).unwrap();
+        for kind in [AddressKind::P2pkh, AddressKind::P2tr] {
+            let address = seed.address(42, kind).unwrap().to_string();
+            assert_eq!(seed.find_index(&address, 0..100).unwrap(), Some(42));
+            assert_eq!(seed.find_index(&address, 0..42).unwrap(), None);
+
+            let mut pod = PodManifestMetaPod {
+                address,
+                address_index: 42,
+                ..Default::default()
+            };
+            assert_eq!(seed.pod_key(&pod).unwrap(), seed.derive(42).unwrap());
+            pod.address_index = 41;
+            assert!(seed.pod_key(&pod).is_err());
+        }
+    }
+}
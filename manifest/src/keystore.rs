@@ -0,0 +1,444 @@
+//! Local keystore of pod and user private keys, kept by address in a TOML
+//! file. Secrets are encrypted with XChaCha20-Poly1305 under a key derived
+//! from a passphrase with Argon2id, and only usable while unlocked.
+
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use bitcoin::{
+    hex::{DisplayHex, FromHex},
+    PrivateKey,
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address::{sign_schnorr, Address, AddressKind},
+    crypto::{sign_message, Signer},
+    error::Error,
+    io::{load_toml, save_toml},
+    keys::MasterSeed,
+};
+
+/// Associated data of passphrase check, entries use their address
+const CHECK: &[u8] = b"decentnet keystore";
+
+/// Argon2id cost parameters, stored with keystore
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub m_cost: u32,
+    /// Iterations
+    pub t_cost: u32,
+    /// Parallelism
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Kind of secret stored for an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretKind {
+    /// WIF private key of address
+    Wif,
+    /// Master seed, stored by its master address at index 0 as in ZeroNet
+    Seed,
+}
+
+/// Encrypted secret of an address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct KeystoreEntry {
+    kind: SecretKind,
+    /// Hex encoded 24 byte nonce
+    nonce: String,
+    /// Hex encoded ciphertext, bound to address
+    secret: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    secret: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct KeystoreFile {
+    kdf: KdfParams,
+    /// Hex encoded salt of KDF
+    salt: String,
+    /// Empty message sealed under derived key, to check passphrase
+    check: Sealed,
+    #[serde(default)]
+    keys: BTreeMap<String, KeystoreEntry>,
+}
+
+/// Keystore, locked after loading until unlocked with its passphrase
+pub struct Keystore {
+    file: KeystoreFile,
+    key: Option<Key>,
+}
+
+impl Keystore {
+    /// New empty keystore, unlocked
+    pub fn create<R: RngCore + CryptoRng>(
+        passphrase: &str,
+        kdf: KdfParams,
+        rng: &mut R,
+    ) -> Result<Self, Error> {
+        let mut salt = [0u8; 16];
+        rng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, &kdf)?;
+        let (nonce, secret) = seal(&key, CHECK, b"", rng)?;
+        Ok(Keystore {
+            file: KeystoreFile {
+                kdf,
+                salt: salt.to_lower_hex_string(),
+                check: Sealed { nonce, secret },
+                keys: BTreeMap::new(),
+            },
+            key: Some(key),
+        })
+    }
+
+    /// Load keystore file, locked
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Keystore {
+            file: load_toml(path)?,
+            key: None,
+        })
+    }
+
+    /// Save keystore file, secrets stay encrypted
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        save_toml(&self.file, path)
+    }
+
+    /// Unlock with passphrase, failing if it is wrong
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), Error> {
+        let salt = Vec::<u8>::from_hex(&self.file.salt)
+            .map_err(|err| Error::Key(format!("invalid salt: {}", err)))?;
+        let key = derive_key(passphrase, &salt, &self.file.kdf)?;
+        open(&key, CHECK, &self.file.check.nonce, &self.file.check.secret)
+            .map_err(|_| Error::Key("wrong passphrase".to_string()))?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Forget derived key, secrets can no longer be used
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// Addresses with stored secrets and their kind
+    pub fn list(&self) -> impl Iterator<Item = (&str, SecretKind)> {
+        self.file
+            .keys
+            .iter()
+            .map(|(address, entry)| (address.as_str(), entry.kind))
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.file.keys.contains_key(address)
+    }
+
+    /// Store WIF private key for its address of kind, returning the address
+    pub fn import_wif<R: RngCore + CryptoRng>(
+        &mut self,
+        wif: &str,
+        kind: AddressKind,
+        rng: &mut R,
+    ) -> Result<String, Error> {
+        let key =
+            PrivateKey::from_wif(wif).map_err(|err| Error::Key(format!("invalid WIF: {}", err)))?;
+        let address = Address::from_key(&key, kind).to_string();
+        self.insert(&address, SecretKind::Wif, key.to_wif().as_bytes(), rng)?;
+        Ok(address)
+    }
+
+    /// Store master seed, returning its master address
+    pub fn import_seed<R: RngCore + CryptoRng>(
+        &mut self,
+        seed: &MasterSeed,
+        rng: &mut R,
+    ) -> Result<String, Error> {
+        let address = seed.address(0, AddressKind::P2pkh)?.to_string();
+        self.insert(&address, SecretKind::Seed, seed.as_str().as_bytes(), rng)?;
+        Ok(address)
+    }
+
+    /// Derive pod key at address index from a stored seed and store it,
+    /// returning the pod address
+    pub fn import_derived<R: RngCore + CryptoRng>(
+        &mut self,
+        master_address: &str,
+        address_index: u32,
+        kind: AddressKind,
+        rng: &mut R,
+    ) -> Result<String, Error> {
+        let key = self.export_seed(master_address)?.derive(address_index)?;
+        let address = Address::from_key(&key, kind).to_string();
+        self.insert(&address, SecretKind::Wif, key.to_wif().as_bytes(), rng)?;
+        Ok(address)
+    }
+
+    /// WIF private key of address
+    pub fn export_wif(&self, address: &str) -> Result<String, Error> {
+        self.secret(address, SecretKind::Wif)
+    }
+
+    /// Master seed stored by master address
+    pub fn export_seed(&self, master_address: &str) -> Result<MasterSeed, Error> {
+        MasterSeed::from_str(&self.secret(master_address, SecretKind::Seed)?)
+    }
+
+    /// Remove secret of address, returning whether it was stored
+    pub fn remove(&mut self, address: &str) -> bool {
+        self.file.keys.remove(address).is_some()
+    }
+
+    /// Signer for address, usable with manifest signing while unlocked
+    pub fn signer(&self, address: &str) -> Result<KeystoreSigner<'_>, Error> {
+        let address = Address::from_str(address)?;
+        self.export_wif(&address.to_string())?;
+        Ok(KeystoreSigner {
+            keystore: self,
+            address,
+        })
+    }
+
+    /// Store secret of address. An address stores one kind of secret, so a
+    /// seed is never replaced by the key of its master address or the other
+    /// way around.
+    fn insert<R: RngCore + CryptoRng>(
+        &mut self,
+        address: &str,
+        kind: SecretKind,
+        secret: &[u8],
+        rng: &mut R,
+    ) -> Result<(), Error> {
+        if let Some(entry) = self.file.keys.get(address) {
+            if entry.kind != kind {
+                return Err(Error::Key(format!(
+                    "{:?} already stored for {}",
+                    entry.kind, address
+                )));
+            }
+        }
+        let key = self.key()?;
+        let (nonce, secret) = seal(key, address.as_bytes(), secret, rng)?;
+        self.file.keys.insert(
+            address.to_string(),
+            KeystoreEntry {
+                kind,
+                nonce,
+                secret,
+            },
+        );
+        Ok(())
+    }
+
+    fn secret(&self, address: &str, kind: SecretKind) -> Result<String, Error> {
+        let key = self.key()?;
+        let entry = match self.file.keys.get(address) {
+            Some(entry) if entry.kind == kind => entry,
+            _ => return Err(Error::Key(format!("no {:?} stored for {}", kind, address))),
+        };
+        let secret = open(key, address.as_bytes(), &entry.nonce, &entry.secret)?;
+        String::from_utf8(secret).map_err(|_| Error::Key(format!("corrupt secret of {}", address)))
+    }
+
+    fn key(&self) -> Result<&Key, Error> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| Error::Key("keystore is locked".to_string()))
+    }
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("addresses", &self.file.keys.keys().collect::<Vec<_>>())
+            .field("locked", &self.is_locked())
+            .finish()
+    }
+}
+
+/// Signs with a key held in keystore, decrypting it for each signature
+pub struct KeystoreSigner<'a> {
+    keystore: &'a Keystore,
+    address: Address,
+}
+
+impl Signer for KeystoreSigner<'_> {
+    fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    fn sign(&self, message: &str) -> Result<String, Error> {
+        let wif = self.keystore.export_wif(&self.address.to_string())?;
+        let key = PrivateKey::from_wif(&wif)
+            .map_err(|err| Error::Key(format!("invalid WIF: {}", err)))?;
+        Ok(match self.address.kind() {
+            AddressKind::P2pkh => sign_message(&key, message),
+            AddressKind::P2tr => sign_schnorr(&key, message),
+        })
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<Key, Error> {
+    let kdf_error = |err: argon2::Error| Error::Key(format!("key derivation failed: {}", err));
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)).map_err(kdf_error)?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(kdf_error)?;
+    Ok(key)
+}
+
+fn seal<R: RngCore + CryptoRng>(
+    key: &Key,
+    aad: &[u8],
+    msg: &[u8],
+    rng: &mut R,
+) -> Result<(String, String), Error> {
+    let mut nonce = XNonce::default();
+    rng.fill_bytes(&mut nonce);
+    let secret = XChaCha20Poly1305::new(key)
+        .encrypt(&nonce, Payload { msg, aad })
+        .map_err(|_| Error::Key("encryption failed".to_string()))?;
+    Ok((nonce.to_lower_hex_string(), secret.to_lower_hex_string()))
+}
+
+fn open(key: &Key, aad: &[u8], nonce: &str, secret: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::Key("secret cannot be decrypted".to_string());
+    let nonce = <[u8; 24]>::from_hex(nonce).map_err(|_| invalid())?;
+    let secret = Vec::<u8>::from_hex(secret).map_err(|_| invalid())?;
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &secret, aad })
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::{KdfParams, Keystore, SecretKind};
+    use crate::{
+        address::AddressKind,
+        keys::MasterSeed,
+        manifest::{PodManifest, PodManifestSignature},
+        test_utils::new_key,
+    };
+
+    /// Cheap parameters, tests only
+    const KDF: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_lock_unlock() {
+        let path = "tests/tmp/keystore/lock.toml";
+        let wif = new_key().to_wif();
+        let mut keystore = Keystore::create("hunter2", KDF, &mut thread_rng()).unwrap();
+        let address = keystore
+            .import_wif(&wif, AddressKind::P2pkh, &mut thread_rng())
+            .unwrap();
+        keystore.save(path).unwrap();
+        assert!(!std::fs::read_to_string(path).unwrap().contains(&wif));
+
+        let mut keystore = Keystore::load(path).unwrap();
+        assert!(keystore.is_locked());
+        assert_eq!(
+            keystore.list().collect::<Vec<_>>(),
+            vec![(address.as_str(), SecretKind::Wif)]
+        );
+        assert!(keystore.export_wif(&address).is_err());
+        assert!(keystore.unlock("hunter3").is_err());
+        keystore.unlock("hunter2").unwrap();
+        assert_eq!(keystore.export_wif(&address).unwrap(), wif);
+        keystore.lock();
+        assert!(keystore.export_wif(&address).is_err());
+    }
+
+    #[test]
+    fn test_seed_and_signer() {
+        let mut rng = thread_rng();
+        let seed = MasterSeed::new(&mut rng);
+        let mut keystore = Keystore::create("hunter2", KDF, &mut rng).unwrap();
+        let master = keystore.import_seed(&seed, &mut rng).unwrap();
+        assert_eq!(keystore.export_seed(&master).unwrap(), seed);
+        // seed is not a signing key
+        assert!(keystore.signer(&master).is_err());
+
+        let pod = keystore
+            .import_derived(&master, 42, AddressKind::P2tr, &mut rng)
+            .unwrap();
+        assert_eq!(
+            pod,
+            seed.address(42, AddressKind::P2tr).unwrap().to_string()
+        );
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: pod.clone(),
+                signs_required: 1,
+                signers: vec![pod.clone()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let signer = keystore.signer(&pod).unwrap();
+        manifest.sign_root(&signer).unwrap();
+        manifest.sign(&signer).unwrap();
+        assert!(manifest.verify().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_seed_not_replaced() {
+        let mut rng = thread_rng();
+        let seed = MasterSeed::new(&mut rng);
+        let mut keystore = Keystore::create("hunter2", KDF, &mut rng).unwrap();
+        let master = keystore.import_seed(&seed, &mut rng).unwrap();
+
+        // key of master address is at index 0
+        assert!(keystore
+            .import_derived(&master, 0, AddressKind::P2pkh, &mut rng)
+            .is_err());
+        let wif = seed.derive(0).unwrap().to_wif();
+        assert!(keystore
+            .import_wif(&wif, AddressKind::P2pkh, &mut rng)
+            .is_err());
+        assert_eq!(keystore.export_seed(&master).unwrap(), seed);
+
+        // same kind again is fine
+        assert_eq!(keystore.import_seed(&seed, &mut rng).unwrap(), master);
+        let pod = keystore
+            .import_derived(&master, 0, AddressKind::P2tr, &mut rng)
+            .unwrap();
+        assert_eq!(
+            keystore
+                .import_wif(&wif, AddressKind::P2tr, &mut rng)
+                .unwrap(),
+            pod
+        );
+        assert_eq!(keystore.export_seed(&master).unwrap(), seed);
+    }
+}
@@ -1,6 +1,25 @@
+pub mod address;
+pub mod bundle;
+pub mod cert;
+pub mod chunk;
+pub mod crypto;
+pub mod diff;
+pub mod error;
 pub mod file;
+pub mod hash;
+pub mod history;
 pub mod internal;
 pub mod io;
+pub mod keys;
+pub mod keystore;
 pub mod manifest;
+pub mod permissions;
+pub mod store;
+#[cfg(test)]
+mod test_utils;
+pub mod threshold;
+pub mod verify;
 #[cfg(feature = "zeronet")]
 pub mod zeronet;
+
+pub use error::Error;
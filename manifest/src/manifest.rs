@@ -1,10 +1,16 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::Path};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::file::PodFileRoot;
+use crate::{
+    crypto::{sha256_hex, Signer},
+    error::Error,
+    file::PodFileRoot,
+    hash::{FileHash, HashAlgorithm},
+    io::write_file,
+};
 
 #[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PodManifest {
@@ -17,12 +23,56 @@ pub struct PodManifest {
 }
 
 impl PodManifest {
-    pub fn from_string(content: &str) -> Option<Self> {
-        if let Ok(file) = toml::from_str::<PodManifest>(content) {
-            Some(file)
-        } else {
-            None
+    pub fn from_string(content: &str) -> Result<Self, Error> {
+        toml::from_str::<PodManifest>(content).map_err(|err| Error::toml(content, err))
+    }
+
+    /// Canonical bytes of manifest.toml covered by signatures,
+    /// i.e. the manifest with `signatures` excluded and keys sorted.
+    /// Under FROST `root_sign` is the signature itself and excluded as well.
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut value = toml::Value::try_from(self)?;
+        if let toml::Value::Table(table) = &mut value {
+            table.remove("signatures");
+            if self.signature.scheme == SignatureScheme::Frost {
+                if let Some(toml::Value::Table(signature)) = table.get_mut("signature") {
+                    signature.remove("root_sign");
+                }
+            }
+        }
+        Ok(toml::to_string(&value)?.into_bytes())
+    }
+
+    /// Hex encoded sha256 of canonical bytes, this is the message signers sign
+    pub fn digest(&self) -> Result<String, Error> {
+        Ok(sha256_hex(&self.canonical_bytes()?))
+    }
+
+    /// Sign digest of manifest, replacing any previous signature of signer
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), Error> {
+        let address = signer.address();
+        if !self.signature.signers.contains(&address) {
+            return Err(Error::unauthorized(address, "not listed in signers"));
+        }
+        let sign = signer.sign(&self.digest()?)?;
+        self.signatures.retain(|signs| signs.address != address);
+        self.signatures.push(PodManifestSigns {
+            address,
+            sign,
+            instant: Utc::now(),
+        });
+        Ok(())
+    }
+
+    /// Regenerate root_sign after signers or signs_required changed,
+    /// existing signatures no longer match digest and need to be renewed
+    pub fn sign_root(&mut self, signer: &impl Signer) -> Result<(), Error> {
+        let address = signer.address();
+        if address != self.signature.primary {
+            return Err(Error::unauthorized(address, "not primary signer"));
         }
+        self.signature.root_sign = signer.sign(&self.signature.root_message())?;
+        Ok(())
     }
 }
 
@@ -34,7 +84,7 @@ pub struct PodManifestFiles {
     /// Size of files.toml
     pub size: usize,
     /// hash of files.toml
-    pub hash: String,
+    pub hash: FileHash,
     /// Last modified time of files.toml
     pub modified: DateTime<Utc>,
 
@@ -42,6 +92,51 @@ pub struct PodManifestFiles {
     pub file_root: PodFileRoot,
 }
 
+impl PodManifestFiles {
+    /// Reference file root, recording size and hash of its serialised files.toml
+    pub fn new(
+        file_root: PodFileRoot,
+        modified: DateTime<Utc>,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, Error> {
+        let mut files = PodManifestFiles {
+            manifest: default_files_manifest_path(),
+            hash: FileHash::digest(algorithm, b""),
+            modified,
+            file_root,
+            ..Default::default()
+        };
+        files.update()?;
+        Ok(files)
+    }
+
+    /// Serialised files.toml of file root
+    pub fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml::to_string(&self.file_root)?)
+    }
+
+    /// Recompute size and hash after file root changed, keeping hash algorithm
+    pub fn update(&mut self) -> Result<(), Error> {
+        let content = self.to_toml()?;
+        let algorithm = self.hash.algorithm().unwrap_or_default();
+        self.size = content.len();
+        self.hash = FileHash::digest(algorithm, content.as_bytes());
+        Ok(())
+    }
+
+    /// Sign root hash of file root, then update size and hash
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<(), Error> {
+        self.file_root.sign(signer)?;
+        self.update()
+    }
+
+    /// Write files.toml of file root into pod directory
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let content = self.to_toml()?;
+        write_file(path.as_ref().join(&self.manifest), content.as_bytes())
+    }
+}
+
 pub fn default_files_manifest_path() -> String {
     "files.toml".to_string()
 }
@@ -50,8 +145,23 @@ pub fn is_default<T: Default + PartialEq>(t: &T) -> bool {
     t == &T::default()
 }
 
+/// How `PodManifestSignature` is signed and verified
+#[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureScheme {
+    /// Bitcoin message signatures, each signer signs individually
+    #[default]
+    Bitcoin,
+    /// FROST threshold signature, `primary` is the hex encoded group verifying key
+    /// and `root_sign` a single aggregated signature over manifest digest
+    Frost,
+}
+
 #[derive(Default, Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub struct PodManifestSignature {
+    /// Signature scheme of manifest
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub scheme: SignatureScheme,
     /// Primary signer of manifest, this is usually the pod address
     pub primary: String,
     /// Root signature of all signers, can only be signed by primary signer
@@ -61,6 +171,56 @@ pub struct PodManifestSignature {
     /// List of Signers for this manifest
     /// Can omit this sign in manifest.toml since we need only one signature required
     pub signers: Vec<String>,
+    /// Hex encoded verifying share of each signer under FROST, by identifier.
+    /// Signers approve rotations with their own share.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signer_keys: BTreeMap<String, String>,
+    /// Changes of signers and signs_required, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rotations: Vec<PodManifestRotation>,
+}
+
+impl PodManifestSignature {
+    /// Message signed by primary as `root_sign`, same as ZeroNet's `signers_sign`
+    pub fn root_message(&self) -> String {
+        format!("{}:{}", self.signs_required, self.signers.join(","))
+    }
+}
+
+/// Change of signer set, approved by at least `signs_required` of the
+/// previous signers, each signing with their own verifying share. Signatures
+/// of the group key would not do, as later signer sets hold the same key.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub struct PodManifestRotation {
+    /// Signers before rotation
+    pub signers: Vec<String>,
+    /// Signatures required before rotation
+    pub signs_required: usize,
+    /// Signers after rotation
+    pub new_signers: Vec<String>,
+    /// Signatures required after rotation
+    pub new_signs_required: usize,
+    /// Date of rotation
+    pub instant: DateTime<Utc>,
+    /// Verifying shares of signers before rotation, by identifier
+    pub signer_keys: BTreeMap<String, String>,
+    /// Signatures of previous signers over rotation message, by identifier
+    #[serde(default)]
+    pub approvals: BTreeMap<String, String>,
+}
+
+impl PodManifestRotation {
+    /// Message approved by previous signers of group `primary`
+    pub fn message(&self, primary: &str) -> String {
+        format!(
+            "rotate:{}:{}:{}>{}:{}",
+            primary,
+            self.signs_required,
+            self.signers.join(","),
+            self.new_signs_required,
+            self.new_signers.join(",")
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
@@ -101,7 +261,7 @@ pub struct PodManifestMeta {
     pub pod: Option<PodManifestMetaPod>,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub struct PodManifestMetaPrev {
     /// tiny source control for safer updates can be extended to full git
     pub modified: DateTime<Utc>,
@@ -115,9 +275,9 @@ pub struct PodManifestMetaPrev {
 #[derive(Default, Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub struct PodManifestMetaClient {
     pub version: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub platform: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub language: String,
 }
 
@@ -132,23 +292,23 @@ pub struct PodManifestMetaPod {
     /// address of pod
     pub address: String,
     /// index of address
-    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub address_index: usize,
     /// title of pod
     pub title: String,
     /// description of pod
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
     /// background color of pod
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     #[serde(rename = "background-color")]
     pub background_color: String,
     /// dark background color of pod
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     #[serde(rename = "background-color-dark")]
     pub background_color_dark: String,
     /// domain of pod
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub domain: String,
 
     /// allow cloning of pod
@@ -178,15 +338,18 @@ pub struct PodManifestMetaPod {
 
     /// additional zeronet site specific data
     pub data: Option<BTreeMap<String, Value>>,
+
+    /// typed export of fields kept in `data`, as it was at conversion
+    pub data_typed: Option<BTreeMap<String, Value>>,
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub struct PodManifestMetaPodParent {
     /// address of parent pod
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub address: String,
     /// root of template
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub template_root: String,
 }
 
@@ -197,7 +360,11 @@ mod tests {
         io::{read_to_string, Write},
     };
 
-    use super::PodManifest;
+    use super::{PodManifest, PodManifestSignature};
+    use crate::{
+        test_utils::new_key,
+        {crypto::Signer, error::Error},
+    };
 
     #[test]
     fn test_manifest_deserialize() {
@@ -206,7 +373,7 @@ mod tests {
 
         let manifest_file = PodManifest::from_string(&content);
 
-        assert!(manifest_file.is_some());
+        assert!(manifest_file.is_ok());
     }
 
     #[test]
@@ -229,7 +396,7 @@ mod tests {
 
         let manifest_file = PodManifest::from_string(&content);
 
-        assert!(manifest_file.is_some());
+        assert!(manifest_file.is_ok());
     }
 
     #[test]
@@ -244,4 +411,71 @@ mod tests {
         let mut file = File::create("tests/tmp/manifest2.toml").unwrap();
         file.write_all(content.as_bytes()).unwrap();
     }
+
+    #[test]
+    fn test_manifest_parse_error_location() {
+        let content = "[signature]\nprimary = \"1Pod\"\nsigns_required = \"one\"\n";
+        match PodManifest::from_string(content) {
+            Err(Error::Toml { line, column, .. }) => assert_eq!((line, column), (3, 18)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_manifest_sign() {
+        let primary = new_key();
+        let cosigner = new_key();
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: primary.address(),
+                signs_required: 2,
+                signers: vec![primary.address(), cosigner.address()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        manifest.sign_root(&primary).unwrap();
+        manifest.sign(&primary).unwrap();
+        manifest.sign(&cosigner).unwrap();
+        assert!(manifest.verify().unwrap().is_valid());
+
+        // signing again replaces previous entry
+        manifest.sign(&cosigner).unwrap();
+        assert_eq!(manifest.signatures.len(), 2);
+        assert_eq!(manifest.signatures[1].address, cosigner.address());
+
+        // changing threshold needs new root_sign and signatures
+        manifest.signature.signs_required = 1;
+        assert!(!manifest.verify().unwrap().is_valid());
+        manifest.sign_root(&primary).unwrap();
+        manifest.sign(&primary).unwrap();
+        let report = manifest.verify().unwrap();
+        assert_eq!(report.valid_signs(), 1);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn test_manifest_sign_unauthorized() {
+        let primary = new_key();
+        let cosigner = new_key();
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: primary.address(),
+                signs_required: 1,
+                signers: vec![primary.address(), cosigner.address()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            manifest.sign(&new_key()),
+            Err(Error::Unauthorized { .. })
+        ));
+        assert!(matches!(
+            manifest.sign_root(&cosigner),
+            Err(Error::Unauthorized { .. })
+        ));
+        assert!(manifest.signatures.is_empty());
+        assert!(manifest.signature.root_sign.is_empty());
+    }
 }
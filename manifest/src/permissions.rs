@@ -0,0 +1,798 @@
+//! Enforcement of ZeroNet `user_contents` rules of a parent internal manifest,
+//! like `data/users/content.json`, on user manifests below it.
+//! Rules of a user are resolved as ZeroNet does: `permissions` by address or
+//! `cert_user_id`, where `false` bans, merged with every `permission_rules`
+//! pattern matching `auth_type/cert_user_id`.
+//! User content modified at or before `archived` of its directory or
+//! `archived_before` is archived, and may be pruned from storage.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use fancy_regex::Regex;
+use serde::Deserialize;
+use zerucontent::{Cert, UserContents};
+
+use crate::{
+    cert::CertExt,
+    crypto::verify_message,
+    error::Error,
+    file::{is_match, pattern},
+    internal::PodInternalManifest,
+    io::load_toml,
+};
+
+/// Rules granted to users, any of them may be absent
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PermissionRules {
+    /// Total size of listed files in bytes
+    pub max_size: Option<usize>,
+    /// Total size of listed optional files in bytes
+    pub max_size_optional: Option<usize>,
+    /// Pattern every listed file must match
+    pub files_allowed: Option<String>,
+    /// Pattern every listed optional file must match
+    pub files_allowed_optional: Option<String>,
+    /// Addresses allowed to sign besides the user
+    #[serde(default)]
+    pub signers: Vec<String>,
+    /// Valid signatures required, 1 if absent
+    pub signers_required: Option<usize>,
+}
+
+impl PermissionRules {
+    /// Merge other rules in, keeping larger sizes and longer patterns
+    /// and appending signers
+    fn merge(&mut self, other: &PermissionRules) {
+        fn larger(current: &mut Option<usize>, other: Option<usize>) {
+            if other > *current {
+                *current = other;
+            }
+        }
+        fn longer(current: &mut Option<String>, other: &Option<String>) {
+            let len = |pattern: &Option<String>| pattern.as_ref().map(String::len);
+            if current.is_none() || len(other) > len(current) {
+                if let Some(other) = other {
+                    *current = Some(other.clone());
+                }
+            }
+        }
+        larger(&mut self.max_size, other.max_size);
+        larger(&mut self.max_size_optional, other.max_size_optional);
+        larger(&mut self.signers_required, other.signers_required);
+        longer(&mut self.files_allowed, &other.files_allowed);
+        longer(
+            &mut self.files_allowed_optional,
+            &other.files_allowed_optional,
+        );
+        self.signers.extend(other.signers.iter().cloned());
+    }
+}
+
+/// Per-user entry of `permissions`, `false` bans the user
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Permission {
+    Allowed(bool),
+    Rules(PermissionRules),
+}
+
+/// `user_contents` of a parent manifest
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct UserContentRules {
+    /// Allowed cert issuer addresses by domain
+    #[serde(default)]
+    pub cert_signers: BTreeMap<String, Vec<String>>,
+    /// Domains which are their own cert issuer address
+    pub cert_signers_pattern: Option<String>,
+    /// Rules by user address or `cert_user_id`
+    #[serde(default)]
+    pub permissions: BTreeMap<String, Permission>,
+    /// Rules by pattern of `auth_type/cert_user_id`
+    #[serde(default)]
+    pub permission_rules: BTreeMap<String, PermissionRules>,
+    /// Epoch seconds by user directory, content modified
+    /// at or before it is archived
+    #[serde(default)]
+    pub archived: BTreeMap<String, f64>,
+    /// Epoch seconds, content of any user modified at or before it is archived
+    pub archived_before: Option<f64>,
+}
+
+/// Rules resolved for a single user
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct UserRules {
+    pub user_address: String,
+    pub banned: bool,
+    /// Merged rules, `signers` includes user unless banned
+    pub rules: PermissionRules,
+}
+
+/// Rule broken by a user manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// User is banned in `permissions`
+    Banned { user_address: String },
+    /// `cert_signers` are set but user manifest has no cert
+    MissingCert,
+    /// `cert_user_id` is not of the form `name@domain`
+    InvalidCertUserId { cert_user_id: String },
+    /// Cert is issued for a domain not listed in `cert_signers`
+    UnknownCertSigner { domain: String },
+    /// Cert is not signed for user by an issuer of its domain
+    InvalidCertSign { cert_user_id: String },
+    /// Listed files exceed `max_size`
+    TooLarge { size: usize, max_size: usize },
+    /// Listed optional files exceed `max_size_optional`
+    OptionalTooLarge { size: usize, max_size: usize },
+    /// File does not match `files_allowed` or `files_allowed_optional`
+    FileNotAllowed { path: String, pattern: String },
+    /// Fewer valid signatures of allowed signers than `signers_required`
+    NotEnoughSigns { valid: usize, required: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Banned { user_address } => write!(f, "user {} is banned", user_address),
+            Violation::MissingCert => write!(f, "cert is required"),
+            Violation::InvalidCertUserId { cert_user_id } => {
+                write!(f, "invalid cert_user_id `{}`", cert_user_id)
+            }
+            Violation::UnknownCertSigner { domain } => {
+                write!(f, "cert domain `{}` is not an allowed cert signer", domain)
+            }
+            Violation::InvalidCertSign { cert_user_id } => {
+                write!(f, "cert `{}` is not signed by its issuer", cert_user_id)
+            }
+            Violation::TooLarge { size, max_size } => {
+                write!(f, "files are too large: {}B > {}B", size, max_size)
+            }
+            Violation::OptionalTooLarge { size, max_size } => {
+                write!(f, "optional files are too large: {}B > {}B", size, max_size)
+            }
+            Violation::FileNotAllowed { path, pattern } => {
+                write!(f, "file `{}` is not allowed by `{}`", path, pattern)
+            }
+            Violation::NotEnoughSigns { valid, required } => {
+                write!(f, "{} valid signs, {} required", valid, required)
+            }
+        }
+    }
+}
+
+/// Outcome of `prune_users`
+#[derive(Default, Debug)]
+pub struct PruneReport {
+    /// User directories whose manifest is archived, which may be deleted
+    pub pruned: Vec<PathBuf>,
+    /// User directories whose manifest could not be judged, left alone
+    pub rejected: Vec<(PathBuf, Error)>,
+}
+
+impl UserContentRules {
+    /// Parse rules out of `user_contents`
+    pub fn from_user_contents(user_contents: &UserContents) -> Result<Self, Error> {
+        let value = serde_json::to_value(user_contents).map_err(Error::json)?;
+        serde_json::from_value(value).map_err(Error::json)
+    }
+
+    /// Rules of user at address, identified by cert if any
+    pub fn rules_for(&self, user_address: &str, cert: Option<&Cert>) -> Result<UserRules, Error> {
+        let (cert_user_id, user_urn) = match cert {
+            Some(cert) => (
+                cert.cert_user_id.as_str(),
+                format!("{}/{}", cert.cert_auth_type, cert.cert_user_id),
+            ),
+            None => ("n-a", "n-a/n-a".to_string()),
+        };
+        let permission = self
+            .permissions
+            .get(user_address)
+            .or_else(|| self.permissions.get(cert_user_id));
+        let (banned, mut rules) = match permission {
+            Some(Permission::Allowed(allowed)) => (!allowed, PermissionRules::default()),
+            Some(Permission::Rules(rules)) => (false, rules.clone()),
+            None => (false, PermissionRules::default()),
+        };
+        for (permission_pattern, permission_rules) in &self.permission_rules {
+            if is_match(&pattern(Some(permission_pattern))?, &user_urn)? {
+                rules.merge(permission_rules);
+            }
+        }
+        if !banned {
+            rules.signers.push(user_address.to_string());
+        }
+        Ok(UserRules {
+            user_address: user_address.to_string(),
+            banned,
+            rules,
+        })
+    }
+
+    /// Whether content of user directory modified at instant is archived,
+    /// by `archived` of the directory or by `archived_before`
+    pub fn is_archived(&self, user_dir: &str, modified: DateTime<Utc>) -> bool {
+        let modified = modified.timestamp_millis() as f64 / 1000.0;
+        [self.archived.get(user_dir).copied(), self.archived_before]
+            .into_iter()
+            .flatten()
+            .any(|archived| modified <= archived)
+    }
+
+    /// Violation if cert is not signed by an issuer of its domain in
+    /// `cert_signers`, certs are optional without `cert_signers`
+    fn check_cert(
+        &self,
+        user_address: &str,
+        cert: Option<&Cert>,
+    ) -> Result<Option<Violation>, Error> {
+        if self.cert_signers.is_empty() && self.cert_signers_pattern.is_none() {
+            return Ok(None);
+        }
+        // domains matching pattern are issuer addresses themselves
+        let signers_pattern = pattern(self.cert_signers_pattern.as_deref())?;
+        let Some(cert) = cert else {
+            return Ok(Some(Violation::MissingCert));
+        };
+        let Some((_, domain)) = cert.name_domain() else {
+            return Ok(Some(Violation::InvalidCertUserId {
+                cert_user_id: cert.cert_user_id.clone(),
+            }));
+        };
+        let issuers = match self.cert_signers.get(domain) {
+            Some(issuers) => issuers.clone(),
+            None if is_match(&signers_pattern, domain)? => vec![domain.to_string()],
+            None => {
+                return Ok(Some(Violation::UnknownCertSigner {
+                    domain: domain.to_string(),
+                }))
+            }
+        };
+        if !cert.verify_by(user_address, &issuers) {
+            return Ok(Some(Violation::InvalidCertSign {
+                cert_user_id: cert.cert_user_id.clone(),
+            }));
+        }
+        Ok(None)
+    }
+}
+
+impl PodInternalManifest {
+    /// Rules of `user_contents`, None if manifest has none
+    pub fn user_content_rules(&self) -> Result<Option<UserContentRules>, Error> {
+        match self
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.user_contents.as_ref())
+        {
+            Some(user_contents) => Ok(Some(UserContentRules::from_user_contents(user_contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Address of user owning manifest, the directory holding it
+    pub fn user_address(&self) -> Option<&str> {
+        let inner_path = &self.meta.as_ref()?.pod.as_ref()?.inner_path;
+        let (dir, _) = inner_path.rsplit_once('/')?;
+        let address = dir.rsplit('/').next()?;
+        (!address.is_empty()).then_some(address)
+    }
+
+    /// Whether user manifest stored in user directory is archived by
+    /// `user_contents` of this manifest, judged by the directory and
+    /// `modified`. A manifest whose inner_path names another directory is
+    /// rejected.
+    pub fn is_archived_user(
+        &self,
+        user: &PodInternalManifest,
+        user_dir: &str,
+    ) -> Result<bool, Error> {
+        check_user_dir(user, user_dir)?;
+        let rules = self
+            .user_content_rules()?
+            .ok_or_else(|| Error::missing_field("meta.user_contents"))?;
+        let modified = user
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.pod.as_ref())
+            .map(|pod| pod.modified)
+            .ok_or_else(|| Error::missing_field("meta.pod.modified"))?;
+        Ok(rules.is_archived(user_dir, modified))
+    }
+
+    /// User directories under dir, the directory of this manifest, whose
+    /// manifest is archived and which may be deleted. Directories without
+    /// a user manifest are left alone. User directories hold user data, so
+    /// one whose manifest does not load or whose inner_path names another
+    /// directory is reported as rejected instead of failing the listing.
+    pub fn prune_users(&self, dir: impl AsRef<Path>) -> Result<PruneReport, Error> {
+        let dir = dir.as_ref();
+        // rules of this manifest failing is not the fault of any user
+        self.user_content_rules()?
+            .ok_or_else(|| Error::missing_field("meta.user_contents"))?;
+        let entries = fs::read_dir(dir).map_err(|err| Error::io(dir, err))?;
+        let mut report = PruneReport::default();
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::io(dir, err))?;
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let user_dir = entry.file_name().to_string_lossy().into_owned();
+            let archived = load_user(&path).and_then(|user| match user {
+                Some(user) => self.is_archived_user(&user, &user_dir),
+                None => Ok(false),
+            });
+            match archived {
+                Ok(true) => report.pruned.push(path),
+                Ok(false) => {}
+                Err(err) => report.rejected.push((path, err)),
+            }
+        }
+        report.pruned.sort();
+        report.rejected.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(report)
+    }
+
+    /// Check user manifest stored in user directory, named by the user's
+    /// address, against `user_contents` rules of this manifest, empty if user
+    /// manifest complies. A manifest whose inner_path names another directory
+    /// is rejected. Signs count when valid over the manifest digest or, for
+    /// manifests converted from ZeroNet, over the content.json they export.
+    pub fn validate_user(
+        &self,
+        user: &PodInternalManifest,
+        user_address: &str,
+    ) -> Result<Vec<Violation>, Error> {
+        let rules = self
+            .user_content_rules()?
+            .ok_or_else(|| Error::missing_field("meta.user_contents"))?;
+        check_user_dir(user, user_address)?;
+        let cert = user.meta.as_ref().and_then(|meta| meta.cert.as_ref());
+        let UserRules {
+            banned,
+            rules: user_rules,
+            ..
+        } = rules.rules_for(user_address, cert)?;
+
+        let mut violations = vec![];
+        if banned {
+            violations.push(Violation::Banned {
+                user_address: user_address.to_string(),
+            });
+        }
+        violations.extend(rules.check_cert(user_address, cert)?);
+
+        let empty = Default::default();
+        let file_root = user.files.as_ref().map_or(&empty, |files| &files.file_root);
+        let checks = [
+            (
+                &file_root.files,
+                user_rules.max_size,
+                &user_rules.files_allowed,
+                false,
+            ),
+            (
+                &file_root.files_optional,
+                user_rules.max_size_optional,
+                &user_rules.files_allowed_optional,
+                true,
+            ),
+        ];
+        for (files, max_size, files_allowed, optional) in checks {
+            let size = files.iter().map(|file| file.size).sum();
+            match max_size {
+                Some(max_size) if size > max_size && optional => {
+                    violations.push(Violation::OptionalTooLarge { size, max_size })
+                }
+                Some(max_size) if size > max_size => {
+                    violations.push(Violation::TooLarge { size, max_size })
+                }
+                _ => {}
+            }
+            if let Some(files_allowed) = files_allowed {
+                let allowed = full_match(files_allowed)?;
+                for file in files {
+                    if !is_match(&allowed, &file.path)? {
+                        violations.push(Violation::FileNotAllowed {
+                            path: file.path.clone(),
+                            pattern: files_allowed.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // a manifest converted from content.json keeps signs over ZeroNet's JSON
+        #[cfg(feature = "zeronet")]
+        let content_signers = user.content_signers().unwrap_or_default();
+        #[cfg(not(feature = "zeronet"))]
+        let content_signers: Vec<String> = vec![];
+        let digest = user.digest()?;
+        let valid = user
+            .signatures
+            .iter()
+            .filter(|sign| {
+                user_rules.signers.contains(&sign.address)
+                    && (verify_message(&sign.address, &digest, &sign.sign)
+                        || content_signers.contains(&sign.address))
+            })
+            .count();
+        let required = user_rules.signers_required.unwrap_or(1);
+        if valid < required {
+            violations.push(Violation::NotEnoughSigns { valid, required });
+        }
+        Ok(violations)
+    }
+}
+
+/// Refuse user manifest whose inner_path names another directory than the
+/// one it is stored in, it would be judged as another user
+fn check_user_dir(user: &PodInternalManifest, user_dir: &str) -> Result<(), Error> {
+    match user.user_address() {
+        Some(user_address) if user_address == user_dir => Ok(()),
+        user_address => Err(Error::unauthorized(
+            user_dir,
+            format!(
+                "directory holds manifest of {}",
+                user_address.unwrap_or("no user")
+            ),
+        )),
+    }
+}
+
+/// Manifest of user directory, manifest.toml or else ZeroNet's content.json
+fn load_user(dir: &Path) -> Result<Option<PodInternalManifest>, Error> {
+    let path = dir.join("manifest.toml");
+    if path.is_file() {
+        return load_toml(path).map(Some);
+    }
+    #[cfg(feature = "zeronet")]
+    {
+        use crate::io::IO;
+
+        let path = dir.join("content.json");
+        if path.is_file() {
+            return PodInternalManifest::load_from_path(path).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Pattern matching whole path, as ZeroNet matches `files_allowed`
+fn full_match(files_allowed: &str) -> Result<Option<Regex>, Error> {
+    pattern(Some(&format!("(?:{})$", files_allowed)))
+}
+
+#[cfg(all(test, feature = "zeronet"))]
+mod tests {
+    use std::{fs, path::Path};
+
+    use bitcoin::PrivateKey;
+    use chrono::{DateTime, Utc};
+    use zerucontent::Cert;
+
+    use super::{UserContentRules, Violation};
+    use crate::{
+        cert::{issue_cert, CertExt},
+        crypto::address_from_key,
+        error::Error,
+        file::{PodFile, PodFileRoot},
+        internal::{PodInternalManifest, PodInternalManifestMeta, PodInternalManifestMetaPod},
+        io::IO,
+        manifest::PodManifestFiles,
+        test_utils::new_key,
+    };
+
+    const TALK_USERS: &str = "tests/data/zeronet/talk/data/users";
+    const TMP_USERS: &str = "tests/tmp/permissions/users";
+    /// Directory of user1 of talk users
+    const USER1: &str = "1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7";
+
+    fn cert(cert_user_id: &str) -> Cert {
+        Cert {
+            cert_auth_type: "web".to_string(),
+            cert_sign: String::new(),
+            cert_user_id: cert_user_id.to_string(),
+        }
+    }
+
+    /// Parent of talk users with certs of `zeroid.bit` issued by issuer
+    fn parent_with_issuer(issuer: &PrivateKey) -> PodInternalManifest {
+        let mut parent =
+            PodInternalManifest::load_from_path(format!("{}/content.json", TALK_USERS)).unwrap();
+        let meta = parent.meta.as_mut().unwrap();
+        let mut user_contents = serde_json::to_value(meta.user_contents.as_ref().unwrap()).unwrap();
+        user_contents["cert_signers"] =
+            serde_json::json!({ "zeroid.bit": [address_from_key(issuer)] });
+        meta.user_contents = Some(serde_json::from_value(user_contents).unwrap());
+        parent
+    }
+
+    fn user_manifest(
+        key: &PrivateKey,
+        issuer: &PrivateKey,
+        cert_user_id: &str,
+        files: &[(&str, usize)],
+    ) -> PodInternalManifest {
+        let address = address_from_key(key);
+        let (name, domain) = cert_user_id.split_once('@').unwrap();
+        let cert = issue_cert(issuer, &address, "web", name, domain).unwrap();
+        let file_root = PodFileRoot {
+            files: files
+                .iter()
+                .map(|(path, size)| PodFile {
+                    path: path.to_string(),
+                    size: *size,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut manifest = PodInternalManifest {
+            files: Some(PodManifestFiles {
+                file_root,
+                modified: Utc::now(),
+                ..Default::default()
+            }),
+            signatures: vec![],
+            meta: Some(PodInternalManifestMeta {
+                ignore: None,
+                cert: Some(cert),
+                prev: None,
+                pod: Some(PodInternalManifestMetaPod {
+                    inner_path: format!("data/users/{}/content.json", address),
+                    modified: Utc::now(),
+                    ..Default::default()
+                }),
+                user_contents: None,
+                user: None,
+            }),
+        };
+        manifest.sign(key).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_rules_of_talk_users() {
+        let parent =
+            PodInternalManifest::load_from_path(format!("{}/content.json", TALK_USERS)).unwrap();
+        let rules = parent.user_content_rules().unwrap().unwrap();
+
+        // permission_rules of `.*`
+        let user = rules
+            .rules_for("1User", Some(&cert("nobody@zeroid.bit")))
+            .unwrap();
+        assert!(!user.banned);
+        assert_eq!(user.rules.max_size, Some(50000));
+        assert_eq!(user.rules.files_allowed.as_deref(), Some("data.json"));
+        assert_eq!(user.rules.signers, vec!["1User".to_string()]);
+
+        // larger max_size of permissions wins
+        let user = rules
+            .rules_for("1User", Some(&cert("aim64c@zeroid.bit")))
+            .unwrap();
+        assert_eq!(user.rules.max_size, Some(400000));
+
+        let user = rules
+            .rules_for("1User", Some(&cert("bad@zeroid.bit")))
+            .unwrap();
+        assert!(user.banned);
+        assert!(user.rules.signers.is_empty());
+    }
+
+    #[test]
+    fn test_validate_talk_user() {
+        let parent =
+            PodInternalManifest::load_from_path(format!("{}/content.json", TALK_USERS)).unwrap();
+        let user =
+            PodInternalManifest::load_from_path(format!("{}/user1/content.json", TALK_USERS))
+                .unwrap();
+        assert_eq!(user.user_address(), Some(USER1));
+        // cert of ZeroNet is signed by issuer of zeroid.bit
+        let rules = parent.user_content_rules().unwrap().unwrap();
+        let cert = user.meta.as_ref().unwrap().cert.as_ref().unwrap();
+        assert!(cert.verify(user.user_address().unwrap(), &rules.cert_signers));
+        // signs of converted manifest are checked against ZeroNet's JSON
+        assert_eq!(parent.validate_user(&user, USER1).unwrap(), vec![]);
+        let mut tampered =
+            PodInternalManifest::load_from_path(format!("{}/user1/content.json", TALK_USERS))
+                .unwrap();
+        let pod = tampered.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.modified = Utc::now();
+        assert_eq!(
+            parent.validate_user(&tampered, USER1).unwrap(),
+            vec![Violation::NotEnoughSigns {
+                valid: 0,
+                required: 1
+            }]
+        );
+        // signed manifest of another user copied into a directory
+        let err = parent
+            .validate_user(&user, "12DzGGTAnviALVX4kj9KKfxGrhoNU1xVgK")
+            .unwrap_err();
+        assert!(matches!(err, Error::Unauthorized { .. }));
+    }
+
+    #[test]
+    fn test_validate_violations() {
+        let issuer = new_key();
+        let parent = parent_with_issuer(&issuer);
+        let key = new_key();
+        let address = &address_from_key(&key);
+
+        let user = user_manifest(&key, &issuer, "nobody@zeroid.bit", &[("data.json", 100)]);
+        assert!(parent.validate_user(&user, address).unwrap().is_empty());
+
+        let user = user_manifest(
+            &key,
+            &issuer,
+            "nobody@zeroid.bit",
+            &[("data.json", 50000), ("evil.js", 1)],
+        );
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            vec![
+                Violation::TooLarge {
+                    size: 50001,
+                    max_size: 50000
+                },
+                Violation::FileNotAllowed {
+                    path: "evil.js".to_string(),
+                    pattern: "data.json".to_string()
+                },
+            ]
+        );
+
+        let user = user_manifest(&key, &issuer, "bad@zeroid.bit", &[]);
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            vec![
+                Violation::Banned {
+                    user_address: address_from_key(&key)
+                },
+                Violation::NotEnoughSigns {
+                    valid: 0,
+                    required: 1
+                },
+            ]
+        );
+
+        let user = user_manifest(&key, &issuer, "nobody@example.bit", &[]);
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            vec![Violation::UnknownCertSigner {
+                domain: "example.bit".to_string()
+            }]
+        );
+
+        // cert issued by someone else, or forged
+        let mut user = user_manifest(&key, &new_key(), "nobody@zeroid.bit", &[]);
+        let invalid_cert_sign = vec![Violation::InvalidCertSign {
+            cert_user_id: "nobody@zeroid.bit".to_string(),
+        }];
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            invalid_cert_sign
+        );
+        let meta = user.meta.as_mut().unwrap();
+        meta.cert.as_mut().unwrap().cert_sign = String::new();
+        user.signatures.clear();
+        user.sign(&key).unwrap();
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            invalid_cert_sign
+        );
+
+        // signed by someone else
+        let mut user = user_manifest(&key, &issuer, "nobody@zeroid.bit", &[]);
+        user.signatures.clear();
+        user.sign(&new_key()).unwrap();
+        assert_eq!(
+            parent.validate_user(&user, address).unwrap(),
+            vec![Violation::NotEnoughSigns {
+                valid: 0,
+                required: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_cert_signers_pattern() {
+        let rules = UserContentRules {
+            cert_signers_pattern: Some("(1[A-Za-z0-9]".to_string()),
+            ..Default::default()
+        };
+        // broken pattern is an error, not a cert signer that never matches
+        let err = rules
+            .check_cert("1User", Some(&cert("nobody@example.bit")))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidPattern { .. }));
+    }
+
+    #[test]
+    fn test_archived_talk_users() {
+        let parent =
+            PodInternalManifest::load_from_path(format!("{}/content.json", TALK_USERS)).unwrap();
+        let mut user =
+            PodInternalManifest::load_from_path(format!("{}/user1/content.json", TALK_USERS))
+                .unwrap();
+        assert!(!parent.is_archived_user(&user, USER1).unwrap());
+
+        // modified at or before archived_before
+        let pod = user.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.modified = DateTime::from_timestamp(1523088096, 0).unwrap();
+        assert!(parent.is_archived_user(&user, USER1).unwrap());
+        let pod = user.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.modified = DateTime::from_timestamp(1523088097, 0).unwrap();
+        assert!(!parent.is_archived_user(&user, USER1).unwrap());
+
+        // judged by the directory it is stored in, not the one it claims
+        let archived = "12DzGGTAnviALVX4kj9KKfxGrhoNU1xVgK";
+        let err = parent.is_archived_user(&user, archived).unwrap_err();
+        assert!(matches!(err, Error::Unauthorized { .. }));
+
+        // directory archived for good
+        let pod = user.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.inner_path = format!("data/users/{}/content.json", archived);
+        assert!(parent.is_archived_user(&user, archived).unwrap());
+        let pod = user.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.modified = Utc::now();
+        assert!(parent.is_archived_user(&user, archived).unwrap());
+        assert!(parent.is_archived_user(&user, USER1).is_err());
+    }
+
+    #[test]
+    fn test_prune_users() {
+        let parent =
+            PodInternalManifest::load_from_path(format!("{}/content.json", TALK_USERS)).unwrap();
+        let _ = fs::remove_dir_all(TMP_USERS);
+        let tmp = Path::new(TMP_USERS);
+
+        // converted user claiming an archived directory it is not stored in
+        let mut user =
+            PodInternalManifest::load_from_path(format!("{}/user1/content.json", TALK_USERS))
+                .unwrap();
+        let pod = user.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.inner_path = "data/users/12DzGGTAnviALVX4kj9KKfxGrhoNU1xVgK/content.json".to_string();
+        let kept = tmp.join("1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7");
+        user.save_to_dir(&kept).unwrap();
+        let report = parent.prune_users(tmp).unwrap();
+        assert!(report.pruned.is_empty());
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].0, kept);
+        assert!(matches!(report.rejected[0].1, Error::Unauthorized { .. }));
+
+        // ZeroNet user which is kept
+        fs::remove_dir_all(&kept).unwrap();
+        fs::create_dir_all(&kept).unwrap();
+        fs::copy(
+            format!("{}/user1/content.json", TALK_USERS),
+            kept.join("content.json"),
+        )
+        .unwrap();
+        // converted user whose directory is archived
+        user.save_to_dir(tmp.join("12DzGGTAnviALVX4kj9KKfxGrhoNU1xVgK"))
+            .unwrap();
+        // no manifest at all
+        fs::create_dir_all(tmp.join("empty")).unwrap();
+        // corrupt manifest does not keep others from being pruned
+        let corrupt = tmp.join("1Corrupt");
+        fs::create_dir_all(&corrupt).unwrap();
+        fs::write(corrupt.join("manifest.toml"), "not = [toml").unwrap();
+
+        let report = parent.prune_users(tmp).unwrap();
+        assert_eq!(
+            report.pruned,
+            vec![tmp.join("12DzGGTAnviALVX4kj9KKfxGrhoNU1xVgK")]
+        );
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].0, corrupt);
+        assert!(parent.prune_users(tmp.join("missing")).is_err());
+    }
+}
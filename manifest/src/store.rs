@@ -0,0 +1,659 @@
+//! Local versioned store of a pod under `.decentnet/objects`, keeping every
+//! committed manifest.toml, files.toml and file by hash, so that any committed
+//! version can be checked out again. Manifests are stored by digest, the hash
+//! `meta.prev` links them by, and files by their listed hash. `HEAD` names the
+//! latest committed version, and each commit has to continue its chain.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    error::Error,
+    file::{check_path, PodFile, PodFileRoot, LOCAL_DIR},
+    hash::FileHash,
+    history::History,
+    io::{copy_file, load_toml, write_file},
+    manifest::{PodManifest, SignatureScheme},
+    verify::{FileReport, FileStatus},
+};
+
+/// Version listed by `PodStore::log`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Digest of manifest
+    pub hash: String,
+    /// Last modified time of manifest
+    pub modified: Option<DateTime<Utc>>,
+    /// Addresses of signers, the group key under FROST
+    pub signers: Vec<String>,
+}
+
+/// Object store of pod directory
+#[derive(Debug, Clone)]
+pub struct PodStore {
+    root: PathBuf,
+}
+
+impl PodStore {
+    /// Open store of pod directory, creating it if absent
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let store = PodStore {
+            root: root.as_ref().to_path_buf(),
+        };
+        let objects = store.objects();
+        fs::create_dir_all(&objects).map_err(|err| Error::io(&objects, err))?;
+        Ok(store)
+    }
+
+    fn objects(&self) -> PathBuf {
+        self.root.join(LOCAL_DIR).join("objects")
+    }
+
+    /// Path of object, refusing anything but a manifest digest or file hash,
+    /// as hashes from HEAD, `meta.prev` or the caller must not leave the store
+    fn object_path(&self, hash: &str) -> Result<PathBuf, Error> {
+        let invalid = |message: &str| Error::InvalidHash {
+            hash: hash.to_string(),
+            message: message.to_string(),
+        };
+        if !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid("expected a single path component"));
+        }
+        if FileHash::from_str(hash)?.is_empty() {
+            return Err(invalid("expected a non-empty hash"));
+        }
+        Ok(self.objects().join(hash))
+    }
+
+    fn head_path(&self) -> PathBuf {
+        self.root.join(LOCAL_DIR).join("HEAD")
+    }
+
+    /// Digest of latest committed manifest, None before first commit
+    pub fn head(&self) -> Result<Option<String>, Error> {
+        let path = self.head_path();
+        match fs::read_to_string(&path) {
+            Ok(head) => Ok(Some(head.trim().to_string())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::io(&path, err)),
+        }
+    }
+
+    /// Whether object of hash is stored, never for a malformed hash
+    pub fn contains(&self, hash: &str) -> bool {
+        self.object_path(hash).is_ok_and(|path| path.is_file())
+    }
+
+    /// Committed manifest of digest
+    pub fn manifest(&self, hash: &str) -> Result<PodManifest, Error> {
+        let path = self.object_path(hash)?;
+        if !path.is_file() {
+            return Err(store_error(format!("version {} is not stored", hash)));
+        }
+        load_toml(path)
+    }
+
+    /// files.toml of committed manifest, None if it lists no files
+    fn file_root(&self, manifest: &PodManifest) -> Result<Option<PodFileRoot>, Error> {
+        let Some(files) = &manifest.files else {
+            return Ok(None);
+        };
+        let hash = files.hash.to_string();
+        if !self.contains(&hash) {
+            return Err(store_error(format!(
+                "{} {} is not stored",
+                files.manifest, hash
+            )));
+        }
+        load_toml(self.object_path(&hash)?).map(Some)
+    }
+
+    /// Copy file into store, unless already stored
+    fn put(&self, hash: &str, path: &Path) -> Result<(), Error> {
+        if !self.contains(hash) {
+            copy_file(path, self.object_path(hash)?)?;
+        }
+        Ok(())
+    }
+
+    /// Store manifest.toml of pod directory along with its files.toml and
+    /// files, then move HEAD to it. Manifest must be validly signed and
+    /// continue the chain from HEAD, files must match their listing while
+    /// optional files may be missing. Returns digest of manifest.
+    pub fn commit(&self) -> Result<String, Error> {
+        let path = self.root.join("manifest.toml");
+        let manifest: PodManifest = load_toml(&path)?;
+        if !manifest.verify()?.is_valid() {
+            return Err(store_error("manifest.toml is not validly signed"));
+        }
+        let hash = manifest.digest()?;
+        if let Some(head) = self.head()? {
+            if let Some(chain_break) = self.manifest(&head)?.check_update(&manifest)? {
+                return Err(store_error(format!(
+                    "{} does not continue {}: {}",
+                    hash, head, chain_break
+                )));
+            }
+        }
+
+        if let Some(files) = &manifest.files {
+            check_path(&files.manifest)?;
+            let files_path = self.root.join(&files.manifest);
+            let report = FileReport {
+                path: files.manifest.clone(),
+                optional: false,
+                status: files.verify(&self.root)?,
+            };
+            if !report.is_ok() {
+                return Err(drift_error(&report));
+            }
+            let file_root: PodFileRoot = load_toml(&files_path)?;
+            file_root.check_paths()?;
+            let listed = file_root.files.iter().map(|file| (file, false));
+            let listed_optional = file_root.files_optional.iter().map(|file| (file, true));
+            let mut stored = vec![];
+            for (file, optional) in listed.chain(listed_optional) {
+                let report = FileReport {
+                    path: file.path.clone(),
+                    optional,
+                    status: file.verify(&self.root)?,
+                };
+                match report.is_ok() {
+                    true if self.root.join(&file.path).is_file() => stored.push(file),
+                    true => {}
+                    false => return Err(drift_error(&report)),
+                }
+            }
+            for file in stored {
+                self.put(&file.hash.to_string(), &self.root.join(&file.path))?;
+            }
+            self.put(&files.hash.to_string(), &files_path)?;
+        }
+        // signatures may have been added since the same digest was committed
+        copy_file(&path, self.object_path(&hash)?)?;
+        write_file(self.head_path(), hash.as_bytes())?;
+        Ok(hash)
+    }
+
+    /// Committed versions, latest first, following `meta.prev` from HEAD
+    /// until a version which is not stored
+    pub fn log(&self) -> Result<Vec<LogEntry>, Error> {
+        let mut entries = vec![];
+        let mut next = self.head()?;
+        while let Some(hash) = next.filter(|hash| self.contains(hash)) {
+            let manifest = self.manifest(&hash)?;
+            next = manifest.prev().map(|prev| prev.hash.clone());
+            let signers = match manifest.signature.scheme {
+                SignatureScheme::Frost => vec![manifest.signature.primary.clone()],
+                SignatureScheme::Bitcoin => manifest
+                    .signatures
+                    .iter()
+                    .map(|sign| sign.address.clone())
+                    .collect(),
+            };
+            entries.push(LogEntry {
+                hash,
+                modified: manifest.modified(),
+                signers,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Restore manifest.toml, files.toml and files of committed version into
+    /// pod directory, removing files listed by the version checked out before.
+    /// Unlisted files are left alone and HEAD stays at the latest version, so
+    /// the restored state is published again by signing it as update of HEAD.
+    /// Paths leaving the pod directory in either file list are refused, and
+    /// unless forced, so is discarding work not committed yet. Stored objects
+    /// are verified against their listing before any file is replaced.
+    pub fn checkout(&self, hash: &str, force: bool) -> Result<(), Error> {
+        let manifest = self.manifest(hash)?;
+        if manifest.digest()? != hash {
+            return Err(store_error(format!("version {} is corrupted", hash)));
+        }
+        if let Some(files) = &manifest.files {
+            check_path(&files.manifest)?;
+        }
+        let file_root = self.file_root(&manifest)?.unwrap_or_default();
+        file_root.check_paths()?;
+        let listed = file_root.files.iter().map(|file| (file, false));
+        let listed_optional = file_root.files_optional.iter().map(|file| (file, true));
+        let mut restore = vec![];
+        for (file, optional) in listed.chain(listed_optional) {
+            let object = file.hash.to_string();
+            match self.contains(&object) {
+                true => restore.push((file, object, optional)),
+                false if optional => {}
+                false => {
+                    return Err(store_error(format!(
+                        "{} {} is not stored",
+                        file.path, object
+                    )));
+                }
+            }
+        }
+        let current = self.current_files()?;
+
+        let staging = self.root.join(LOCAL_DIR).join("checkout");
+        remove_dir(&staging)?;
+        let result = self
+            .stage(&manifest, &restore, &staging)
+            .and_then(|_| match force {
+                true => Ok(()),
+                false => self.check_committed(&manifest, &restore, &current),
+            });
+        if let Err(err) = result {
+            remove_dir(&staging)?;
+            return Err(err);
+        }
+
+        let target: BTreeSet<&str> = restore
+            .iter()
+            .map(|(file, _, _)| file.path.as_str())
+            .chain(manifest.files.iter().map(|files| files.manifest.as_str()))
+            .collect();
+        for path in current
+            .iter()
+            .filter(|path| !target.contains(path.as_str()))
+        {
+            let path = self.root.join(path);
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(Error::io(&path, err));
+                }
+                _ => {}
+            }
+        }
+        for path in target.iter().copied().chain(["manifest.toml"]) {
+            let to = self.root.join(path);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+            }
+            fs::rename(staging.join(path), &to).map_err(|err| Error::io(&to, err))?;
+        }
+        remove_dir(&staging)
+    }
+
+    /// Copy objects of version into staging laid out as pod directory,
+    /// each verified against its listing
+    fn stage(
+        &self,
+        manifest: &PodManifest,
+        restore: &[(&PodFile, String, bool)],
+        staging: &Path,
+    ) -> Result<(), Error> {
+        for (file, object, optional) in restore {
+            copy_file(self.object_path(object)?, staging.join(&file.path))?;
+            let report = FileReport {
+                path: file.path.clone(),
+                optional: *optional,
+                status: file.verify(staging)?,
+            };
+            if report.status != FileStatus::Ok {
+                return Err(corrupted_error(&report));
+            }
+        }
+        if let Some(files) = &manifest.files {
+            let object = self.object_path(&files.hash.to_string())?;
+            copy_file(object, staging.join(&files.manifest))?;
+            let report = FileReport {
+                path: files.manifest.clone(),
+                optional: false,
+                status: files.verify(staging)?,
+            };
+            if report.status != FileStatus::Ok {
+                return Err(corrupted_error(&report));
+            }
+        }
+        let hash = manifest.digest()?;
+        copy_file(self.object_path(&hash)?, staging.join("manifest.toml"))?;
+        Ok(())
+    }
+
+    /// Refuse checkout replacing or removing files of pod directory whose
+    /// content is not committed: files of a manifest.toml not stored, files
+    /// differing from their listing and unlisted files in the way of restored ones
+    fn check_committed(
+        &self,
+        manifest: &PodManifest,
+        restore: &[(&PodFile, String, bool)],
+        current: &[String],
+    ) -> Result<(), Error> {
+        let mut changed = vec![];
+        let path = self.root.join("manifest.toml");
+        if path.is_file() {
+            let current_manifest: PodManifest = load_toml(&path)?;
+            if !self.contains(&current_manifest.digest()?) {
+                changed.push("manifest.toml".to_string());
+            }
+            let report = current_manifest.verify_dir(&self.root)?;
+            changed.extend(
+                report
+                    .files_manifest
+                    .iter()
+                    .chain(report.files.iter())
+                    .filter(|report| {
+                        !matches!(
+                            report.status,
+                            FileStatus::Ok | FileStatus::Missing | FileStatus::Extra
+                        )
+                    })
+                    .map(|report| report.path.clone()),
+            );
+        }
+        for (file, _, _) in restore {
+            if current.contains(&file.path) {
+                continue;
+            }
+            if !matches!(
+                file.verify(&self.root)?,
+                FileStatus::Ok | FileStatus::Missing
+            ) {
+                changed.push(file.path.clone());
+            }
+        }
+        if let Some(files) = manifest
+            .files
+            .as_ref()
+            .filter(|files| !current.contains(&files.manifest))
+        {
+            if !matches!(
+                files.verify(&self.root)?,
+                FileStatus::Ok | FileStatus::Missing
+            ) {
+                changed.push(files.manifest.clone());
+            }
+        }
+        if changed.is_empty() {
+            return Ok(());
+        }
+        Err(store_error(format!(
+            "{} changed since last commit, commit first or force checkout",
+            changed.join(", ")
+        )))
+    }
+
+    /// Paths listed by manifest.toml and files.toml in pod directory,
+    /// including files.toml itself
+    fn current_files(&self) -> Result<Vec<String>, Error> {
+        let path = self.root.join("manifest.toml");
+        if !path.is_file() {
+            return Ok(vec![]);
+        }
+        let manifest: PodManifest = load_toml(&path)?;
+        let Some(files) = &manifest.files else {
+            return Ok(vec![]);
+        };
+        check_path(&files.manifest)?;
+        let files_path = self.root.join(&files.manifest);
+        let mut paths = vec![files.manifest.clone()];
+        if files_path.is_file() {
+            let file_root: PodFileRoot = load_toml(&files_path)?;
+            file_root.check_paths()?;
+            paths.extend(
+                file_root
+                    .files
+                    .iter()
+                    .chain(file_root.files_optional.iter())
+                    .map(|file| file.path.clone()),
+            );
+        }
+        Ok(paths)
+    }
+
+    /// Remove objects not used by the latest `keep` versions of log,
+    /// or by any version of log if None. Returns hashes of removed objects.
+    pub fn gc(&self, keep: Option<usize>) -> Result<Vec<String>, Error> {
+        let keep = keep.map_or(usize::MAX, |keep| keep.max(1));
+        let mut live = BTreeSet::new();
+        for entry in self.log()?.into_iter().take(keep) {
+            let manifest = self.manifest(&entry.hash)?;
+            if let Some(files) = &manifest.files {
+                live.insert(files.hash.to_string());
+            }
+            if let Some(file_root) = self.file_root(&manifest)? {
+                live.extend(
+                    file_root
+                        .files
+                        .iter()
+                        .chain(file_root.files_optional.iter())
+                        .map(|file| file.hash.to_string()),
+                );
+            }
+            live.insert(entry.hash);
+        }
+
+        let objects = self.objects();
+        let entries = fs::read_dir(&objects).map_err(|err| Error::io(&objects, err))?;
+        let mut removed = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::io(&objects, err))?;
+            let hash = entry.file_name().to_string_lossy().to_string();
+            if !live.contains(&hash) {
+                fs::remove_file(entry.path()).map_err(|err| Error::io(entry.path(), err))?;
+                removed.push(hash);
+            }
+        }
+        removed.sort();
+        Ok(removed)
+    }
+}
+
+fn store_error(reason: impl Into<String>) -> Error {
+    Error::Store(reason.into())
+}
+
+fn drift_error(report: &FileReport) -> Error {
+    store_error(format!("{} is {:?}", report.path, report.status))
+}
+
+fn corrupted_error(report: &FileReport) -> Error {
+    store_error(format!(
+        "stored {} is corrupted, {:?}",
+        report.path, report.status
+    ))
+}
+
+fn remove_dir(path: &Path) -> Result<(), Error> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(Error::io(path, err)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::PodStore;
+    use crate::{
+        crypto::Signer,
+        error::Error,
+        file::PodFileRoot,
+        io::{load_toml, save_toml, write_file},
+        manifest::PodManifest,
+        test_utils::{new_key, pod_dir, publish},
+    };
+
+    const TMP_STORE: &str = "tests/tmp/store";
+
+    #[test]
+    fn test_commit_log_checkout() {
+        let dir = &pod_dir(TMP_STORE, "checkout");
+        let key = new_key();
+        write_file(dir.join("index.html"), b"v1").unwrap();
+        write_file(dir.join("optional/big.bin"), b"big").unwrap();
+        let v1 = publish(dir, &key, 0, None);
+        let store = PodStore::open(dir).unwrap();
+        let hash1 = store.commit().unwrap();
+        assert_eq!(hash1, v1.digest().unwrap());
+        // committing again is a no-op, and the store is not listed
+        assert_eq!(store.commit().unwrap(), hash1);
+        assert!(v1.verify_dir(dir).unwrap().is_ok());
+
+        write_file(dir.join("index.html"), b"v2").unwrap();
+        write_file(dir.join("new.js"), b"js").unwrap();
+        fs::remove_file(dir.join("optional/big.bin")).unwrap();
+        let v2 = publish(dir, &key, 1, Some(&v1));
+        let hash2 = store.commit().unwrap();
+        let log = store.log().unwrap();
+        assert_eq!(
+            log.iter()
+                .map(|entry| entry.hash.as_str())
+                .collect::<Vec<_>>(),
+            vec![hash2.as_str(), hash1.as_str()]
+        );
+        assert_eq!(log[0].signers, vec![key.address()]);
+
+        store.checkout(&hash1, false).unwrap();
+        assert_eq!(fs::read(dir.join("index.html")).unwrap(), b"v1");
+        assert_eq!(fs::read(dir.join("optional/big.bin")).unwrap(), b"big");
+        assert!(!dir.join("new.js").exists());
+        let manifest: PodManifest = load_toml(dir.join("manifest.toml")).unwrap();
+        assert_eq!(manifest.digest().unwrap(), hash1);
+        assert!(manifest.verify_dir(dir).unwrap().is_ok());
+        assert_eq!(store.head().unwrap(), Some(hash2.clone()));
+
+        // checked out version is published again as update of HEAD
+        publish(dir, &key, 2, Some(&v2));
+        let hash3 = store.commit().unwrap();
+        assert_eq!(store.log().unwrap().len(), 3);
+        assert_eq!(store.head().unwrap(), Some(hash3));
+        assert!(store.checkout("missing", false).is_err());
+    }
+
+    #[test]
+    fn test_commit_rejects() {
+        let dir = &pod_dir(TMP_STORE, "rejects");
+        let key = new_key();
+        write_file(dir.join("index.html"), b"v1").unwrap();
+        let v1 = publish(dir, &key, 0, None);
+        let store = PodStore::open(dir).unwrap();
+        store.commit().unwrap();
+        let v2 = publish(dir, &key, 1, Some(&v1));
+        store.commit().unwrap();
+
+        // validly signed, but forked from v1
+        publish(dir, &key, 2, Some(&v1));
+        assert!(store.commit().is_err());
+        // older version again
+        save_toml(&v1, dir.join("manifest.toml")).unwrap();
+        assert!(store.commit().is_err());
+        // not signed by signers
+        let mut unsigned = publish(dir, &key, 2, Some(&v2));
+        unsigned.signatures.clear();
+        save_toml(&unsigned, dir.join("manifest.toml")).unwrap();
+        assert!(store.commit().is_err());
+        // file changed after signing
+        publish(dir, &key, 2, Some(&v2));
+        write_file(dir.join("index.html"), b"v3").unwrap();
+        assert!(store.commit().is_err());
+        assert_eq!(store.log().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_gc() {
+        let dir = &pod_dir(TMP_STORE, "gc");
+        let key = new_key();
+        write_file(dir.join("index.html"), b"v1").unwrap();
+        write_file(dir.join("style.css"), b"css").unwrap();
+        let v1 = publish(dir, &key, 0, None);
+        let store = PodStore::open(dir).unwrap();
+        let hash1 = store.commit().unwrap();
+        write_file(dir.join("index.html"), b"v2").unwrap();
+        publish(dir, &key, 1, Some(&v1));
+        let hash2 = store.commit().unwrap();
+
+        assert!(store.gc(None).unwrap().is_empty());
+        let removed = store.gc(Some(1)).unwrap();
+        // manifest, files.toml and index.html of v1, style.css is shared
+        assert_eq!(removed.len(), 3);
+        assert!(removed.contains(&hash1));
+        assert_eq!(store.log().unwrap().len(), 1);
+        assert!(store.checkout(&hash1, false).is_err());
+        store.checkout(&hash2, false).unwrap();
+        assert_eq!(fs::read(dir.join("style.css")).unwrap(), b"css");
+    }
+
+    #[test]
+    fn test_checkout_rejects_escaping_paths() {
+        let dir = &pod_dir(TMP_STORE, "escape/pod");
+        let key = new_key();
+        write_file(dir.join("index.html"), b"v1").unwrap();
+        publish(dir, &key, 0, None);
+        let store = PodStore::open(dir).unwrap();
+        let hash = store.commit().unwrap();
+
+        // files.toml of pod, e.g. from an applied bundle, listing a file outside it
+        let victim = Path::new(TMP_STORE).join("escape/victim.txt");
+        write_file(&victim, b"keep").unwrap();
+        let mut file_root: PodFileRoot = load_toml(dir.join("files.toml")).unwrap();
+        file_root.files[0].path = "../victim.txt".to_string();
+        save_toml(&file_root, dir.join("files.toml")).unwrap();
+        let err = store.checkout(&hash, false).unwrap_err();
+        assert!(matches!(err, Error::InvalidPath { .. }));
+        assert!(victim.exists());
+
+        // hashes are never joined into a path outside the store
+        let err = store.checkout("../../victim.txt", false).unwrap_err();
+        assert!(matches!(err, Error::InvalidHash { .. }));
+        assert!(!store.contains("../../victim.txt"));
+    }
+
+    #[test]
+    fn test_checkout_keeps_uncommitted_work() {
+        let dir = &pod_dir(TMP_STORE, "uncommitted");
+        let key = new_key();
+        write_file(dir.join("index.html"), b"v1").unwrap();
+        write_file(dir.join("old.js"), b"js").unwrap();
+        let v1 = publish(dir, &key, 0, None);
+        let store = PodStore::open(dir).unwrap();
+        let hash1 = store.commit().unwrap();
+        write_file(dir.join("index.html"), b"v2").unwrap();
+        fs::remove_file(dir.join("old.js")).unwrap();
+        let v2 = publish(dir, &key, 1, Some(&v1));
+        let v2_root: PodFileRoot = load_toml(dir.join("files.toml")).unwrap();
+        let hash2 = store.commit().unwrap();
+
+        // edited since last commit
+        write_file(dir.join("index.html"), b"draft").unwrap();
+        let err = store.checkout(&hash1, false).unwrap_err();
+        assert!(matches!(err, Error::Store(_)));
+        assert_eq!(fs::read(dir.join("index.html")).unwrap(), b"draft");
+
+        // published but not committed
+        let draft = publish(dir, &key, 2, Some(&v2));
+        assert!(store.checkout(&hash1, false).is_err());
+        let manifest: PodManifest = load_toml(dir.join("manifest.toml")).unwrap();
+        assert_eq!(manifest, draft);
+        store.checkout(&hash2, true).unwrap();
+        assert_eq!(fs::read(dir.join("index.html")).unwrap(), b"v2");
+
+        // untracked file in the way of a restored one
+        write_file(dir.join("old.js"), b"mine").unwrap();
+        assert!(store.checkout(&hash1, false).is_err());
+        assert_eq!(fs::read(dir.join("old.js")).unwrap(), b"mine");
+        store.checkout(&hash1, true).unwrap();
+        assert_eq!(fs::read(dir.join("old.js")).unwrap(), b"js");
+
+        // corrupted object is not restored
+        let object = dir
+            .join(".decentnet/objects")
+            .join(v2_root.files[0].hash.to_string());
+        write_file(&object, b"v3").unwrap();
+        let err = store.checkout(&hash2, false).unwrap_err();
+        assert!(matches!(err, Error::Store(_)));
+        assert_eq!(fs::read(dir.join("index.html")).unwrap(), b"v1");
+        let manifest: PodManifest = load_toml(dir.join("manifest.toml")).unwrap();
+        assert_eq!(manifest.digest().unwrap(), hash1);
+        assert!(!dir.join(".decentnet/checkout").exists());
+    }
+}
@@ -0,0 +1,92 @@
+//! Helpers shared by unit tests
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bitcoin::{
+    secp256k1::{rand, SecretKey},
+    Network, PrivateKey,
+};
+use chrono::{Duration, Utc};
+
+use crate::{
+    crypto::Signer,
+    file::{FromDirOptions, PodFileRoot},
+    hash::HashAlgorithm,
+    io::save_toml,
+    manifest::{
+        PodManifest, PodManifestFiles, PodManifestMeta, PodManifestMetaPod, PodManifestSignature,
+    },
+};
+
+/// Fresh random key on mainnet
+pub(crate) fn new_key() -> PrivateKey {
+    PrivateKey::new(SecretKey::new(&mut rand::thread_rng()), Network::Bitcoin)
+}
+
+/// Empty pod directory of name under tmp directory
+pub(crate) fn pod_dir(tmp: &str, name: &str) -> PathBuf {
+    let dir = Path::new(tmp).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write manifest.toml and files.toml for files in pod directory, those under
+/// optional/ as optional, signed as update of previous manifest if any
+pub(crate) fn publish(
+    dir: &Path,
+    key: &PrivateKey,
+    seconds: i64,
+    prev: Option<&PodManifest>,
+) -> PodManifest {
+    let options = FromDirOptions {
+        optional: Some("optional/".to_string()),
+        ..Default::default()
+    };
+    let file_root = PodFileRoot::from_dir(dir, &options).unwrap();
+    publish_root(dir, key, seconds, prev, file_root)
+}
+
+/// Write manifest.toml and files.toml listing file root, signed as update
+/// of previous manifest if any
+pub(crate) fn publish_root(
+    dir: &Path,
+    key: &PrivateKey,
+    seconds: i64,
+    prev: Option<&PodManifest>,
+    file_root: PodFileRoot,
+) -> PodManifest {
+    let modified = Utc::now() + Duration::seconds(seconds);
+    let files = PodManifestFiles::new(file_root, modified, HashAlgorithm::Blake3).unwrap();
+    files.save(dir).unwrap();
+    let mut manifest = PodManifest {
+        files: Some(files),
+        signature: PodManifestSignature {
+            primary: key.address(),
+            signs_required: 1,
+            signers: vec![key.address()],
+            ..Default::default()
+        },
+        meta: Some(PodManifestMeta {
+            ignore: None,
+            prev: None,
+            client: None,
+            pod: Some(PodManifestMetaPod {
+                address: key.address(),
+                modified,
+                ..Default::default()
+            }),
+        }),
+        ..Default::default()
+    };
+    manifest.sign_root(key).unwrap();
+    match prev {
+        Some(prev) => manifest.sign_update(prev, key).unwrap(),
+        None => manifest.sign(key).unwrap(),
+    }
+    save_toml(&manifest, dir.join("manifest.toml")).unwrap();
+    manifest
+}
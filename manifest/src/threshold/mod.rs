@@ -0,0 +1,297 @@
+//! FROST threshold signing of manifests, where any `signs_required` of the
+//! group's key share holders co-sign a single signature over the digest.
+
+pub mod recovery;
+pub mod rotation;
+pub mod session;
+
+use std::collections::BTreeMap;
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use frost_ristretto255 as frost;
+use rand::{CryptoRng, RngCore};
+
+pub use frost::{
+    keys::{KeyPackage, PublicKeyPackage, SecretShare},
+    round1::{SigningCommitments, SigningNonces},
+    round2::SignatureShare,
+    Identifier, Signature, SigningPackage, VerifyingKey,
+};
+
+use crate::{
+    error::Error,
+    manifest::{PodManifest, PodManifestSignature, SignatureScheme},
+};
+
+/// Hex encoded group verifying key, stored as `primary`
+pub fn verifying_key_to_hex(key: &VerifyingKey) -> String {
+    key.serialize().to_lower_hex_string()
+}
+
+pub fn verifying_key_from_hex(hex: &str) -> Result<VerifyingKey, Error> {
+    let bytes = <[u8; 32]>::from_hex(hex)
+        .map_err(|err| Error::Sign(format!("invalid verifying key `{}`: {}", hex, err)))?;
+    Ok(VerifyingKey::deserialize(bytes)?)
+}
+
+/// Hex encoded aggregated signature, stored as `root_sign`
+pub fn signature_to_hex(signature: &Signature) -> String {
+    signature.serialize().to_lower_hex_string()
+}
+
+pub fn signature_from_hex(hex: &str) -> Result<Signature, Error> {
+    let bytes = <[u8; 64]>::from_hex(hex)
+        .map_err(|err| Error::Sign(format!("invalid signature `{}`: {}", hex, err)))?;
+    Ok(Signature::deserialize(bytes)?)
+}
+
+/// Hex encoded participant identifier, stored in `signers`
+pub fn identifier_to_hex(identifier: &Identifier) -> String {
+    identifier.serialize().to_lower_hex_string()
+}
+
+pub fn identifier_from_hex(hex: &str) -> Result<Identifier, Error> {
+    let bytes = <[u8; 32]>::from_hex(hex)
+        .map_err(|err| Error::Sign(format!("invalid identifier `{}`: {}", hex, err)))?;
+    Ok(Identifier::deserialize(&bytes)?)
+}
+
+/// Hex encoded verifying shares of participants by identifier, stored as
+/// `signer_keys`
+pub fn signer_keys(pubkeys: &PublicKeyPackage) -> BTreeMap<String, String> {
+    pubkeys
+        .verifying_shares()
+        .iter()
+        .map(|(identifier, share)| {
+            (
+                identifier_to_hex(identifier),
+                share.serialize().to_lower_hex_string(),
+            )
+        })
+        .collect()
+}
+
+/// Round 1 of a participant, nonces stay with participant while
+/// commitments are sent to the coordinator
+pub fn commit<R: RngCore + CryptoRng>(
+    key_package: &KeyPackage,
+    rng: &mut R,
+) -> (SigningNonces, SigningCommitments) {
+    frost::round1::commit(key_package.signing_share(), rng)
+}
+
+impl PodManifestSignature {
+    /// Threshold signature of group, `root_sign` is empty until aggregated
+    pub fn frost(pubkeys: &PublicKeyPackage, signs_required: u16) -> Self {
+        PodManifestSignature {
+            scheme: SignatureScheme::Frost,
+            primary: verifying_key_to_hex(pubkeys.verifying_key()),
+            root_sign: String::new(),
+            signs_required: signs_required as usize,
+            signers: pubkeys
+                .verifying_shares()
+                .keys()
+                .map(identifier_to_hex)
+                .collect(),
+            signer_keys: signer_keys(pubkeys),
+            rotations: vec![],
+        }
+    }
+
+    /// Group verifying key held in `primary`
+    pub fn verifying_key(&self) -> Result<VerifyingKey, Error> {
+        self.require_frost()?;
+        verifying_key_from_hex(&self.primary)
+    }
+
+    /// Whether `root_sign` is a valid group signature of message
+    pub fn verify_frost(&self, message: &[u8]) -> bool {
+        let (Ok(key), Ok(signature)) = (self.verifying_key(), signature_from_hex(&self.root_sign))
+        else {
+            return false;
+        };
+        key.verify(message, &signature).is_ok()
+    }
+
+    fn require_frost(&self) -> Result<(), Error> {
+        match self.scheme {
+            SignatureScheme::Frost => Ok(()),
+            SignatureScheme::Bitcoin => Err(Error::Sign(
+                "manifest is not signed with FROST scheme".to_string(),
+            )),
+        }
+    }
+}
+
+impl PodManifestSignature {
+    /// Signing package binding commitments of participants to message,
+    /// built once at least `signs_required` committed
+    pub(crate) fn signing_package(
+        &self,
+        commitments: BTreeMap<Identifier, SigningCommitments>,
+        message: &[u8],
+    ) -> Result<SigningPackage, Error> {
+        self.require_frost()?;
+        if commitments.len() < self.signs_required {
+            return Err(Error::Sign(format!(
+                "{} commitments received, {} required",
+                commitments.len(),
+                self.signs_required
+            )));
+        }
+        Ok(SigningPackage::new(commitments, message))
+    }
+
+    /// Signature share of participant over message of signing package
+    pub(crate) fn sign_share(
+        &self,
+        signing_package: &SigningPackage,
+        message: &[u8],
+        nonces: &SigningNonces,
+        key_package: &KeyPackage,
+    ) -> Result<SignatureShare, Error> {
+        check_message(signing_package, message)?;
+        let identifier = identifier_to_hex(key_package.identifier());
+        if key_package.verifying_key() != &self.verifying_key()? {
+            return Err(Error::unauthorized(
+                identifier,
+                "key share of another group",
+            ));
+        }
+        // signs_required is only advertised, the key share knows the real threshold
+        let min_signers = *key_package.min_signers() as usize;
+        if self.signs_required != min_signers {
+            return Err(Error::unauthorized(
+                identifier,
+                format!(
+                    "signs_required {} differs from threshold {} of key share",
+                    self.signs_required, min_signers
+                ),
+            ));
+        }
+        if !signing_package
+            .signing_commitments()
+            .contains_key(key_package.identifier())
+        {
+            return Err(Error::unauthorized(
+                identifier,
+                "no commitment in signing package",
+            ));
+        }
+        Ok(frost::round2::sign(signing_package, nonces, key_package)?)
+    }
+
+    /// Group signature of message from signature shares, verified against group key
+    pub(crate) fn aggregate(
+        &self,
+        signing_package: &SigningPackage,
+        message: &[u8],
+        signature_shares: &BTreeMap<Identifier, SignatureShare>,
+        pubkeys: &PublicKeyPackage,
+    ) -> Result<Signature, Error> {
+        check_message(signing_package, message)?;
+        if pubkeys.verifying_key() != &self.verifying_key()? {
+            return Err(Error::Sign(
+                "public key package belongs to another group".to_string(),
+            ));
+        }
+        let signature = frost::aggregate(signing_package, signature_shares, pubkeys)?;
+        pubkeys.verifying_key().verify(message, &signature)?;
+        Ok(signature)
+    }
+}
+
+fn check_message(signing_package: &SigningPackage, message: &[u8]) -> Result<(), Error> {
+    if signing_package.message().as_slice() != message {
+        return Err(Error::Sign(
+            "signing package is bound to another message".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl PodManifest {
+    /// Signing package binding commitments of participants to manifest digest,
+    /// built by the coordinator once at least `signs_required` committed
+    pub fn frost_signing_package(
+        &self,
+        commitments: BTreeMap<Identifier, SigningCommitments>,
+    ) -> Result<SigningPackage, Error> {
+        self.signature
+            .signing_package(commitments, self.digest()?.as_bytes())
+    }
+
+    /// Round 2 of a participant, signature share over signing package
+    /// after checking it targets this manifest and group, and that
+    /// `signs_required` is the threshold of the group
+    pub fn frost_sign(
+        &self,
+        signing_package: &SigningPackage,
+        nonces: &SigningNonces,
+        key_package: &KeyPackage,
+    ) -> Result<SignatureShare, Error> {
+        let digest = self.digest()?;
+        self.signature
+            .sign_share(signing_package, digest.as_bytes(), nonces, key_package)
+    }
+
+    /// Aggregate signature shares into `root_sign`, verifying it against group key
+    pub fn frost_aggregate(
+        &mut self,
+        signing_package: &SigningPackage,
+        signature_shares: &BTreeMap<Identifier, SignatureShare>,
+        pubkeys: &PublicKeyPackage,
+    ) -> Result<(), Error> {
+        let digest = self.digest()?;
+        let signature = self.signature.aggregate(
+            signing_package,
+            digest.as_bytes(),
+            signature_shares,
+            pubkeys,
+        )?;
+        self.signature.root_sign = signature_to_hex(&signature);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manifest::{PodManifest, PodManifestSignature, SignatureScheme};
+
+    #[test]
+    fn test_digest_excludes_frost_root_sign() {
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                scheme: SignatureScheme::Frost,
+                primary: "00".repeat(32),
+                signs_required: 3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let digest = manifest.digest().unwrap();
+        manifest.signature.root_sign = "11".repeat(64);
+        assert_eq!(manifest.digest().unwrap(), digest);
+
+        // root_sign is covered under bitcoin scheme
+        manifest.signature.scheme = SignatureScheme::Bitcoin;
+        assert_ne!(manifest.digest().unwrap(), digest);
+    }
+
+    #[test]
+    fn test_scheme_serde() {
+        let manifest = PodManifest {
+            signature: PodManifestSignature {
+                scheme: SignatureScheme::Frost,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let content = toml::to_string(&manifest).unwrap();
+        assert!(content.contains("scheme = \"frost\""));
+        assert_eq!(PodManifest::from_string(&content).unwrap(), manifest);
+
+        let content = toml::to_string(&PodManifest::default()).unwrap();
+        assert!(!content.contains("scheme"));
+    }
+}
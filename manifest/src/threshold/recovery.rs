@@ -0,0 +1,230 @@
+//! Recovery of a lost key share with help of other signers, without
+//! revealing their shares. Each helper writes a delta file for every
+//! other helper, each helper sums deltas addressed to it into a sigma
+//! file, and the participant who lost the share combines the sigmas.
+
+use std::{collections::BTreeSet, path::Path};
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use frost_ristretto255::{
+    self as frost,
+    keys::{
+        repairable::{repair_share_step_1, repair_share_step_2, repair_share_step_3},
+        VerifiableSecretSharingCommitment,
+    },
+    Field, Ristretto255Sha512, RistrettoScalarField,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    io::{load_toml, save_private_toml},
+};
+
+use super::{identifier_to_hex, Identifier, KeyPackage, PublicKeyPackage, SecretShare};
+
+type Scalar = <RistrettoScalarField as Field>::Scalar;
+
+/// Delta of a helper for another helper, to be sent over a confidential channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairDelta {
+    /// Hex encoded identifier of participant recovering their share
+    pub participant: String,
+    /// Hex encoded identifiers of all helpers
+    pub helpers: Vec<String>,
+    /// Helper who computed delta
+    pub sender: String,
+    /// Helper delta is addressed to
+    pub recipient: String,
+    /// Hex encoded scalar
+    pub delta: String,
+}
+
+/// Sum of deltas received by a helper, sent to participant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairSigma {
+    /// Hex encoded identifier of participant recovering their share
+    pub participant: String,
+    /// Hex encoded identifiers of all helpers
+    pub helpers: Vec<String>,
+    /// Helper who computed sigma
+    pub sender: String,
+    /// Hex encoded scalar
+    pub sigma: String,
+    /// Public commitment of group's shares
+    pub commitment: VerifiableSecretSharingCommitment,
+}
+
+impl RepairDelta {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        load_toml(path)
+    }
+
+    /// Save readable by owner only, delta is secret
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        save_private_toml(self, path)
+    }
+}
+
+impl RepairSigma {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        load_toml(path)
+    }
+
+    /// Save readable by owner only, sigma is secret
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        save_private_toml(self, path)
+    }
+}
+
+/// Step 1 of a helper, one delta for every helper including itself.
+/// Fails unless at least `min_signers` of the group help, taken from the
+/// commitment of the helper's share, which holds one coefficient per signer
+/// needed.
+pub fn repair_deltas<R: RngCore + CryptoRng>(
+    share: &SecretShare,
+    helpers: &[Identifier],
+    participant: Identifier,
+    rng: &mut R,
+) -> Result<Vec<RepairDelta>, Error> {
+    let sender = identifier_to_hex(share.identifier());
+    let min_signers = share.commitment().serialize().len();
+    let min_signers_error = || {
+        Error::Sign(format!(
+            "share recovery needs at least {} helpers, the group's min_signers, {} given",
+            min_signers,
+            helpers.len()
+        ))
+    };
+    if helpers.len() < min_signers {
+        return Err(min_signers_error());
+    }
+    if helpers.contains(&participant) {
+        return Err(Error::Sign(
+            "participant recovering their share cannot help".to_string(),
+        ));
+    }
+    if !helpers.contains(share.identifier()) {
+        return Err(Error::unauthorized(sender, "not listed in helpers"));
+    }
+    let deltas = repair_share_step_1::<Ristretto255Sha512, _>(helpers, share, rng, participant)
+        .map_err(|err| match err {
+            frost::Error::InvalidMinSigners => min_signers_error(),
+            err => Error::Frost(err),
+        })?;
+    let helpers: Vec<String> = helpers.iter().map(identifier_to_hex).collect();
+    Ok(deltas
+        .iter()
+        .map(|(recipient, delta)| RepairDelta {
+            participant: identifier_to_hex(&participant),
+            helpers: helpers.clone(),
+            sender: sender.clone(),
+            recipient: identifier_to_hex(recipient),
+            delta: scalar_to_hex(delta),
+        })
+        .collect())
+}
+
+/// Step 2 of a helper, summing deltas addressed to it from every helper
+pub fn repair_sigma(share: &SecretShare, deltas: &[RepairDelta]) -> Result<RepairSigma, Error> {
+    let recipient = identifier_to_hex(share.identifier());
+    let Some(first) = deltas.first() else {
+        return Err(Error::Sign("no deltas received".to_string()));
+    };
+    let mut senders = BTreeSet::new();
+    for delta in deltas {
+        if delta.recipient != recipient {
+            return Err(Error::unauthorized(
+                &delta.sender,
+                "delta addressed to another helper",
+            ));
+        }
+        if delta.participant != first.participant || delta.helpers != first.helpers {
+            return Err(Error::Sign(
+                "deltas belong to different recoveries".to_string(),
+            ));
+        }
+        senders.insert(delta.sender.as_str());
+    }
+    let helpers: BTreeSet<&str> = first.helpers.iter().map(String::as_str).collect();
+    if senders != helpers {
+        return Err(Error::Sign(format!(
+            "expected a delta from each of {} helpers, received from {}",
+            helpers.len(),
+            senders.len()
+        )));
+    }
+    let deltas = deltas
+        .iter()
+        .map(|delta| scalar_from_hex(&delta.delta))
+        .collect::<Result<Vec<Scalar>, Error>>()?;
+    Ok(RepairSigma {
+        participant: first.participant.clone(),
+        helpers: first.helpers.clone(),
+        sender: recipient,
+        sigma: scalar_to_hex(&repair_share_step_2(&deltas)),
+        commitment: share.commitment().clone(),
+    })
+}
+
+/// Step 3 of participant, combining sigmas of every helper into a new share
+/// which is only accepted when it matches group's public key package
+pub fn recover_share(
+    participant: Identifier,
+    sigmas: &[RepairSigma],
+    pubkeys: &PublicKeyPackage,
+) -> Result<SecretShare, Error> {
+    let identifier = identifier_to_hex(&participant);
+    let Some(first) = sigmas.first() else {
+        return Err(Error::Sign("no sigmas received".to_string()));
+    };
+    let mut senders = BTreeSet::new();
+    for sigma in sigmas {
+        if sigma.participant != identifier {
+            return Err(Error::unauthorized(
+                &sigma.sender,
+                "sigma for another participant",
+            ));
+        }
+        if sigma.helpers != first.helpers || sigma.commitment != first.commitment {
+            return Err(Error::Sign(
+                "sigmas belong to different recoveries".to_string(),
+            ));
+        }
+        senders.insert(sigma.sender.as_str());
+    }
+    let helpers: BTreeSet<&str> = first.helpers.iter().map(String::as_str).collect();
+    if senders != helpers {
+        return Err(Error::Sign(format!(
+            "expected a sigma from each of {} helpers, received from {}",
+            helpers.len(),
+            senders.len()
+        )));
+    }
+    let scalars = sigmas
+        .iter()
+        .map(|sigma| scalar_from_hex(&sigma.sigma))
+        .collect::<Result<Vec<Scalar>, Error>>()?;
+    let share = repair_share_step_3(&scalars, participant, &first.commitment);
+
+    let key_package = KeyPackage::try_from(share.clone())?;
+    let matches = key_package.verifying_key() == pubkeys.verifying_key()
+        && pubkeys.verifying_shares().get(&participant) == Some(key_package.verifying_share());
+    if !matches {
+        return Err(Error::Sign(
+            "recovered share does not match group's public key package".to_string(),
+        ));
+    }
+    Ok(share)
+}
+
+fn scalar_to_hex(scalar: &Scalar) -> String {
+    <RistrettoScalarField as Field>::serialize(scalar).to_lower_hex_string()
+}
+
+fn scalar_from_hex(hex: &str) -> Result<Scalar, Error> {
+    let invalid = || Error::Sign(format!("invalid scalar `{}`", hex));
+    let bytes = <[u8; 32]>::from_hex(hex).map_err(|_| invalid())?;
+    <RistrettoScalarField as Field>::deserialize(&bytes).map_err(|_| invalid())
+}
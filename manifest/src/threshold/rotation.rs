@@ -0,0 +1,402 @@
+//! Rotation of signers and threshold of a FROST group by resharing,
+//! keeping the group key. At least `signs_required` of the previous signers
+//! approve the rotation before reissuing shares, and every rotation stays
+//! in the manifest.
+//!
+//! Approvals are signatures of each previous signer with their own verifying
+//! share, listed in `signer_keys`. The group key outlives every signer set,
+//! so a group signature could not tell the previous set from a later one.
+//! A rotation on its own only names the keys approving it, which is why an
+//! update of a verified version must rotate from the keys that version lists.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hex::DisplayHex;
+use chrono::Utc;
+use frost_ristretto255::{
+    keys::resharing::{reshare_step_1, reshare_step_2},
+    SigningKey,
+};
+use rand::{CryptoRng, RngCore};
+
+pub use frost_ristretto255::keys::resharing::SecretSubshare;
+
+use crate::{
+    error::Error,
+    manifest::{PodManifest, PodManifestRotation, PodManifestSignature, SignatureScheme},
+};
+
+use super::{
+    identifier_from_hex, identifier_to_hex, signature_from_hex, signature_to_hex, signer_keys,
+    verifying_key_from_hex, verifying_key_to_hex, Identifier, KeyPackage, PublicKeyPackage,
+};
+
+impl PodManifestSignature {
+    /// Unapproved rotation from current signers to new signers
+    pub fn rotation(
+        &self,
+        new_signers: &[Identifier],
+        new_signs_required: u16,
+    ) -> Result<PodManifestRotation, Error> {
+        self.require_frost()?;
+        if new_signs_required < 2 || new_signers.len() < new_signs_required as usize {
+            return Err(Error::Sign(format!(
+                "cannot require {} of {} new signers",
+                new_signs_required,
+                new_signers.len()
+            )));
+        }
+        if !self.signer_keys.keys().eq(sorted(&self.signers)) {
+            return Err(Error::Sign(
+                "signer_keys do not match current signers".to_string(),
+            ));
+        }
+        Ok(PodManifestRotation {
+            signers: self.signers.clone(),
+            signs_required: self.signs_required,
+            new_signers: new_signers.iter().map(identifier_to_hex).collect(),
+            new_signs_required: new_signs_required as usize,
+            instant: Utc::now(),
+            signer_keys: self.signer_keys.clone(),
+            approvals: BTreeMap::new(),
+        })
+    }
+
+    /// Approval of rotation by a current signer, signing rotation message
+    /// with their own key share
+    pub fn approve_rotation<R: RngCore + CryptoRng>(
+        &self,
+        rotation: &mut PodManifestRotation,
+        key_package: &KeyPackage,
+        rng: &mut R,
+    ) -> Result<(), Error> {
+        self.check_rotation(rotation)?;
+        self.require_signer(key_package.identifier())?;
+        let identifier = identifier_to_hex(key_package.identifier());
+        let share = key_package
+            .verifying_share()
+            .serialize()
+            .to_lower_hex_string();
+        if key_package.verifying_key() != &self.verifying_key()?
+            || self.signer_keys.get(&identifier) != Some(&share)
+        {
+            return Err(Error::unauthorized(
+                identifier,
+                "key share is not listed in signer_keys",
+            ));
+        }
+        let key = SigningKey::deserialize(key_package.signing_share().serialize())?;
+        let signature = key.sign(rng, rotation.message(&self.primary).as_bytes());
+        rotation
+            .approvals
+            .insert(identifier, signature_to_hex(&signature));
+        Ok(())
+    }
+
+    /// Signature with approved rotation applied and recorded, listing key
+    /// shares of new signers. `root_sign` is cleared until the manifest is
+    /// signed again.
+    pub fn rotated(
+        &self,
+        rotation: PodManifestRotation,
+        new_pubkeys: &PublicKeyPackage,
+    ) -> Result<PodManifestSignature, Error> {
+        self.check_rotation(&rotation)?;
+        if !rotation.is_approved(&self.primary) {
+            return Err(Error::Sign(format!(
+                "rotation is not approved by {} current signers",
+                self.signs_required
+            )));
+        }
+        if new_pubkeys.verifying_key() != &self.verifying_key()? {
+            return Err(Error::Sign(
+                "public key package belongs to another group".to_string(),
+            ));
+        }
+        let signer_keys = signer_keys(new_pubkeys);
+        if !signer_keys.keys().eq(sorted(&rotation.new_signers)) {
+            return Err(Error::Sign(
+                "public key package does not match new signers".to_string(),
+            ));
+        }
+        let mut rotations = self.rotations.clone();
+        let (signers, signs_required) = (rotation.new_signers.clone(), rotation.new_signs_required);
+        rotations.push(rotation);
+        Ok(PodManifestSignature {
+            scheme: self.scheme,
+            primary: self.primary.clone(),
+            root_sign: String::new(),
+            signs_required,
+            signers,
+            signer_keys,
+            rotations,
+        })
+    }
+
+    /// Whether each rotation continues from the signer set before it, is
+    /// approved by the keys it names, and the last one leads to current
+    /// signers. Only [`check_signers`](Self::check_signers) ties those keys
+    /// to the ones a previous version listed.
+    pub fn verify_rotations(&self) -> bool {
+        let Some(last) = self.rotations.last() else {
+            return true;
+        };
+        if self.scheme != SignatureScheme::Frost {
+            return false;
+        }
+        let chained = self.rotations.windows(2).all(|pair| {
+            pair[0].new_signers == pair[1].signers
+                && pair[0].new_signs_required == pair[1].signs_required
+        });
+        chained
+            && last.new_signers == self.signers
+            && last.new_signs_required == self.signs_required
+            && self
+                .rotations
+                .iter()
+                .all(|rotation| rotation.is_approved(&self.primary))
+    }
+
+    /// Why signers of update, replacing a version signed by this signature,
+    /// are not approved by current signers. None if unchanged or a single
+    /// new rotation approved with current `signer_keys` leads to them.
+    pub fn check_signers(&self, update: &PodManifestSignature) -> Option<String> {
+        if update.scheme != self.scheme || update.primary != self.primary {
+            return Some("primary signer changed".to_string());
+        }
+        if self.scheme != SignatureScheme::Frost {
+            // signers are covered by root_sign of primary
+            return None;
+        }
+        if !update.rotations.starts_with(&self.rotations) {
+            return Some("recorded rotations changed".to_string());
+        }
+        let rotation = match &update.rotations[self.rotations.len()..] {
+            [] => {
+                if update.signers != self.signers
+                    || update.signs_required != self.signs_required
+                    || update.signer_keys != self.signer_keys
+                {
+                    return Some("signers changed without rotation".to_string());
+                }
+                return None;
+            }
+            [rotation] => rotation,
+            _ => return Some("more than one rotation since previous version".to_string()),
+        };
+        if self.check_rotation(rotation).is_err() {
+            return Some("rotation does not start from previous signers".to_string());
+        }
+        if !rotation.is_approved(&self.primary) {
+            return Some(format!(
+                "rotation is not approved by {} previous signers",
+                self.signs_required
+            ));
+        }
+        if update.signers != rotation.new_signers
+            || update.signs_required != rotation.new_signs_required
+            || !update.signer_keys.keys().eq(sorted(&update.signers))
+        {
+            return Some("signers do not match rotation".to_string());
+        }
+        None
+    }
+
+    /// Rotation must start from current signers and their key shares
+    fn check_rotation(&self, rotation: &PodManifestRotation) -> Result<(), Error> {
+        self.require_frost()?;
+        if rotation.signers != self.signers
+            || rotation.signs_required != self.signs_required
+            || rotation.signer_keys != self.signer_keys
+        {
+            return Err(Error::Sign(
+                "rotation does not start from current signers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn require_signer(&self, identifier: &Identifier) -> Result<(), Error> {
+        let identifier = identifier_to_hex(identifier);
+        if !self.signers.contains(&identifier) {
+            return Err(Error::unauthorized(identifier, "not listed in signers"));
+        }
+        Ok(())
+    }
+}
+
+impl PodManifestRotation {
+    /// Whether at least `signs_required` distinct previous signers approved
+    /// rotation of group `primary` with their key listed in `signer_keys`
+    pub fn is_approved(&self, primary: &str) -> bool {
+        let message = self.message(primary);
+        let approved = self
+            .approvals
+            .iter()
+            .filter(|(identifier, sign)| {
+                let Some(key) = self.signer_keys.get(*identifier) else {
+                    return false;
+                };
+                let (Ok(key), Ok(signature)) =
+                    (verifying_key_from_hex(key), signature_from_hex(sign))
+                else {
+                    return false;
+                };
+                self.signers.contains(*identifier)
+                    && key.verify(message.as_bytes(), &signature).is_ok()
+            })
+            .count();
+        self.signs_required > 0 && approved >= self.signs_required
+    }
+}
+
+impl PodManifest {
+    /// Apply approved rotation to manifest, new signers then sign the manifest
+    pub fn rotate(
+        &mut self,
+        rotation: PodManifestRotation,
+        new_pubkeys: &PublicKeyPackage,
+    ) -> Result<(), Error> {
+        self.signature = self.signature.rotated(rotation, new_pubkeys)?;
+        Ok(())
+    }
+}
+
+/// Reshare step of a previous signer, subshares for each new signer which must
+/// be sent over a confidential channel. Only rotations approved with the key
+/// shares of `old_pubkeys` are reshared.
+pub fn reshare_subshares<R: RngCore + CryptoRng>(
+    key_package: &KeyPackage,
+    old_pubkeys: &PublicKeyPackage,
+    rotation: &PodManifestRotation,
+    rng: &mut R,
+) -> Result<BTreeMap<Identifier, SecretSubshare>, Error> {
+    if key_package.verifying_key() != old_pubkeys.verifying_key() {
+        return Err(Error::Sign(
+            "public key package belongs to another group".to_string(),
+        ));
+    }
+    require_approved(rotation, old_pubkeys)?;
+    let identifier = identifier_to_hex(key_package.identifier());
+    if !rotation.signers.contains(&identifier) {
+        return Err(Error::unauthorized(identifier, "not listed in signers"));
+    }
+    let new_signers = new_signers(rotation)?;
+    Ok(reshare_step_1(
+        key_package.signing_share(),
+        rng,
+        rotation.new_signs_required as u16,
+        &new_signers,
+    )?)
+}
+
+/// Reshare step of a new signer, combining subshares received from previous
+/// signers into a key package of the unchanged group key
+pub fn reshare_key_package(
+    identifier: Identifier,
+    old_pubkeys: &PublicKeyPackage,
+    rotation: &PodManifestRotation,
+    received_subshares: &BTreeMap<Identifier, SecretSubshare>,
+) -> Result<(KeyPackage, PublicKeyPackage), Error> {
+    require_approved(rotation, old_pubkeys)?;
+    if received_subshares.len() < rotation.signs_required {
+        return Err(Error::Sign(format!(
+            "{} subshares received, {} required",
+            received_subshares.len(),
+            rotation.signs_required
+        )));
+    }
+    let new_signers = new_signers(rotation)?;
+    let (key_package, pubkeys) = reshare_step_2(
+        identifier,
+        old_pubkeys,
+        rotation.new_signs_required as u16,
+        &new_signers,
+        received_subshares,
+    )?;
+    if pubkeys.verifying_key() != old_pubkeys.verifying_key() {
+        return Err(Error::Sign("resharing changed the group key".to_string()));
+    }
+    Ok((key_package, pubkeys))
+}
+
+/// Rotation must name the key shares of `old_pubkeys` and be approved with them
+fn require_approved(
+    rotation: &PodManifestRotation,
+    old_pubkeys: &PublicKeyPackage,
+) -> Result<(), Error> {
+    if rotation.signer_keys != signer_keys(old_pubkeys) {
+        return Err(Error::Sign(
+            "rotation does not start from key shares of group".to_string(),
+        ));
+    }
+    let primary = verifying_key_to_hex(old_pubkeys.verifying_key());
+    if !rotation.is_approved(&primary) {
+        return Err(Error::Sign(format!(
+            "rotation is not approved by {} previous signers",
+            rotation.signs_required
+        )));
+    }
+    Ok(())
+}
+
+fn new_signers(rotation: &PodManifestRotation) -> Result<Vec<Identifier>, Error> {
+    rotation
+        .new_signers
+        .iter()
+        .map(|identifier| identifier_from_hex(identifier))
+        .collect()
+}
+
+/// Signers in order of their `signer_keys`
+fn sorted(signers: &[String]) -> BTreeSet<&String> {
+    signers.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+
+    use crate::manifest::{PodManifestRotation, PodManifestSignature, SignatureScheme};
+
+    fn rotation(from: (&[&str], usize), to: (&[&str], usize)) -> PodManifestRotation {
+        PodManifestRotation {
+            signers: from.0.iter().map(|signer| signer.to_string()).collect(),
+            signs_required: from.1,
+            new_signers: to.0.iter().map(|signer| signer.to_string()).collect(),
+            new_signs_required: to.1,
+            instant: Utc::now(),
+            signer_keys: BTreeMap::new(),
+            approvals: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_rotations_chain() {
+        let mut signature = PodManifestSignature {
+            scheme: SignatureScheme::Frost,
+            signers: vec!["b".to_string(), "c".to_string()],
+            signs_required: 2,
+            ..Default::default()
+        };
+        assert!(signature.verify_rotations());
+
+        // rotations must lead to current signers
+        signature.rotations = vec![rotation((&["a", "b", "c"], 3), (&["b", "c", "d"], 2))];
+        assert!(!signature.verify_rotations());
+
+        // and continue from each other
+        signature.rotations = vec![
+            rotation((&["a", "b", "c"], 3), (&["a", "b", "c"], 2)),
+            rotation((&["a", "b", "d"], 2), (&["b", "c"], 2)),
+        ];
+        assert!(!signature.verify_rotations());
+
+        // bitcoin signers cannot be rotated by group signature
+        signature.scheme = SignatureScheme::Bitcoin;
+        signature.rotations = vec![rotation((&["a"], 1), (&["b", "c"], 2))];
+        assert!(!signature.verify_rotations());
+    }
+}
@@ -0,0 +1,264 @@
+//! Signing session passed around as files between offline co-signers.
+//! Coordinator opens session.toml for a manifest, each co-signer runs
+//! the commit and later the sign step on their own machine, keeping
+//! nonces in a separate file, then coordinator aggregates.
+
+use std::{collections::BTreeMap, path::Path};
+
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    io::{from_toml, load_toml, save_private_toml, save_toml},
+    manifest::PodManifest,
+};
+
+use super::{
+    commit, identifier_from_hex, identifier_to_hex, verifying_key_to_hex, KeyPackage,
+    PublicKeyPackage, SignatureShare, SigningCommitments, SigningNonces, SigningPackage,
+};
+
+/// State of a signing session, bound to the digest of a single manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningSession {
+    /// Digest of manifest being signed
+    pub digest: String,
+    /// Hex encoded group verifying key
+    pub group: String,
+    /// Number of co-signers required
+    pub signs_required: usize,
+    /// Round 1 commitments by hex encoded identifier
+    #[serde(default)]
+    pub commitments: BTreeMap<String, SigningCommitments>,
+    /// Round 2 signature shares by hex encoded identifier
+    #[serde(default)]
+    pub shares: BTreeMap<String, SignatureShare>,
+    /// Hex encoded aggregated signature, once complete
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signature: String,
+}
+
+/// Secret nonces of a co-signer for one session, must never leave the co-signer
+#[derive(Serialize, Deserialize)]
+pub struct SessionNonces {
+    /// Digest of manifest of session
+    pub digest: String,
+    /// Hex encoded identifier of co-signer
+    pub identifier: String,
+    /// Secret nonces, dropped by the sign step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonces: Option<SigningNonces>,
+    /// Commitments recorded in session for nonces
+    pub commitments: SigningCommitments,
+    /// Commitments of every co-signer the nonces signed over, empty until
+    /// the sign step. Nonces are used for a single signature share only.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signed: BTreeMap<String, SigningCommitments>,
+}
+
+impl SigningSession {
+    /// Open session for manifest signed with FROST scheme
+    pub fn new(manifest: &PodManifest) -> Result<Self, Error> {
+        Ok(SigningSession {
+            digest: manifest.digest()?,
+            group: verifying_key_to_hex(&manifest.signature.verifying_key()?),
+            signs_required: manifest.signature.signs_required,
+            commitments: BTreeMap::new(),
+            shares: BTreeMap::new(),
+            signature: String::new(),
+        })
+    }
+
+    pub fn from_string(content: &str) -> Result<Self, Error> {
+        from_toml(content)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        load_toml(path)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        save_toml(self, path)
+    }
+
+    /// Commit step of co-signer, recording commitments in session.
+    /// Returned nonces are kept by co-signer until the sign step.
+    pub fn commit<R: RngCore + CryptoRng>(
+        &mut self,
+        key_package: &KeyPackage,
+        rng: &mut R,
+    ) -> Result<SessionNonces, Error> {
+        let identifier = identifier_to_hex(key_package.identifier());
+        if verifying_key_to_hex(key_package.verifying_key()) != self.group {
+            return Err(Error::unauthorized(
+                identifier,
+                "key share of another group",
+            ));
+        }
+        if !self.shares.is_empty() {
+            return Err(session_error("signing has started, commitments are closed"));
+        }
+        if self.commitments.contains_key(&identifier) {
+            return Err(Error::unauthorized(identifier, "already committed"));
+        }
+        let (nonces, commitments) = commit(key_package, rng);
+        self.commitments.insert(identifier.clone(), commitments);
+        Ok(SessionNonces {
+            digest: self.digest.clone(),
+            identifier,
+            nonces: Some(nonces),
+            commitments,
+            signed: BTreeMap::new(),
+        })
+    }
+
+    /// Signing package over all commitments, identical for every co-signer
+    pub fn signing_package(&self) -> Result<SigningPackage, Error> {
+        if self.commitments.len() < self.signs_required {
+            return Err(session_error(format!(
+                "{} commitments received, {} required",
+                self.commitments.len(),
+                self.signs_required
+            )));
+        }
+        let commitments = self
+            .commitments
+            .iter()
+            .map(|(identifier, commitments)| Ok((identifier_from_hex(identifier)?, *commitments)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        Ok(SigningPackage::new(commitments, self.digest.as_bytes()))
+    }
+
+    /// Sign step of co-signer, consuming nonces of commit step by dropping
+    /// the secret nonces and recording the commitments signed over. Nonces
+    /// of another session, used nonces, a replaced commitment or an
+    /// identifier which already signed are rejected.
+    pub fn sign(
+        &mut self,
+        manifest: &PodManifest,
+        nonces: &mut SessionNonces,
+        key_package: &KeyPackage,
+    ) -> Result<(), Error> {
+        self.check_manifest(manifest)?;
+        let identifier = identifier_to_hex(key_package.identifier());
+        if nonces.digest != self.digest {
+            return Err(session_error("nonces belong to another session"));
+        }
+        if nonces.identifier != identifier {
+            return Err(Error::unauthorized(
+                identifier,
+                "nonces of another co-signer",
+            ));
+        }
+        if nonces.nonces.is_none()
+            || !nonces.signed.is_empty()
+            || self.shares.contains_key(&identifier)
+        {
+            return Err(Error::unauthorized(identifier, "nonces already used"));
+        }
+        if self.commitments.get(&identifier) != Some(&nonces.commitments) {
+            return Err(Error::unauthorized(
+                identifier,
+                "nonces do not match commitments in session",
+            ));
+        }
+        let signing_package = self.signing_package()?;
+        nonces.signed = self.commitments.clone();
+        let Some(secret) = nonces.nonces.take() else {
+            return Err(Error::unauthorized(identifier, "nonces already used"));
+        };
+        let share = manifest.frost_sign(&signing_package, &secret, key_package)?;
+        self.shares.insert(identifier, share);
+        Ok(())
+    }
+
+    /// Sign step of co-signer with nonces kept in a file, which is stripped
+    /// of its secret nonces and marked used before the share is added, so
+    /// another copy of the session cannot have them sign again
+    pub fn sign_file(
+        &mut self,
+        manifest: &PodManifest,
+        nonces_path: impl AsRef<Path>,
+        key_package: &KeyPackage,
+    ) -> Result<(), Error> {
+        let mut nonces = SessionNonces::load(&nonces_path)?;
+        let mut session = self.clone();
+        let result = session.sign(manifest, &mut nonces, key_package);
+        if !nonces.signed.is_empty() {
+            nonces.save(&nonces_path)?;
+        }
+        result?;
+        *self = session;
+        Ok(())
+    }
+
+    /// Aggregate step of coordinator, writing group signature into manifest
+    pub fn aggregate(
+        &mut self,
+        manifest: &mut PodManifest,
+        pubkeys: &PublicKeyPackage,
+    ) -> Result<(), Error> {
+        self.check_manifest(manifest)?;
+        let missing: Vec<&String> = self
+            .commitments
+            .keys()
+            .filter(|identifier| !self.shares.contains_key(*identifier))
+            .collect();
+        if !missing.is_empty() {
+            return Err(session_error(format!(
+                "waiting for signature shares of {:?}",
+                missing
+            )));
+        }
+        let signing_package = self.signing_package()?;
+        let shares = self
+            .shares
+            .iter()
+            .map(|(identifier, share)| Ok((identifier_from_hex(identifier)?, *share)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        manifest.frost_aggregate(&signing_package, &shares, pubkeys)?;
+        self.signature = manifest.signature.root_sign.clone();
+        Ok(())
+    }
+
+    /// Whether aggregated signature is present
+    pub fn is_complete(&self) -> bool {
+        !self.signature.is_empty()
+    }
+
+    fn check_manifest(&self, manifest: &PodManifest) -> Result<(), Error> {
+        if manifest.digest()? != self.digest {
+            return Err(session_error("manifest does not match session digest"));
+        }
+        Ok(())
+    }
+}
+
+impl SessionNonces {
+    pub fn from_string(content: &str) -> Result<Self, Error> {
+        from_toml(content)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        load_toml(path)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Save readable by owner only, replacing file in one step, so nonces
+    /// are never seen unused once marked used
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        save_private_toml(self, path)
+    }
+}
+
+fn session_error(reason: impl Into<String>) -> Error {
+    Error::Sign(reason.into())
+}
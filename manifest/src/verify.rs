@@ -0,0 +1,605 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::ErrorKind,
+    path::Path,
+};
+
+use crate::{
+    crypto::verify_message,
+    error::Error,
+    file::{check_path, is_manifest_file, is_match, pattern, walk_dir, PodFile, PodFileRoot},
+    hash::FileHash,
+    manifest::{PodManifest, PodManifestFiles, SignatureScheme},
+};
+
+/// Verification outcome of a single signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignStatus {
+    /// Signature matches manifest digest
+    Valid,
+    /// Signature present but does not match manifest digest
+    Invalid,
+    /// Signer listed in signers has not signed
+    Missing,
+    /// Signature by an address not listed in signers
+    Unauthorized,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerReport {
+    /// Address of signer
+    pub address: String,
+    /// Verification outcome
+    pub status: SignStatus,
+}
+
+/// Structured result of `PodManifest::verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Signature scheme of manifest
+    pub scheme: SignatureScheme,
+    /// Digest of canonical manifest signed by signers
+    pub digest: String,
+    /// Whether root_sign is signed by primary over signers and signs_required,
+    /// or under FROST, is a valid group signature of digest
+    pub root_sign: bool,
+    /// Whether rotations form a chain leading to current signers, each
+    /// approved by `signs_required` of the signers before it
+    pub rotations: bool,
+    /// Number of valid signatures required
+    pub signs_required: usize,
+    /// Outcome per signer, followed by signatures of unknown addresses
+    pub signers: Vec<SignerReport>,
+}
+
+impl VerifyReport {
+    /// Number of valid signatures by listed signers
+    pub fn valid_signs(&self) -> usize {
+        self.signers
+            .iter()
+            .filter(|signer| signer.status == SignStatus::Valid)
+            .count()
+    }
+
+    /// Whether root_sign is valid and enough signers signed the manifest,
+    /// a valid FROST signature already proves the threshold was met, and
+    /// co-signers refuse to sign a `signs_required` other than the threshold
+    pub fn is_valid(&self) -> bool {
+        match self.scheme {
+            SignatureScheme::Bitcoin => {
+                self.root_sign && self.rotations && self.valid_signs() >= self.signs_required.max(1)
+            }
+            SignatureScheme::Frost => self.root_sign && self.rotations,
+        }
+    }
+}
+
+impl PodManifest {
+    /// Verify signatures against the canonical manifest,
+    /// without converting to ZeroNet content
+    pub fn verify(&self) -> Result<VerifyReport, Error> {
+        let digest = self.digest()?;
+        let signature = &self.signature;
+        if signature.scheme == SignatureScheme::Frost {
+            return Ok(VerifyReport {
+                scheme: signature.scheme,
+                root_sign: signature.verify_frost(digest.as_bytes()),
+                rotations: signature.verify_rotations(),
+                digest,
+                signs_required: signature.signs_required,
+                signers: vec![],
+            });
+        }
+        let root_sign = verify_message(
+            &signature.primary,
+            &signature.root_message(),
+            &signature.root_sign,
+        );
+
+        let mut signers: Vec<SignerReport> = vec![];
+        for address in &signature.signers {
+            if signers.iter().any(|signer| &signer.address == address) {
+                continue;
+            }
+            let sign = self.signatures.iter().find(|sign| &sign.address == address);
+            let status = match sign {
+                Some(sign) if verify_message(address, &digest, &sign.sign) => SignStatus::Valid,
+                Some(_) => SignStatus::Invalid,
+                None => SignStatus::Missing,
+            };
+            signers.push(SignerReport {
+                address: address.clone(),
+                status,
+            });
+        }
+        for sign in &self.signatures {
+            if !signature.signers.contains(&sign.address) {
+                signers.push(SignerReport {
+                    address: sign.address.clone(),
+                    status: SignStatus::Unauthorized,
+                });
+            }
+        }
+
+        Ok(VerifyReport {
+            scheme: signature.scheme,
+            digest,
+            root_sign,
+            rotations: signature.verify_rotations(),
+            signs_required: signature.signs_required,
+            signers,
+        })
+    }
+}
+
+/// State of a file on disk compared to its listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    /// Size and hash match
+    Ok,
+    /// File is listed but absent on disk
+    Missing,
+    /// Size on disk differs from listed size
+    SizeMismatch { expected: usize, actual: usize },
+    /// Content on disk does not match listed hash
+    HashMismatch,
+    /// Chunked file on disk with only some chunks intact, such as a
+    /// partially downloaded optional file
+    Partial { intact: usize, total: usize },
+    /// File on disk is not listed
+    Extra,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// Path of file, relative to pod directory
+    pub path: String,
+    /// Whether file is listed in files_optional
+    pub optional: bool,
+    /// State of file on disk
+    pub status: FileStatus,
+}
+
+impl FileReport {
+    /// Whether file is intact, optional files are allowed to be missing
+    pub fn is_ok(&self) -> bool {
+        match self.status {
+            FileStatus::Ok => true,
+            FileStatus::Missing => self.optional,
+            _ => false,
+        }
+    }
+}
+
+/// Structured result of `PodFileRoot::verify_dir`
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DirReport {
+    /// files.toml checked against manifest.toml, when verified through `PodManifest`
+    pub files_manifest: Option<FileReport>,
+    /// Listed files, followed by extra files found on disk
+    pub files: Vec<FileReport>,
+}
+
+impl DirReport {
+    /// Reports of files which are not intact
+    pub fn drift(&self) -> impl Iterator<Item = &FileReport> {
+        self.files_manifest
+            .iter()
+            .chain(self.files.iter())
+            .filter(|report| !report.is_ok())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.drift().next().is_none()
+    }
+}
+
+/// Compare file at path with expected size and hash
+fn file_status(path: &Path, size: usize, hash: &FileHash) -> Result<FileStatus, Error> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return Ok(FileStatus::Missing),
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(FileStatus::Missing),
+        Err(err) => return Err(Error::io(path, err)),
+    };
+    if metadata.len() != size as u64 {
+        return Ok(FileStatus::SizeMismatch {
+            expected: size,
+            actual: metadata.len() as usize,
+        });
+    }
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    match hash
+        .verify_reader(file)
+        .map_err(|err| Error::io(path, err))?
+    {
+        true => Ok(FileStatus::Ok),
+        false => Ok(FileStatus::HashMismatch),
+    }
+}
+
+impl PodFile {
+    /// Check file at relative path of pod directory against its size and hash,
+    /// and a chunked file which does not match against its chunks. Paths
+    /// leaving the pod directory are refused.
+    pub fn verify(&self, root: impl AsRef<Path>) -> Result<FileStatus, Error> {
+        check_path(&self.path)?;
+        let path = root.as_ref().join(&self.path);
+        let status = file_status(&path, self.size, &self.hash)?;
+        let Some(chunks) = &self.chunks else {
+            return Ok(status);
+        };
+        if matches!(status, FileStatus::Ok | FileStatus::Missing) {
+            return Ok(status);
+        }
+        let file = File::open(&path).map_err(|err| Error::io(&path, err))?;
+        let broken = chunks
+            .verify_reader(file)
+            .map_err(|err| Error::io(&path, err))?;
+        let total = chunks.list.len();
+        match broken.len() {
+            0 => Ok(status),
+            broken => Ok(FileStatus::Partial {
+                intact: total - broken,
+                total,
+            }),
+        }
+    }
+}
+
+impl PodManifestFiles {
+    /// Check files.toml in pod directory against size and hash recorded in manifest.toml
+    pub fn verify(&self, root: impl AsRef<Path>) -> Result<FileStatus, Error> {
+        check_path(&self.manifest)?;
+        file_status(&root.as_ref().join(&self.manifest), self.size, &self.hash)
+    }
+}
+
+impl PodFileRoot {
+    /// Check files on disk against listed files, files matching ignore
+    /// pattern are not reported as extra. Nothing is read unless every
+    /// listed path stays within root.
+    pub fn verify_dir(
+        &self,
+        root: impl AsRef<Path>,
+        ignore: Option<&str>,
+    ) -> Result<DirReport, Error> {
+        let root = root.as_ref();
+        let ignore = pattern(ignore)?;
+        self.check_paths()?;
+
+        let mut report = DirReport::default();
+        let mut listed = BTreeSet::new();
+        let files = self.files.iter().map(|file| (file, false));
+        let files_optional = self.files_optional.iter().map(|file| (file, true));
+        for (file, optional) in files.chain(files_optional) {
+            listed.insert(file.path.as_str());
+            report.files.push(FileReport {
+                path: file.path.clone(),
+                optional,
+                status: file.verify(root)?,
+            });
+        }
+
+        let mut paths = vec![];
+        walk_dir(root, "", &mut paths)?;
+        paths.sort();
+        for path in paths {
+            if listed.contains(path.as_str())
+                || is_manifest_file(&path)
+                || is_match(&ignore, &path)?
+            {
+                continue;
+            }
+            report.files.push(FileReport {
+                path,
+                optional: false,
+                status: FileStatus::Extra,
+            });
+        }
+        Ok(report)
+    }
+}
+
+impl PodManifest {
+    /// Check files.toml against manifest.toml, then files on disk against files.toml
+    pub fn verify_dir(&self, root: impl AsRef<Path>) -> Result<DirReport, Error> {
+        let root = root.as_ref();
+        let Some(files) = &self.files else {
+            return Ok(DirReport::default());
+        };
+        let status = files.verify(root)?;
+        let files_manifest = FileReport {
+            path: files.manifest.clone(),
+            optional: false,
+            status,
+        };
+        if status == FileStatus::Missing {
+            return Ok(DirReport {
+                files_manifest: Some(files_manifest),
+                files: vec![],
+            });
+        }
+
+        let path = root.join(&files.manifest);
+        let content = fs::read_to_string(&path).map_err(|err| Error::io(&path, err))?;
+        let file_root = PodFileRoot::from_string(&content).map_err(|err| err.with_path(&path))?;
+        let ignore = self.meta.as_ref().and_then(|meta| meta.ignore.as_deref());
+        let mut report = file_root.verify_dir(root, ignore)?;
+        report.files_manifest = Some(files_manifest);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::PrivateKey;
+    use chrono::Utc;
+
+    use super::{FileStatus, SignStatus};
+    use crate::{
+        chunk::ChunkOptions,
+        crypto::{address_from_key, sign_message, Signer, TaprootKey},
+        error::Error,
+        file::{FromDirOptions, PodFileRoot},
+        hash::{FileHash, HashAlgorithm},
+        io::write_file,
+        manifest::{PodManifest, PodManifestFiles, PodManifestSignature, PodManifestSigns},
+        test_utils::new_key,
+    };
+
+    fn new_signer() -> (PrivateKey, String) {
+        let key = new_key();
+        let address = address_from_key(&key);
+        (key, address)
+    }
+
+    fn add_sign(manifest: &mut PodManifest, key: &PrivateKey) {
+        let digest = manifest.digest().unwrap();
+        manifest.signatures.push(PodManifestSigns {
+            address: address_from_key(key),
+            sign: sign_message(key, &digest),
+            instant: Utc::now(),
+        });
+    }
+
+    fn new_manifest(primary: &(PrivateKey, String), cosigner: &str) -> PodManifest {
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: primary.1.clone(),
+                signs_required: 2,
+                signers: vec![primary.1.clone(), cosigner.to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        manifest.signature.root_sign = sign_message(&primary.0, &manifest.signature.root_message());
+        manifest
+    }
+
+    #[test]
+    fn test_verify_threshold() {
+        let primary = new_signer();
+        let cosigner = new_signer();
+        let mut manifest = new_manifest(&primary, &cosigner.1);
+
+        add_sign(&mut manifest, &primary.0);
+        let report = manifest.verify().unwrap();
+        assert!(report.root_sign);
+        assert_eq!(report.signers[0].status, SignStatus::Valid);
+        assert_eq!(report.signers[1].status, SignStatus::Missing);
+        assert!(!report.is_valid());
+
+        add_sign(&mut manifest, &cosigner.0);
+        let report = manifest.verify().unwrap();
+        assert_eq!(report.valid_signs(), 2);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn test_verify_tampered_and_unauthorized() {
+        let primary = new_signer();
+        let cosigner = new_signer();
+        let stranger = new_signer();
+        let mut manifest = new_manifest(&primary, &cosigner.1);
+        add_sign(&mut manifest, &primary.0);
+        add_sign(&mut manifest, &cosigner.0);
+        add_sign(&mut manifest, &stranger.0);
+
+        manifest.signature.signs_required = 1;
+        let report = manifest.verify().unwrap();
+        assert!(!report.root_sign);
+        assert_eq!(report.signers[0].status, SignStatus::Invalid);
+        assert_eq!(report.signers[1].status, SignStatus::Invalid);
+        assert_eq!(report.signers[2].address, stranger.1);
+        assert_eq!(report.signers[2].status, SignStatus::Unauthorized);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn test_verify_taproot_primary() {
+        let primary = TaprootKey(new_key());
+        let cosigner = new_signer();
+        let mut manifest = PodManifest {
+            signature: PodManifestSignature {
+                primary: primary.address(),
+                signs_required: 2,
+                signers: vec![primary.address(), cosigner.1.clone()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        manifest.sign_root(&primary).unwrap();
+        manifest.sign(&primary).unwrap();
+        manifest.sign(&cosigner.0).unwrap();
+        let report = manifest.verify().unwrap();
+        assert!(report.root_sign);
+        assert_eq!(report.valid_signs(), 2);
+        assert!(report.is_valid());
+
+        // legacy key of same secret is another signer
+        assert!(manifest.sign(&primary.0).is_err());
+    }
+
+    #[cfg(feature = "zeronet")]
+    #[test]
+    fn test_verify_converted_zeronet_site() {
+        use crate::io::IO;
+
+        let root = PodManifest::load_from_path("tests/data/zeronet/talk/content.json").unwrap();
+        let report = root.verify().unwrap();
+        // signers_sign carries over, signs cover ZeroNet's JSON instead of the manifest
+        assert!(report.root_sign);
+        assert_eq!(report.signers[0].status, SignStatus::Invalid);
+        assert!(!report.is_valid());
+    }
+
+    fn write_pod(dir: &str) -> PodFileRoot {
+        let _ = std::fs::remove_dir_all(dir);
+        for path in [
+            "index.html",
+            "js/all.js",
+            "img/photo.jpg",
+            "data/users/a.db",
+        ] {
+            write_file(format!("{}/{}", dir, path), path.as_bytes()).unwrap();
+        }
+        let options = FromDirOptions {
+            ignore: Some("data/users/.*".to_string()),
+            optional: Some(".*jpg".to_string()),
+            ..Default::default()
+        };
+        PodFileRoot::from_dir(dir, &options).unwrap()
+    }
+
+    #[test]
+    fn test_verify_dir_drift() {
+        let dir = "tests/tmp/verify_dir/drift";
+        let root = write_pod(dir);
+        let report = root.verify_dir(dir, Some("data/users/.*")).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.files.len(), 3);
+
+        // optional files may be missing
+        std::fs::remove_file(format!("{}/img/photo.jpg", dir)).unwrap();
+        assert!(root.verify_dir(dir, Some("data/users/.*")).unwrap().is_ok());
+
+        write_file(format!("{}/index.html", dir), b"INDEX.HTML").unwrap();
+        std::fs::remove_file(format!("{}/js/all.js", dir)).unwrap();
+        write_file(format!("{}/js/new.js", dir), b"js").unwrap();
+        let report = root.verify_dir(dir, None).unwrap();
+        let drift: Vec<(&str, FileStatus)> = report
+            .drift()
+            .map(|report| (report.path.as_str(), report.status))
+            .collect();
+        assert_eq!(
+            drift,
+            vec![
+                ("index.html", FileStatus::HashMismatch),
+                ("js/all.js", FileStatus::Missing),
+                ("data/users/a.db", FileStatus::Extra),
+                ("js/new.js", FileStatus::Extra),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_dir_partial_chunked() {
+        let dir = "tests/tmp/verify_dir/partial";
+        let _ = std::fs::remove_dir_all(dir);
+        let video: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i * 7919).to_le_bytes())
+            .collect();
+        write_file(format!("{}/video.mp4", dir), &video).unwrap();
+        let options = FromDirOptions {
+            optional: Some(".*mp4".to_string()),
+            chunk: Some(ChunkOptions {
+                min_size: 16 * 1024,
+                avg_size: 64 * 1024,
+                max_size: 256 * 1024,
+            }),
+            ..Default::default()
+        };
+        let root = PodFileRoot::from_dir(dir, &options).unwrap();
+        let chunks = root.files_optional[0].chunks.as_ref().unwrap();
+        assert!(root.verify_dir(dir, None).unwrap().is_ok());
+
+        // first chunk downloaded only
+        let first = chunks.list[0].size;
+        write_file(format!("{}/video.mp4", dir), &video[..first]).unwrap();
+        let report = root.verify_dir(dir, None).unwrap();
+        assert_eq!(
+            report.files[0].status,
+            FileStatus::Partial {
+                intact: 1,
+                total: chunks.list.len()
+            }
+        );
+        assert!(!report.is_ok());
+
+        // whole file with trailing bytes has every chunk intact
+        let mut longer = video.clone();
+        longer.push(0);
+        write_file(format!("{}/video.mp4", dir), &longer).unwrap();
+        let report = root.verify_dir(dir, None).unwrap();
+        assert!(matches!(
+            report.files[0].status,
+            FileStatus::SizeMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn test_verify_dir_files_manifest() {
+        let dir = "tests/tmp/verify_dir/manifest";
+        let root = write_pod(dir);
+        let content = toml::to_string(&root).unwrap();
+        write_file(format!("{}/files.toml", dir), content.as_bytes()).unwrap();
+        let mut manifest = PodManifest {
+            files: Some(PodManifestFiles {
+                manifest: "files.toml".to_string(),
+                size: content.len(),
+                hash: FileHash::digest(HashAlgorithm::Blake3, content.as_bytes()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let report = manifest.verify_dir(dir).unwrap();
+        assert_eq!(
+            report.files_manifest.as_ref().unwrap().status,
+            FileStatus::Ok
+        );
+        // data/users is not ignored without meta.ignore
+        assert_eq!(report.drift().count(), 1);
+
+        manifest.files.as_mut().unwrap().size += 1;
+        let report = manifest.verify_dir(dir).unwrap();
+        assert_eq!(
+            report.files_manifest.unwrap().status,
+            FileStatus::SizeMismatch {
+                expected: content.len() + 1,
+                actual: content.len()
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_dir_rejects_escaping_paths() {
+        let dir = "tests/tmp/verify_dir/escape";
+        let mut root = write_pod(dir);
+        write_file("tests/tmp/verify_dir/secret.txt", b"secret").unwrap();
+        root.files[0].path = "../secret.txt".to_string();
+        assert!(matches!(
+            root.verify_dir(dir, None),
+            Err(Error::InvalidPath { .. })
+        ));
+        assert!(matches!(
+            root.files[0].verify(dir),
+            Err(Error::InvalidPath { .. })
+        ));
+        assert!(matches!(
+            root.modified_from_dir(dir),
+            Err(Error::InvalidPath { .. })
+        ));
+    }
+}
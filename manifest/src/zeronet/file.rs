@@ -1,90 +1,128 @@
-use std::{
-    fs::{self, OpenOptions},
-    io::Write,
-    path::Path,
-};
+use std::path::Path;
 
-use serde_bytes::ByteBuf;
 use zerucontent::Content;
 
 use crate::{
+    error::Error,
     file::{PodFile, PodFileRoot},
-    io::IO,
+    hash::{FileHash, HashAlgorithm},
+    io::{write_file, IO},
 };
 
-use super::utils::datetime_from_number;
+use super::utils::{content_from_path, datetime_from_number};
+
+/// Source of `PodFile.modified` when converting a site directory
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifiedSource {
+    /// Top-level `modified` of content.json, same for every file
+    #[default]
+    Content,
+    /// Modification times of files on disk
+    FileSystem,
+}
+
+/// Options for converting a ZeroNet site directory
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SiteOptions<'a> {
+    /// Source of modified times of files
+    pub modified: ModifiedSource,
+    /// Previous file list, its modified times are kept where hash is unchanged
+    pub previous: Option<&'a PodFileRoot>,
+}
 
-impl From<&Content> for PodFileRoot {
-    fn from(content: &Content) -> PodFileRoot {
+impl PodFileRoot {
+    /// Convert content.json of site directory
+    pub fn from_site(path: impl AsRef<Path>, options: &SiteOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut root = PodFileRoot::try_from(path.join("content.json").as_path())?;
+        root.apply_site_options(path, options)?;
+        Ok(root)
+    }
+
+    pub(crate) fn apply_site_options(
+        &mut self,
+        path: &Path,
+        options: &SiteOptions,
+    ) -> Result<(), Error> {
+        if options.modified == ModifiedSource::FileSystem {
+            self.modified_from_dir(path)?;
+        }
+        if let Some(previous) = options.previous {
+            self.carry_modified(previous);
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Content> for PodFileRoot {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<PodFileRoot, Error> {
         let mut root = PodFileRoot::default();
-        let modified = datetime_from_number(content.modified.clone());
+        let modified = datetime_from_number(content.modified.clone())?;
 
         root.optional = (!content.optional.is_empty()).then_some(content.optional.clone());
 
         for (path, file) in &content.files {
             root.files.push(PodFile {
                 path: path.clone(),
-                hash: file.sha512.clone(),
+                hash: FileHash::from_sha512t_hex(&file.sha512)?,
                 size: file.size,
                 modified,
+                chunks: None,
             })
         }
 
         for (path, file) in &content.files_optional {
             root.files_optional.push(PodFile {
                 path: path.clone(),
-                hash: file.sha512.clone(),
+                hash: FileHash::from_sha512t_hex(&file.sha512)?,
                 size: file.size,
                 modified,
+                chunks: None,
             })
         }
-        root
+        root.hash = root.root_hash(HashAlgorithm::Sha512Trunc256);
+        Ok(root)
+    }
+}
+
+impl TryFrom<&Path> for PodFileRoot {
+    type Error = Error;
+
+    fn try_from(path: &Path) -> Result<PodFileRoot, Error> {
+        let content = content_from_path(path)?;
+        PodFileRoot::try_from(&content).map_err(|err| err.with_path(path))
     }
 }
 
 impl IO for PodFileRoot {
     type Item = PodFileRoot;
 
-    fn load(content: &str) -> Option<Self::Item> {
-        if let Ok(file) = toml::from_str::<PodFileRoot>(content) {
-            Some(file)
-        } else {
-            None
-        }
+    fn load(content: &str) -> Result<Self::Item, Error> {
+        PodFileRoot::from_string(content)
     }
 
-    fn save(&self, path: impl AsRef<Path>) -> Option<bool> {
-        if self.files.is_empty() {
-            return None;
-        }
-        let content = toml::to_string(&self).unwrap();
-        //save to file
-        fs::create_dir_all(&path).unwrap();
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path.as_ref().join("files.toml"))
-            .unwrap();
-        //create file if not exists including parent directories
-        Some(file.write_all(content.as_bytes()).is_ok())
-    }
-
-    fn load_from_path(path: impl AsRef<Path>) -> Option<Self::Item> {
-        let path = path.as_ref();
-        if let Ok(buf) = std::fs::read(path) {
-            let bytes = ByteBuf::from(buf);
-            let content = Content::from_buf(bytes).unwrap();
-            Some(PodFileRoot::from(&content))
-        } else {
-            None
+    fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        if self.files.is_empty() && self.files_optional.is_empty() {
+            return Ok(());
         }
+        let content = toml::to_string(&self)?;
+        write_file(path.as_ref().join("files.toml"), content.as_bytes())
+    }
+
+    fn load_from_path(path: impl AsRef<Path>) -> Result<Self::Item, Error> {
+        PodFileRoot::try_from(path.as_ref())
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::{file::PodFileRoot, io::IO, manifest::PodManifest};
+    use chrono::{DateTime, Utc};
+
+    use super::{ModifiedSource, SiteOptions};
+    use crate::{error::Error, file::PodFileRoot, io::IO, manifest::PodManifest};
 
     const TEST_DATA_DIR_BARE: &str = "tests/data/zeronet/bare";
     const TEST_TMP_DIR_BARE: &str = "tests/tmp/data/zeronet/bare";
@@ -110,7 +148,7 @@ mod tests {
     fn test_pod_root_file_save_bare() {
         let path = format!("{}{}", TEST_DATA_DIR_BARE, "/content.json");
         let root = PodFileRoot::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_BARE);
+        root.save(TEST_TMP_DIR_BARE).unwrap();
     }
 
     #[test]
@@ -124,7 +162,7 @@ mod tests {
     fn test_pod_root_file_save_empty() {
         let path = format!("{}{}", TEST_DATA_DIR_EMPTY, "/content.json");
         let root = PodFileRoot::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_EMPTY);
+        root.save(TEST_TMP_DIR_EMPTY).unwrap();
     }
 
     #[test]
@@ -138,6 +176,48 @@ mod tests {
     fn test_pod_root_file_save_hello() {
         let path = format!("{}{}", TEST_DATA_DIR_HELLO, "/content.json");
         let root = PodFileRoot::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_HELLO);
+        root.save(TEST_TMP_DIR_HELLO).unwrap();
+    }
+
+    #[test]
+    fn test_pod_root_file_missing_content() {
+        let path = format!("{}{}", TEST_DATA_DIR_BARE, "/missing.json");
+        let err = PodFileRoot::load_from_path(&path).unwrap_err();
+        assert!(matches!(err, Error::Io { .. }));
+        assert_eq!(err.path().unwrap().to_str().unwrap(), path);
+    }
+
+    #[test]
+    fn test_pod_root_file_from_site_mtime() {
+        let content = PodFileRoot::from_site(TEST_DATA_DIR_EMPTY, &Default::default()).unwrap();
+        let options = SiteOptions {
+            modified: ModifiedSource::FileSystem,
+            ..Default::default()
+        };
+        let root = PodFileRoot::from_site(TEST_DATA_DIR_EMPTY, &options).unwrap();
+        let path = format!("{}/{}", TEST_DATA_DIR_EMPTY, "index.html");
+        let mtime: DateTime<Utc> = std::fs::metadata(path).unwrap().modified().unwrap().into();
+        assert_eq!(root.files[0].modified, mtime);
+        assert_eq!(root.hash, content.hash);
+
+        // carried forward from previous list, as hash is unchanged
+        let options = SiteOptions {
+            modified: ModifiedSource::FileSystem,
+            previous: Some(&content),
+        };
+        let root = PodFileRoot::from_site(TEST_DATA_DIR_EMPTY, &options).unwrap();
+        assert_eq!(root.files[0].modified, content.files[0].modified);
+    }
+
+    #[test]
+    fn test_pod_root_file_carry_modified_changed_hash() {
+        let mut previous =
+            PodFileRoot::from_site(TEST_DATA_DIR_EMPTY, &Default::default()).unwrap();
+        previous.files[0].hash = PodFileRoot::default().hash;
+        previous.files[0].modified = DateTime::from_timestamp_millis(0).unwrap();
+        let mut root = PodFileRoot::from_site(TEST_DATA_DIR_EMPTY, &Default::default()).unwrap();
+        let modified = root.files[0].modified;
+        root.carry_modified(&previous);
+        assert_eq!(root.files[0].modified, modified);
     }
 }
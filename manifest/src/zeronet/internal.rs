@@ -1,55 +1,35 @@
-use std::{
-    fs::{self, OpenOptions},
-    io::Write,
-    path::Path,
-};
+use std::path::Path;
 
 use serde_bytes::ByteBuf;
 use serde_json::Value;
 use zerucontent::{meta::Meta, Content, UserContents};
 
 use crate::{
+    error::Error,
+    file::{check_path, PodFileRoot},
     internal::{PodInternalManifest, PodInternalManifestMeta, PodInternalManifestMetaPod},
-    io::IO,
+    io::{write_file, IO},
     manifest::{PodManifestFiles, PodManifestSigns},
 };
 
-use super::utils::{datetime_from_number, number_from_datetime};
+use super::{
+    json::{json_dumps, leftover, overlay},
+    utils::{
+        address_index_to_u32, content_files, content_from_path, datetime_from_number, inner_dir,
+        number_from_datetime,
+    },
+};
 
 impl PodInternalManifest {
     pub fn contains_files(&self) -> bool {
         self.files.is_some()
     }
 
-    pub fn to_content(&self) -> Content {
+    pub fn to_content(&self) -> Result<Content, Error> {
         let mut content = Content::default();
         if let Some(files) = &self.files {
-            content.files = files.file_root.files.iter().fold(
-                std::collections::BTreeMap::new(),
-                |mut map, file| {
-                    map.insert(
-                        file.path.clone(),
-                        zerucontent::File {
-                            sha512: file.hash.clone(),
-                            size: file.size,
-                        },
-                    );
-                    map
-                },
-            );
-            content.files_optional = files.file_root.files_optional.iter().fold(
-                std::collections::BTreeMap::new(),
-                |mut map, file| {
-                    map.insert(
-                        file.path.clone(),
-                        zerucontent::File {
-                            sha512: file.hash.clone(),
-                            size: file.size,
-                        },
-                    );
-                    map
-                },
-            );
+            content.files = content_files(&files.file_root.files)?;
+            content.files_optional = content_files(&files.file_root.files_optional)?;
             content.optional = files.file_root.optional.clone().unwrap_or_default();
         }
 
@@ -65,6 +45,7 @@ impl PodInternalManifest {
             content.ignore = meta.ignore.clone();
             if let Some(pod) = &meta.pod {
                 content.address = pod.address.clone();
+                content.address_index = address_index_to_u32(pod.address_index)?;
                 content.modified = number_from_datetime(pod.modified);
                 content.meta = Meta {
                     inner_path: pod.inner_path.clone(),
@@ -72,99 +53,153 @@ impl PodInternalManifest {
                 };
                 user_content_optional_null = pod.user_contents_optional_null;
             }
-        }
-        if let Some(user_contents) = &self.meta.as_ref().unwrap().user_contents {
-            let mut user_contents = user_contents.clone();
-            if user_content_optional_null {
-                user_contents
-                    .data
-                    .insert("optional".to_string(), Value::Null);
-            }
+            if let Some(user_contents) = &meta.user_contents {
+                let mut user_contents = user_contents.clone();
+                if user_content_optional_null {
+                    user_contents
+                        .data
+                        .insert("optional".to_string(), Value::Null);
+                }
 
-            content.user_contents = Some(user_contents.clone());
+                content.user_contents = Some(user_contents);
+            }
+            if let Some(user) = &meta.user {
+                content.other.insert("user".to_string(), user.clone());
+            }
         }
-        if let Some(user) = &self.meta.as_ref().unwrap().user {
-            content.other.insert("user".to_string(), user.clone());
+
+        Ok(content)
+    }
+
+    /// Export content.json byte for byte as ZeroNet writes it,
+    /// restoring fields of the original kept in `meta.pod.data`.
+    /// Formatting is not kept, so a content.json edited by hand out of
+    /// ZeroNet's layout exports equal as JSON only
+    pub fn to_content_json(&self) -> Result<String, Error> {
+        let mut value = serde_json::to_value(self.to_content()?).map_err(Error::json)?;
+        if let Some(pod) = self.meta.as_ref().and_then(|meta| meta.pod.as_ref()) {
+            if let Some(data) = &pod.data {
+                overlay(
+                    &mut value,
+                    data,
+                    &pod.data_typed.clone().unwrap_or_default(),
+                );
+            }
         }
+        Ok(json_dumps(&value))
+    }
+
+    /// Addresses of signatures valid over the content.json this manifest
+    /// exports, as ZeroNet signs user content
+    pub fn content_signers(&self) -> Result<Vec<String>, Error> {
+        let json = self.to_content_json()?;
+        let content = Content::from_buf(ByteBuf::from(json.into_bytes())).map_err(Error::json)?;
+        Ok(self
+            .signatures
+            .iter()
+            .filter(|sign| content.verify(sign.address.clone()))
+            .map(|sign| sign.address.clone())
+            .collect())
+    }
 
-        content
+    /// Save content.json at its inner path under site directory,
+    /// refusing inner paths leaving it
+    pub fn save_content(path: impl AsRef<Path>, content: Content) -> Result<(), Error> {
+        check_path(&content.meta.inner_path)?;
+        let dir = path.as_ref().join(inner_dir(&content.meta.inner_path));
+        let content = serde_json::to_string_pretty(&content).map_err(Error::json)?;
+        write_file(dir.join("content.json"), content.as_bytes())
     }
 
-    pub fn save_content(path: impl AsRef<Path> + Clone, content: Content) -> Option<bool> {
-        fs::create_dir_all(&(path.clone())).unwrap();
-        let content = serde_json::to_string_pretty(&content).unwrap();
-        let path = path.as_ref().join("data/users/content.json");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .unwrap();
-        //create file if not exists including parent directories
-        Some(file.write_all(content.as_bytes()).is_ok())
+    /// Inner path of content.json this manifest was converted from
+    pub fn inner_path(&self) -> &str {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.pod.as_ref())
+            .map(|pod| pod.inner_path.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Write manifest.toml and files.toml into directory
+    pub fn save_to_dir(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        if let Some(files) = &self.files {
+            check_path(&files.manifest)?;
+            if files.file_root != PodFileRoot::default() {
+                files.save(dir)?;
+            }
+        }
+        let content = toml::to_string(&self)?;
+        write_file(dir.join("manifest.toml"), content.as_bytes())
     }
 }
 
 impl IO for PodInternalManifest {
     type Item = PodInternalManifest;
 
-    fn load(content: &str) -> Option<Self::Item> {
-        if let Ok(file) = toml::from_str::<PodInternalManifest>(content) {
-            Some(file)
-        } else {
-            None
-        }
+    fn load(content: &str) -> Result<Self::Item, Error> {
+        PodInternalManifest::from_string(content)
     }
 
-    fn save(&self, path: impl AsRef<Path> + Clone) -> Option<bool> {
-        let content = toml::to_string(&self).unwrap();
-        let path = path.as_ref().join("data/users/manifest.toml");
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .unwrap();
-        //create file if not exists including parent directories
-        Some(file.write_all(content.as_bytes()).is_ok())
+    /// Save at inner path under site directory, refusing inner paths leaving it
+    fn save(&self, path: impl AsRef<Path> + Clone) -> Result<(), Error> {
+        check_path(self.inner_path())?;
+        self.save_to_dir(path.as_ref().join(inner_dir(self.inner_path())))
     }
 
-    fn load_from_path(path: impl AsRef<Path>) -> Option<Self::Item> {
-        let manifest = PodInternalManifest::from(path.as_ref());
-        Some(manifest)
+    fn load_from_path(path: impl AsRef<Path>) -> Result<Self::Item, Error> {
+        PodInternalManifest::try_from(path.as_ref())
     }
 }
 
-impl From<&Path> for PodInternalManifest {
-    fn from(path: &Path) -> PodInternalManifest {
-        let buf = std::fs::read(path).unwrap();
-        let bytes = ByteBuf::from(buf);
-        let content = Content::from_buf(bytes).unwrap();
-        PodInternalManifest::from(&content)
+impl TryFrom<&Path> for PodInternalManifest {
+    type Error = Error;
+
+    fn try_from(path: &Path) -> Result<PodInternalManifest, Error> {
+        let content = content_from_path(path)?;
+        PodInternalManifest::try_from(&content).map_err(|err| err.with_path(path))
     }
 }
 
-impl From<&Content> for PodInternalManifest {
-    fn from(content: &Content) -> Self {
-        Self {
-            files: (!content.files.is_empty()).then_some(PodManifestFiles::from(content)),
+impl TryFrom<&Content> for PodInternalManifest {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<Self, Error> {
+        let instant = datetime_from_number(content.modified.clone())?;
+        let mut manifest = Self {
+            files: if content.files.is_empty() && content.files_optional.is_empty() {
+                None
+            } else {
+                Some(PodManifestFiles::try_from(content)?)
+            },
             signatures: content
                 .signs
                 .iter()
                 .map(|(address, sign)| PodManifestSigns {
                     address: address.clone(),
                     sign: sign.clone(),
-                    instant: datetime_from_number(content.modified.clone()),
+                    instant,
                 })
                 .collect(),
-            meta: Some(PodInternalManifestMeta::from(content)),
+            meta: Some(PodInternalManifestMeta::try_from(content)?),
+        };
+        let original = serde_json::to_value(content).map_err(Error::json)?;
+        let typed = serde_json::to_value(manifest.to_content()?).map_err(Error::json)?;
+        let (data, data_typed) = leftover(&original, &typed);
+        if let Some(pod) = manifest.meta.as_mut().and_then(|meta| meta.pod.as_mut()) {
+            pod.data = (!data.is_empty()).then_some(data);
+            pod.data_typed = (!data_typed.is_empty()).then_some(data_typed);
         }
+        Ok(manifest)
     }
 }
 
-impl From<&Content> for PodInternalManifestMeta {
-    fn from(content: &Content) -> Self {
+impl TryFrom<&Content> for PodInternalManifestMeta {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<Self, Error> {
         let user_contents = content.user_contents.clone();
-        let mut meta = PodInternalManifestMetaPod::from(content);
+        let mut meta = PodInternalManifestMetaPod::try_from(content)?;
         if let Some(UserContents { data, .. }) = &user_contents {
             if let Some(Value::Null) = data.get("optional") {
                 meta.user_contents_optional_null = true;
@@ -172,25 +207,28 @@ impl From<&Content> for PodInternalManifestMeta {
         }
         let user = content.other.get("user").cloned();
 
-        Self {
+        Ok(Self {
             ignore: content.ignore.clone(),
             prev: None,
             pod: Some(meta),
             user_contents,
             cert: content.cert.clone(),
             user,
-        }
+        })
     }
 }
 
-impl From<&Content> for PodInternalManifestMetaPod {
-    fn from(content: &Content) -> Self {
-        Self {
+impl TryFrom<&Content> for PodInternalManifestMetaPod {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<Self, Error> {
+        Ok(Self {
             address: content.address.clone(),
-            modified: datetime_from_number(content.modified.clone()),
+            address_index: content.address_index as usize,
+            modified: datetime_from_number(content.modified.clone())?,
             inner_path: content.meta.inner_path.clone(),
             ..Default::default()
-        }
+        })
     }
 }
 
@@ -200,7 +238,7 @@ mod tests {
     use serde_bytes::ByteBuf;
     use zerucontent::Content;
 
-    use crate::{internal::PodInternalManifest, io::IO};
+    use crate::{error::Error, internal::PodInternalManifest, io::IO};
 
     const TEST_DATA_DIR_TALK: &str = "tests/data/zeronet/talk";
     const TEST_TMP_DIR_TALK: &str = "tests/tmp/data/zeronet/talk";
@@ -217,17 +255,49 @@ mod tests {
     fn test_pod_manifest_save_data_talk() {
         let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/content.json");
         let root = PodInternalManifest::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_TALK);
+        root.save(TEST_TMP_DIR_TALK).unwrap();
+    }
+
+    #[test]
+    fn test_pod_manifest_save_at_inner_path() {
+        let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/user1/content.json");
+        let root = PodInternalManifest::load_from_path(path).unwrap();
+        let dir = "tests/tmp/data/zeronet/talk_inner";
+        root.save(dir).unwrap();
+        let path = format!(
+            "{}/data/users/1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7/manifest.toml",
+            dir
+        );
+        assert!(std::path::Path::new(&path).is_file());
+    }
+
+    #[test]
+    fn test_pod_manifest_save_rejects_escaping_inner_path() {
+        let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/user1/content.json");
+        let mut root = PodInternalManifest::load_from_path(path).unwrap();
+        let inner_path = "../../escape/content.json";
+        let pod = root.meta.as_mut().unwrap().pod.as_mut().unwrap();
+        pod.inner_path = inner_path.to_string();
+        let dir = "tests/tmp/data/zeronet/talk_escape/site";
+        assert!(matches!(root.save(dir), Err(Error::InvalidPath { .. })));
+
+        let mut content = root.to_content().unwrap();
+        content.meta.inner_path = inner_path.to_string();
+        assert!(matches!(
+            PodInternalManifest::save_content(dir, content),
+            Err(Error::InvalidPath { .. })
+        ));
+        assert!(!std::path::Path::new("tests/tmp/data/zeronet/escape").exists());
     }
 
     #[test]
     fn test_pod_content_save_verify_talk() {
         let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/content.json");
         let root = PodInternalManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
-        PodInternalManifest::save_content(TEST_TMP_DIR_TALK, content.clone());
+        PodInternalManifest::save_content(TEST_TMP_DIR_TALK, content.clone()).unwrap();
         let verify = content.verify(content.address.clone());
         assert!(verify);
     }
@@ -236,7 +306,7 @@ mod tests {
     fn test_pod_content_save_user_verify_talk() {
         let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/user1/content.json");
         let root = PodInternalManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
         let verify = content.verify("1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7".into());
@@ -247,7 +317,7 @@ mod tests {
     fn test_pod_content_user_verify_me() {
         let path = format!("{}/{}", TEST_DATA_DIR_ME, "data/users/user1/content.json");
         let root = PodInternalManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
         let verify = content.verify("129AZxKKZFQAyrSxv8ocZtZzPU1Gy6Ua71".into());
@@ -258,7 +328,7 @@ mod tests {
     fn test_pod_content_user2_verify_me() {
         let path = format!("{}/{}", TEST_DATA_DIR_ME, "data/users/user2/content.json");
         let root = PodInternalManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
         let verify = content.verify("1AmeB7f5wBfJm6iR7MRZfFh65xkJzaVCX7".into());
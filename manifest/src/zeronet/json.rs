@@ -0,0 +1,287 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use fancy_regex::{Captures, Regex};
+use serde_json::{Map, Value};
+
+/// Format value like ZeroNet's `helper.jsonDumps`, i.e. python's `json.dumps`
+/// with `indent=1` and `sort_keys=True`, followed by compacting
+/// short objects and lists onto a single line
+pub fn json_dumps(value: &Value) -> String {
+    static COMPACT_DICT: OnceLock<Regex> = OnceLock::new();
+    static COMPACT_LIST: OnceLock<Regex> = OnceLock::new();
+    static TRAILING_SPACE: OnceLock<Regex> = OnceLock::new();
+
+    let mut content = String::new();
+    dump(value, 0, &mut content);
+
+    let compact_dict = COMPACT_DICT
+        .get_or_init(|| Regex::new(r"\{(\n[^,\[\{]{10,}?)\}[, ]{0,2}\n").expect("valid regex"));
+    let content = compact_dict.replace_all(&content, |caps: &Captures| {
+        let (all, inner) = (&caps[0], &caps[1]);
+        all.replace(inner, inner.trim())
+    });
+
+    let compact_list = COMPACT_LIST
+        .get_or_init(|| Regex::new(r"\[([^\[\{]{2,}?)\][, ]{0,2}\n").expect("valid regex"));
+    let content = compact_list.replace_all(&content, |caps: &Captures| {
+        let (all, inner) = (&caps[0], &caps[1]);
+        if !all.contains('\n') {
+            return all.to_string();
+        }
+        let stripped: String = inner
+            .split('\n')
+            .map(|line| line.trim_start_matches(' '))
+            .collect();
+        all.replace(inner, &stripped)
+    });
+
+    let trailing_space =
+        TRAILING_SPACE.get_or_init(|| Regex::new(r"(?m)[ ]+$").expect("valid regex"));
+    trailing_space.replace_all(&content, "").to_string()
+}
+
+fn dump(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() => out.push_str(&python_float(float)),
+            _ => out.push_str(&number.to_string()),
+        },
+        Value::String(string) => dump_str(string, out),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(indent + 1, out);
+                dump(item, indent + 1, out);
+            }
+            newline(indent, out);
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(indent + 1, out);
+                dump_str(key, out);
+                out.push_str(": ");
+                dump(item, indent + 1, out);
+            }
+            newline(indent, out);
+            out.push('}');
+        }
+    }
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+/// String escaped as python's json with `ensure_ascii=True`
+fn dump_str(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            ' '..='~' => out.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+/// Float formatted as python's `repr`
+fn python_float(float: f64) -> String {
+    if !float.is_finite() {
+        return if float.is_nan() {
+            "NaN".to_string()
+        } else if float > 0.0 {
+            "Infinity".to_string()
+        } else {
+            "-Infinity".to_string()
+        };
+    }
+    let scientific = format!("{:e}", float);
+    let (mantissa, exp) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exp: i32 = exp.parse().unwrap_or_default();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    if !(-4..16).contains(&exp) {
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        return format!("{}{}e{}{:02}", sign, mantissa, exp_sign, exp.abs());
+    }
+    let digits = mantissa.replace('.', "");
+    if exp < 0 {
+        let zeros = "0".repeat((-exp - 1) as usize);
+        return format!("{}0.{}{}", sign, zeros, digits);
+    }
+    let int_len = exp as usize + 1;
+    if digits.len() <= int_len {
+        let zeros = "0".repeat(int_len - digits.len());
+        format!("{}{}{}.0", sign, digits, zeros)
+    } else {
+        format!("{}{}.{}", sign, &digits[..int_len], &digits[int_len..])
+    }
+}
+
+/// Top-level fields of original content which typed export does not
+/// reproduce, along with what typed export gave for them instead
+pub(crate) fn leftover(
+    original: &Value,
+    typed: &Value,
+) -> (BTreeMap<String, Value>, BTreeMap<String, Value>) {
+    let (Some(original), Some(typed)) = (original.as_object(), typed.as_object()) else {
+        return Default::default();
+    };
+    let mut data = BTreeMap::new();
+    let mut data_typed = BTreeMap::new();
+    for (key, value) in original {
+        match typed.get(key.as_str()) {
+            Some(typed) if typed == value => continue,
+            Some(typed) => data_typed.insert(key.clone(), typed.clone()),
+            None => None,
+        };
+        data.insert(key.clone(), value.clone());
+    }
+    (data, data_typed)
+}
+
+/// Put leftover fields back over exported content, each only while typed
+/// export still gives what it gave at conversion, so later edits are not
+/// reverted. Objects are restored entry by entry, editing one include or
+/// file keeps the original of the others.
+pub(crate) fn overlay(
+    value: &mut Value,
+    leftover: &BTreeMap<String, Value>,
+    typed: &BTreeMap<String, Value>,
+) {
+    let Some(map) = value.as_object_mut() else {
+        return;
+    };
+    for (key, original) in leftover {
+        restore(map, key, original, typed.get(key));
+    }
+}
+
+fn restore(map: &mut Map<String, Value>, key: &str, original: &Value, typed: Option<&Value>) {
+    if map.get(key) == typed {
+        map.insert(key.to_string(), original.clone());
+        return;
+    }
+    if let (Some(Value::Object(current)), Value::Object(original), Some(Value::Object(typed))) =
+        (map.get_mut(key), original, typed)
+    {
+        for (entry, item) in original {
+            restore(current, entry, item, typed.get(entry));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{json_dumps, leftover, overlay, python_float};
+
+    #[test]
+    fn test_python_float() {
+        assert_eq!(python_float(0.0), "0.0");
+        assert_eq!(python_float(1590595520.0), "1590595520.0");
+        assert_eq!(python_float(1590595520.123), "1590595520.123");
+        assert_eq!(python_float(0.0001), "0.0001");
+        assert_eq!(python_float(0.00001), "1e-05");
+        assert_eq!(python_float(1e16), "1e+16");
+        assert_eq!(python_float(-1.5), "-1.5");
+    }
+
+    #[test]
+    fn test_json_dumps() {
+        let value = json!({
+            "translate": ["index.html", "js/all.js"],
+            "signs": {"1Addr": "sign"},
+            "files": {},
+            "b": {"size": 1, "sha512": "abc"},
+            "unicode": "caf\u{e9}",
+        });
+        let expected = "{\n \"b\": {\n  \"sha512\": \"abc\",\n  \"size\": 1\n },\n \
+            \"files\": {},\n \"signs\": {\"1Addr\": \"sign\"},\n \
+            \"translate\": [\"index.html\",\"js/all.js\"],\n \"unicode\": \"caf\\u00e9\"\n}";
+        assert_eq!(json_dumps(&value), expected);
+    }
+
+    #[test]
+    fn test_leftover_overlay() {
+        let original = json!({"modified": 1590595520.5, "title": "a", "extra": [1]});
+        let typed = json!({"modified": 1590595520, "title": "a"});
+        let (leftover, typed_leftover) = leftover(&original, &typed);
+        assert_eq!(leftover.len(), 2);
+        assert_eq!(typed_leftover.len(), 1);
+
+        let mut value = typed.clone();
+        overlay(&mut value, &leftover, &typed_leftover);
+        assert_eq!(value, original);
+
+        // re-signed manifest keeps its own modified
+        let mut value = json!({"modified": 1600000000, "title": "a"});
+        overlay(&mut value, &leftover, &typed_leftover);
+        assert_eq!(value["modified"], json!(1600000000));
+        assert_eq!(value["extra"], json!([1]));
+    }
+
+    #[test]
+    fn test_overlay_keeps_edits() {
+        let original = json!({"includes": {
+            "a/content.json": {"signers": ["1A"], "files_allowed": "a.json"},
+            "b/content.json": {"signers": ["1B"], "max_size": 100},
+        }});
+        let typed = json!({"includes": {
+            "a/content.json": {"signers": ["1A"]},
+            "b/content.json": {"signers": ["1B"]},
+        }});
+        let (leftover, typed_leftover) = leftover(&original, &typed);
+
+        // edited include keeps its edit along with fields typed export lacks,
+        // the other one is exported as it was
+        let mut value = json!({"includes": {
+            "a/content.json": {"signers": ["1A", "1C"]},
+            "b/content.json": {"signers": ["1B"]},
+        }});
+        overlay(&mut value, &leftover, &typed_leftover);
+        assert_eq!(
+            value["includes"]["a/content.json"],
+            json!({"signers": ["1A", "1C"], "files_allowed": "a.json"})
+        );
+        assert_eq!(
+            value["includes"]["b/content.json"],
+            original["includes"]["b/content.json"]
+        );
+
+        // removed include stays removed
+        let mut value = json!({"includes": {"b/content.json": {"signers": ["1B"]}}});
+        overlay(&mut value, &leftover, &typed_leftover);
+        assert!(value["includes"].get("a/content.json").is_none());
+    }
+}
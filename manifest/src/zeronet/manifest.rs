@@ -1,15 +1,12 @@
-use std::{
-    fs::{self, OpenOptions},
-    io::Write,
-    path::Path,
-};
+use std::path::Path;
 
-use serde_bytes::ByteBuf;
 use zerucontent::{Content, Include};
 
 use crate::{
-    file::PodFileRoot,
-    io::IO,
+    error::Error,
+    file::{check_path, PodFileRoot},
+    hash::HashAlgorithm,
+    io::{write_file, IO},
     manifest::{
         PodManifest, PodManifestExtension, PodManifestExtensionInternal, PodManifestFiles,
         PodManifestMeta, PodManifestMetaClient, PodManifestMetaPod, PodManifestMetaPodParent,
@@ -17,7 +14,14 @@ use crate::{
     },
 };
 
-use super::utils::{datetime_from_number, number_from_datetime};
+use super::{
+    file::SiteOptions,
+    json::{json_dumps, leftover, overlay},
+    utils::{
+        address_index_to_u32, content_files, content_from_path, datetime_from_number,
+        number_from_datetime,
+    },
+};
 
 impl PodManifest {
     pub fn is_zeronet_site(path: impl AsRef<Path>) -> bool {
@@ -31,22 +35,23 @@ impl PodManifest {
         self.files.is_some()
     }
 
-    pub fn to_content(&self) -> Content {
+    /// Convert content.json of site directory, then update files.toml reference
+    pub fn from_site(path: impl AsRef<Path>, options: &SiteOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut manifest = PodManifest::try_from(path.join("content.json").as_path())?;
+        if let Some(files) = &mut manifest.files {
+            files.file_root.apply_site_options(path, options)?;
+            files.update()?;
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_content(&self) -> Result<Content, Error> {
         let mut content = Content::default();
         if let Some(files) = &self.files {
-            content.files = files.file_root.files.iter().fold(
-                std::collections::BTreeMap::new(),
-                |mut map, file| {
-                    map.insert(
-                        file.path.clone(),
-                        zerucontent::File {
-                            sha512: file.hash.clone(),
-                            size: file.size,
-                        },
-                    );
-                    map
-                },
-            );
+            content.files = content_files(&files.file_root.files)?;
+            content.files_optional = content_files(&files.file_root.files_optional)?;
+            content.optional = files.file_root.optional.clone().unwrap_or_default();
         }
         content.address = self.signature.primary.clone();
         content.signers_sign = self.signature.root_sign.clone();
@@ -66,7 +71,7 @@ impl PodManifest {
                 content.cloneable = pod.allow_cloning.unwrap_or_default();
                 content.domain = pod.domain.clone();
                 content.meta.description = Some(pod.description.clone());
-                content.address_index = pod.address_index as u32;
+                content.address_index = address_index_to_u32(pod.address_index)?;
                 content.title = pod.title.clone();
                 content.meta.inner_path = pod.inner_path.clone();
                 content.modified = number_from_datetime(pod.modified);
@@ -106,36 +111,54 @@ impl PodManifest {
                         });
             }
         }
-        content
+        Ok(content)
     }
 
-    pub fn save_content(path: impl AsRef<Path> + Clone, content: Content) -> Option<bool> {
-        fs::create_dir_all(&(path.clone())).unwrap();
-        let content = serde_json::to_string_pretty(&content).unwrap();
+    /// Export content.json byte for byte as ZeroNet writes it,
+    /// restoring fields of the original kept in `meta.pod.data`.
+    /// Formatting is not kept, so a content.json edited by hand out of
+    /// ZeroNet's layout exports equal as JSON only
+    pub fn to_content_json(&self) -> Result<String, Error> {
+        let mut value = serde_json::to_value(self.to_content()?).map_err(Error::json)?;
+        if let Some(pod) = self.meta.as_ref().and_then(|meta| meta.pod.as_ref()) {
+            if let Some(data) = &pod.data {
+                overlay(
+                    &mut value,
+                    data,
+                    &pod.data_typed.clone().unwrap_or_default(),
+                );
+            }
+        }
+        Ok(json_dumps(&value))
+    }
+
+    pub fn save_content(path: impl AsRef<Path>, content: Content) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(&content).map_err(Error::json)?;
         let path = path.as_ref().join("content.json");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .unwrap();
-        //create file if not exists including parent directories
-        Some(file.write_all(content.as_bytes()).is_ok())
+        write_file(path, content.as_bytes())
     }
 }
 
-impl From<&Path> for PodManifest {
-    fn from(path: &Path) -> PodManifest {
-        let buf = std::fs::read(path).unwrap();
-        let bytes = ByteBuf::from(buf);
-        let content = Content::from_buf(bytes).unwrap();
-        PodManifest::from(&content)
+impl TryFrom<&Path> for PodManifest {
+    type Error = Error;
+
+    fn try_from(path: &Path) -> Result<PodManifest, Error> {
+        let content = content_from_path(path)?;
+        PodManifest::try_from(&content).map_err(|err| err.with_path(path))
     }
 }
 
-impl From<&Content> for PodManifest {
-    fn from(content: &Content) -> PodManifest {
-        PodManifest {
-            files: (!content.files.is_empty()).then_some(PodManifestFiles::from(content)),
+impl TryFrom<&Content> for PodManifest {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<PodManifest, Error> {
+        let instant = datetime_from_number(content.modified.clone())?;
+        let mut manifest = PodManifest {
+            files: if content.files.is_empty() && content.files_optional.is_empty() {
+                None
+            } else {
+                Some(PodManifestFiles::try_from(content)?)
+            },
             signature: PodManifestSignature::from(content),
             signatures: content
                 .signs
@@ -143,27 +166,31 @@ impl From<&Content> for PodManifest {
                 .map(|(address, sign)| PodManifestSigns {
                     address: address.clone(),
                     sign: sign.clone(),
-                    instant: datetime_from_number(content.modified.clone()),
+                    instant,
                 })
                 .collect(),
             extensions: (!content.includes.is_empty())
                 .then_some(PodManifestExtension::from(content)),
-            meta: Some(PodManifestMeta::from(content)),
+            meta: Some(PodManifestMeta::try_from(content)?),
+        };
+        let original = serde_json::to_value(content).map_err(Error::json)?;
+        let typed = serde_json::to_value(manifest.to_content()?).map_err(Error::json)?;
+        let (data, data_typed) = leftover(&original, &typed);
+        if let Some(pod) = manifest.meta.as_mut().and_then(|meta| meta.pod.as_mut()) {
+            pod.data = (!data.is_empty()).then_some(data);
+            pod.data_typed = (!data_typed.is_empty()).then_some(data_typed);
         }
+        Ok(manifest)
     }
 }
 
-impl From<&Content> for PodManifestFiles {
-    fn from(content: &Content) -> PodManifestFiles {
-        let file_root = PodFileRoot::from(content);
-        let modified = datetime_from_number(content.modified.clone());
-        PodManifestFiles {
-            manifest: "files.toml".to_string(),
-            size: 0,
-            hash: "".to_string(),
-            modified,
-            file_root,
-        }
+impl TryFrom<&Content> for PodManifestFiles {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<PodManifestFiles, Error> {
+        let file_root = PodFileRoot::try_from(content)?;
+        let modified = datetime_from_number(content.modified.clone())?;
+        PodManifestFiles::new(file_root, modified, HashAlgorithm::Sha512Trunc256)
     }
 }
 
@@ -174,25 +201,38 @@ impl From<&Content> for PodManifestSignature {
             root_sign: content.signers_sign.clone(),
             signs_required: content.signs_required,
             signers: content.signs.keys().cloned().collect(),
+            ..Default::default()
         }
     }
 }
 
-impl From<&Content> for PodManifestMeta {
-    fn from(content: &Content) -> PodManifestMeta {
-        PodManifestMeta {
+impl TryFrom<&Content> for PodManifestMeta {
+    type Error = Error;
+
+    fn try_from(content: &Content) -> Result<PodManifestMeta, Error> {
+        let version = content
+            .meta
+            .zeronet_version
+            .clone()
+            .ok_or_else(|| Error::missing_field("zeronet_version"))?;
+        let description = content
+            .meta
+            .description
+            .clone()
+            .ok_or_else(|| Error::missing_field("description"))?;
+        Ok(PodManifestMeta {
             client: Some(PodManifestMetaClient {
-                version: content.meta.clone().zeronet_version.unwrap().clone(),
+                version,
                 ..Default::default()
             }),
             ignore: content.ignore.clone(),
             pod: Some(PodManifestMetaPod {
                 address: content.address.clone(),
-                description: content.meta.clone().description.unwrap().clone(),
+                description,
                 address_index: content.address_index as usize,
                 title: content.title.clone(),
                 inner_path: content.meta.inner_path.clone(),
-                modified: datetime_from_number(content.modified.clone()),
+                modified: datetime_from_number(content.modified.clone())?,
                 postmessage_nonce_security: content.postmessage_nonce_security,
                 background_color: content.background_color.clone(),
                 background_color_dark: content.background_color_dark.clone(),
@@ -207,9 +247,10 @@ impl From<&Content> for PodManifestMeta {
                     }),
                 settings: { (!content.settings.is_empty()).then_some(content.settings.clone()) },
                 data: None,
+                data_typed: None,
             }),
             prev: None,
-        }
+        })
     }
 }
 
@@ -252,30 +293,24 @@ impl From<&Content> for PodManifestExtension {
 impl IO for PodManifest {
     type Item = PodManifest;
 
-    fn load(content: &str) -> Option<Self::Item> {
-        if let Ok(file) = toml::from_str::<PodManifest>(content) {
-            Some(file)
-        } else {
-            None
-        }
+    fn load(content: &str) -> Result<Self::Item, Error> {
+        PodManifest::from_string(content)
     }
 
-    fn save(&self, path: impl AsRef<Path> + Clone) -> Option<bool> {
-        let content = toml::to_string(&self).unwrap();
-        fs::create_dir_all(&(path.clone())).unwrap();
-        let path = path.as_ref().join("manifest.toml");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .unwrap();
-        //create file if not exists including parent directories
-        Some(file.write_all(content.as_bytes()).is_ok())
+    /// Save in pod directory, refusing a files.toml path leaving it
+    fn save(&self, path: impl AsRef<Path> + Clone) -> Result<(), Error> {
+        if let Some(files) = &self.files {
+            check_path(&files.manifest)?;
+            if files.file_root != PodFileRoot::default() {
+                files.save(path.clone())?;
+            }
+        }
+        let content = toml::to_string(&self)?;
+        write_file(path.as_ref().join("manifest.toml"), content.as_bytes())
     }
 
-    fn load_from_path(path: impl AsRef<Path>) -> Option<Self::Item> {
-        let manifest = PodManifest::from(path.as_ref());
-        Some(manifest)
+    fn load_from_path(path: impl AsRef<Path>) -> Result<Self::Item, Error> {
+        PodManifest::try_from(path.as_ref())
     }
 }
 
@@ -283,9 +318,20 @@ impl IO for PodManifest {
 mod tests {
 
     use serde_bytes::ByteBuf;
+    use serde_json::json;
     use zerucontent::Content;
 
-    use crate::{io::IO, manifest::PodManifest};
+    use chrono::{DateTime, Utc};
+
+    use crate::{
+        crypto::Signer,
+        error::Error,
+        io::IO,
+        manifest::PodManifest,
+        test_utils::new_key,
+        verify::FileStatus,
+        zeronet::file::{ModifiedSource, SiteOptions},
+    };
 
     const TEST_DATA_DIR_BARE: &str = "tests/data/zeronet/bare";
     const TEST_TMP_DIR_BARE: &str = "tests/tmp/data/zeronet/bare";
@@ -313,19 +359,19 @@ mod tests {
     fn test_pod_manifest_save_bare() {
         let path = format!("{}/{}", TEST_DATA_DIR_BARE, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_BARE);
+        root.save(TEST_TMP_DIR_BARE).unwrap();
     }
 
     #[test]
     fn test_pod_content_save_verify_bare() {
         let path = format!("{}/{}", TEST_DATA_DIR_BARE, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
         let verify = content.verify(content.address.clone());
         assert!(verify);
-        PodManifest::save_content(TEST_TMP_DIR_BARE, content);
+        PodManifest::save_content(TEST_TMP_DIR_BARE, content).unwrap();
     }
 
     #[test]
@@ -339,19 +385,19 @@ mod tests {
     fn test_pod_manifest_save_empty() {
         let path = format!("{}/{}", TEST_DATA_DIR_EMPTY, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_EMPTY);
+        root.save(TEST_TMP_DIR_EMPTY).unwrap();
     }
 
     #[test]
     fn test_pod_content_save_verify_empty() {
         let path = format!("{}/{}", TEST_DATA_DIR_EMPTY, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
         let verify = content.verify(content.address.clone());
         assert!(verify);
-        PodManifest::save_content(TEST_TMP_DIR_EMPTY, content);
+        PodManifest::save_content(TEST_TMP_DIR_EMPTY, content).unwrap();
     }
 
     #[test]
@@ -365,17 +411,28 @@ mod tests {
     fn test_pod_manifest_save_hello() {
         let path = format!("{}/{}", TEST_DATA_DIR_HELLO, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_HELLO);
+        root.save(TEST_TMP_DIR_HELLO).unwrap();
+    }
+
+    #[test]
+    fn test_pod_manifest_save_rejects_escaping_files_manifest() {
+        let path = format!("{}/{}", TEST_DATA_DIR_HELLO, "content.json");
+        let mut root = PodManifest::load_from_path(path).unwrap();
+        root.files.as_mut().unwrap().manifest = "../files.toml".to_string();
+        assert!(matches!(
+            root.save("tests/tmp/data/zeronet/hello_escape/site"),
+            Err(Error::InvalidPath { .. })
+        ));
     }
 
     #[test]
     fn test_pod_content_save_verify_hello() {
         let path = format!("{}/{}", TEST_DATA_DIR_HELLO, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
-        PodManifest::save_content(TEST_TMP_DIR_HELLO, content.clone());
+        PodManifest::save_content(TEST_TMP_DIR_HELLO, content.clone()).unwrap();
         let verify = content.verify(content.address.clone());
         assert!(verify);
     }
@@ -391,18 +448,117 @@ mod tests {
     fn test_pod_manifest_save_talk() {
         let path = format!("{}/{}", TEST_DATA_DIR_TALK, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        root.save(TEST_TMP_DIR_TALK);
+        root.save(TEST_TMP_DIR_TALK).unwrap();
     }
 
     #[test]
     fn test_pod_content_save_verify_talk() {
         let path = format!("{}/{}", TEST_DATA_DIR_TALK, "content.json");
         let root = PodManifest::load_from_path(path).unwrap();
-        let content = root.to_content();
+        let content = root.to_content().unwrap();
         let bytes = ByteBuf::from(serde_json::to_vec(&content).unwrap());
         let content = Content::from_buf(bytes).unwrap();
-        PodManifest::save_content(TEST_TMP_DIR_TALK, content.clone());
+        PodManifest::save_content(TEST_TMP_DIR_TALK, content.clone()).unwrap();
         let verify = content.verify(content.address.clone());
         assert!(verify);
     }
+
+    #[test]
+    fn test_pod_manifest_files_reference_hello() {
+        let path = format!("{}/{}", TEST_DATA_DIR_HELLO, "content.json");
+        let root = PodManifest::load_from_path(path).unwrap();
+        let dir = "tests/tmp/data/zeronet/hello_files";
+        root.save(dir).unwrap();
+
+        let files = root.files.as_ref().unwrap();
+        let content = std::fs::read(format!("{}/files.toml", dir)).unwrap();
+        assert_eq!(files.size, content.len());
+        assert!(files.hash.verify(&content));
+
+        let saved = PodManifest::from_string(
+            &std::fs::read_to_string(format!("{}/manifest.toml", dir)).unwrap(),
+        )
+        .unwrap();
+        let report = saved.verify_dir(dir).unwrap();
+        assert_eq!(report.files_manifest.unwrap().status, FileStatus::Ok);
+    }
+
+    #[test]
+    fn test_pod_manifest_from_site_mtime() {
+        let options = SiteOptions {
+            modified: ModifiedSource::FileSystem,
+            ..Default::default()
+        };
+        let root = PodManifest::from_site(TEST_DATA_DIR_EMPTY, &options).unwrap();
+        let files = root.files.as_ref().unwrap();
+        let mtime = std::fs::metadata(format!("{}/{}", TEST_DATA_DIR_EMPTY, "index.html"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(
+            files.file_root.files[0].modified,
+            DateTime::<Utc>::from(mtime)
+        );
+        assert!(files.hash.verify(files.to_toml().unwrap().as_bytes()));
+    }
+
+    #[test]
+    fn test_pod_manifest_files_sign() {
+        let path = format!("{}/{}", TEST_DATA_DIR_HELLO, "content.json");
+        let mut root = PodManifest::load_from_path(path).unwrap();
+        let key = new_key();
+        let files = root.files.as_mut().unwrap();
+        let size = files.size;
+        files.sign(&key).unwrap();
+        assert!(files.file_root.verify_sign(&key.address()));
+        assert!(files.size > size);
+        assert!(files.hash.verify(files.to_toml().unwrap().as_bytes()));
+    }
+
+    #[test]
+    fn test_pod_manifest_missing_zeronet_version() {
+        let path = format!("{}/{}", TEST_DATA_DIR_TALK, "data/users/content.json");
+        let err = PodManifest::load_from_path(&path).unwrap_err();
+        match &err {
+            Error::MissingField { field, .. } => assert_eq!(field, "zeronet_version"),
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert_eq!(err.path().unwrap().to_str().unwrap(), path);
+    }
+
+    #[test]
+    fn test_content_json_keeps_include_edits() {
+        let path = format!("{}/{}", TEST_DATA_DIR_TALK, "content.json");
+        let mut original: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let include = &mut original["includes"]["data/users/content.json"];
+        include["files_allowed"] = "data.json".into();
+        include["max_size"] = 10000.into();
+        let bytes = ByteBuf::from(serde_json::to_vec(&original).unwrap());
+        let content = Content::from_buf(bytes).unwrap();
+        let manifest = PodManifest::try_from(&content).unwrap();
+        let mut manifest = PodManifest::from_string(&toml::to_string(&manifest).unwrap()).unwrap();
+
+        // untouched include comes back as it was
+        let exported: serde_json::Value =
+            serde_json::from_str(&manifest.to_content_json().unwrap()).unwrap();
+        assert_eq!(exported["includes"], original["includes"]);
+
+        let internal = manifest.extensions.as_mut().unwrap().internal.as_mut();
+        let include = &mut internal.unwrap()[0];
+        include
+            .signers
+            .push("1CjfbrbwtP8Y2QjPy12vpTATkUT7oSiPQ9".to_string());
+        include.signs_required = 2;
+        let exported: serde_json::Value =
+            serde_json::from_str(&manifest.to_content_json().unwrap()).unwrap();
+        let include = &exported["includes"]["data/users/content.json"];
+        assert_eq!(
+            include["signers"],
+            json!(["1CjfbrbwtP8Y2QjPy12vpTATkUT7oSiPQ9"])
+        );
+        assert_eq!(include["signers_required"], json!(2));
+        assert_eq!(include["files_allowed"], json!("data.json"));
+        assert_eq!(include["max_size"], json!(10000));
+    }
 }
@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt, fs,
+    path::Path,
+};
+
+use zerucontent::Content;
+
+use crate::{
+    error::Error,
+    file::{check_path, PodFileRoot},
+    internal::PodInternalManifest,
+    io::{copy_file, IO},
+    manifest::PodManifest,
+    verify::{FileReport, FileStatus},
+};
+
+use super::{
+    file::SiteOptions,
+    utils::{content_from_path, inner_dir},
+};
+
+/// Outcome of `migrate_site`
+#[derive(Default, Debug)]
+pub struct MigrationReport {
+    /// manifest.toml files written, relative to destination
+    pub manifests: Vec<String>,
+    /// Payload files copied, relative to destination
+    pub copied: Vec<String>,
+    /// Listed payload files absent from source
+    pub missing: Vec<FileReport>,
+    /// Included content.json files which could not be migrated
+    pub failed: Vec<(String, Error)>,
+}
+
+impl MigrationReport {
+    /// Whether every content.json and required file was migrated
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.missing.iter().all(FileReport::is_ok)
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "manifests: {}", self.manifests.len())?;
+        for path in &self.manifests {
+            writeln!(f, "  {}", path)?;
+        }
+        writeln!(f, "copied files: {}", self.copied.len())?;
+        writeln!(f, "missing files: {}", self.missing.len())?;
+        for report in &self.missing {
+            let kind = if report.optional { " (optional)" } else { "" };
+            writeln!(f, "  {}{}", report.path, kind)?;
+        }
+        writeln!(f, "failed: {}", self.failed.len())?;
+        for (path, err) in &self.failed {
+            writeln!(f, "  {}: {}", path, err)?;
+        }
+        Ok(())
+    }
+}
+
+/// Migrate ZeroNet site directory into DecentNet pod directory
+pub fn migrate_site(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+) -> Result<MigrationReport, Error> {
+    migrate_site_with(src, dst, &SiteOptions::default())
+}
+
+/// Migrate ZeroNet site directory into DecentNet pod directory.
+/// Root content.json must convert, included content.json files
+/// which fail are recorded in the report.
+pub fn migrate_site_with(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    options: &SiteOptions,
+) -> Result<MigrationReport, Error> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let mut report = MigrationReport::default();
+
+    let content = content_from_path(src.join("content.json"))?;
+    let manifest = PodManifest::from_site(src, options)?;
+    if let Some(files) = &manifest.files {
+        files.file_root.check_paths()?;
+    }
+    manifest.save(dst)?;
+    report.manifests.push("manifest.toml".to_string());
+    if let Some(files) = &manifest.files {
+        copy_files(src, dst, "", &files.file_root, &mut report)?;
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut queue: VecDeque<String> = included(src, "", &content)?.into();
+    while let Some(inner_path) = queue.pop_front() {
+        if !visited.insert(inner_path.clone()) {
+            continue;
+        }
+        match migrate_include(src, dst, &inner_path, options, &mut report) {
+            Ok(includes) => queue.extend(includes),
+            Err(err) => report.failed.push((inner_path, err)),
+        }
+    }
+    Ok(report)
+}
+
+/// Migrate included content.json, returning inner paths it includes in turn
+fn migrate_include(
+    src: &Path,
+    dst: &Path,
+    inner_path: &str,
+    options: &SiteOptions,
+    report: &mut MigrationReport,
+) -> Result<Vec<String>, Error> {
+    check_path(inner_path)?;
+    let dir = inner_dir(inner_path);
+    let content = content_from_path(src.join(inner_path))?;
+    let mut manifest = PodInternalManifest::try_from(&content)
+        .map_err(|err| err.with_path(src.join(inner_path)))?;
+    if let Some(files) = &mut manifest.files {
+        files.file_root.check_paths()?;
+        files
+            .file_root
+            .apply_site_options(&src.join(dir), options)?;
+        files.update()?;
+    }
+    manifest.save_to_dir(dst.join(dir))?;
+    report.manifests.push(join(dir, "manifest.toml"));
+    if let Some(files) = &manifest.files {
+        copy_files(src, dst, dir, &files.file_root, report)?;
+    }
+    included(src, dir, &content)
+}
+
+/// Inner paths of content.json files included by content in dir,
+/// through `includes` and user directories permitted by `user_contents`
+fn included(src: &Path, dir: &str, content: &Content) -> Result<Vec<String>, Error> {
+    let mut paths: Vec<String> = content
+        .includes
+        .keys()
+        .map(|path| join(dir, path))
+        .collect();
+    if content.user_contents.is_some() {
+        let path = src.join(dir);
+        let entries = fs::read_dir(&path).map_err(|err| Error::io(&path, err))?;
+        let mut users = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::io(&path, err))?;
+            if entry.path().join("content.json").is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                users.push(join(dir, &format!("{}/content.json", name)));
+            }
+        }
+        users.sort();
+        paths.extend(users);
+    }
+    Ok(paths)
+}
+
+fn copy_files(
+    src: &Path,
+    dst: &Path,
+    dir: &str,
+    file_root: &PodFileRoot,
+    report: &mut MigrationReport,
+) -> Result<(), Error> {
+    let files = file_root.files.iter().map(|file| (file, false));
+    let files_optional = file_root.files_optional.iter().map(|file| (file, true));
+    for (file, optional) in files.chain(files_optional) {
+        let path = join(dir, &file.path);
+        check_path(&path)?;
+        if src.join(&path).is_file() {
+            copy_file(src.join(&path), dst.join(&path))?;
+            report.copied.push(path);
+        } else {
+            report.missing.push(FileReport {
+                path,
+                optional,
+                status: FileStatus::Missing,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", dir, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use serde_json::json;
+
+    use super::migrate_site;
+    use crate::{error::Error, io::write_file, manifest::PodManifest};
+
+    #[test]
+    fn test_migrate_site_empty() {
+        let dst = "tests/tmp/migrate/empty";
+        let report = migrate_site("tests/data/zeronet/empty", dst).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.copied, vec!["index.html"]);
+
+        let content = std::fs::read_to_string(format!("{}/manifest.toml", dst)).unwrap();
+        let manifest = PodManifest::from_string(&content).unwrap();
+        assert!(manifest.verify_dir(dst).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_migrate_site_talk_includes() {
+        let report = migrate_site("tests/data/zeronet/talk", "tests/tmp/migrate/talk").unwrap();
+        assert_eq!(
+            report.manifests,
+            vec![
+                "manifest.toml",
+                "data/users/manifest.toml",
+                "data/users/user1/manifest.toml"
+            ]
+        );
+        assert!(report.failed.is_empty());
+        // fixture carries content.json files only
+        assert!(!report.missing.is_empty());
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn test_migrate_site_rejects_escaping_paths() {
+        let src = "tests/tmp/migrate/escape/src";
+        let fixture = fs::read_to_string("tests/data/zeronet/empty/content.json").unwrap();
+        let mut content: serde_json::Value = serde_json::from_str(&fixture).unwrap();
+        let index = content["files"]["index.html"].take();
+        content["files"] = json!({});
+        content["includes"] = json!({
+            "../../outside/content.json": {"signers": [], "signers_required": 1}
+        });
+        write_file(
+            format!("{}/content.json", src),
+            content.to_string().as_bytes(),
+        )
+        .unwrap();
+        let report = migrate_site(src, "tests/tmp/migrate/escape/dst").unwrap();
+        assert_eq!(report.failed.len(), 1);
+        assert!(matches!(report.failed[0].1, Error::InvalidPath { .. }));
+
+        content["files"] = json!({ "../secret.txt": index });
+        write_file(
+            format!("{}/content.json", src),
+            content.to_string().as_bytes(),
+        )
+        .unwrap();
+        write_file("tests/tmp/migrate/escape/secret.txt", b"secret").unwrap();
+        let dst = "tests/tmp/migrate/escape/dst_files";
+        let err = migrate_site(src, dst).unwrap_err();
+        assert!(matches!(err, Error::InvalidPath { .. }));
+        assert!(!Path::new(dst).join("manifest.toml").exists());
+    }
+}
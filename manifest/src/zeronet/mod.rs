@@ -1,4 +1,6 @@
 pub mod file;
 pub mod internal;
+pub mod json;
 pub mod manifest;
+pub mod migrate;
 pub mod utils;
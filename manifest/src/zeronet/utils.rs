@@ -1,7 +1,12 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
-use zerucontent::Number;
+use std::{collections::BTreeMap, path::Path};
 
-pub fn datetime_from_number(modified: Number) -> DateTime<Utc> {
+use chrono::{DateTime, Utc};
+use serde_bytes::ByteBuf;
+use zerucontent::{Content, Number};
+
+use crate::{error::Error, file::PodFile};
+
+pub fn datetime_from_number(modified: Number) -> Result<DateTime<Utc>, Error> {
     let epoch = if let Number::Integer(epoch) = modified {
         let count = epoch.checked_ilog10().unwrap_or(0) + 1;
         if count == 13 {
@@ -9,14 +14,18 @@ pub fn datetime_from_number(modified: Number) -> DateTime<Utc> {
         } else if count == 10 {
             (epoch * 1000) as i64
         } else {
-            unreachable!("epoch is not 10 or 13 digits");
+            return Err(Error::zeronet(format!(
+                "modified {} is not a 10 or 13 digit epoch",
+                epoch
+            )));
         }
     } else if let Number::Float(epoch) = modified {
         (epoch * 1000.0) as i64
     } else {
-        unreachable!("modified is not integer or float");
+        return Err(Error::zeronet("modified is not integer or float"));
     };
-    DateTime::from_naive_utc_and_offset(NaiveDateTime::from_timestamp_millis(epoch).unwrap(), Utc)
+    DateTime::from_timestamp_millis(epoch)
+        .ok_or_else(|| Error::zeronet(format!("modified {} is out of range", epoch)))
 }
 
 pub fn number_from_datetime(modified: DateTime<Utc>) -> Number {
@@ -24,9 +33,48 @@ pub fn number_from_datetime(modified: DateTime<Utc>) -> Number {
     Number::Integer(epoch as usize)
 }
 
+/// Address index as content.json holds it, refusing one that does not fit
+pub fn address_index_to_u32(address_index: usize) -> Result<u32, Error> {
+    u32::try_from(address_index)
+        .map_err(|_| Error::zeronet(format!("address_index {} is out of range", address_index)))
+}
+
+/// Files as content.json lists them, refusing hashes ZeroNet cannot check
+pub fn content_files(files: &[PodFile]) -> Result<BTreeMap<String, zerucontent::File>, Error> {
+    files
+        .iter()
+        .map(|file| {
+            let sha512 = file
+                .hash
+                .to_sha512t_hex()
+                .ok_or_else(|| Error::InvalidHash {
+                    hash: file.hash.to_string(),
+                    message: format!("{} is not hashed with truncated sha512", file.path),
+                })?;
+            let size = file.size;
+            Ok((file.path.clone(), zerucontent::File { sha512, size }))
+        })
+        .collect()
+}
+
+/// Read and parse a ZeroNet content.json
+pub fn content_from_path(path: impl AsRef<Path>) -> Result<Content, Error> {
+    let path = path.as_ref();
+    let buf = std::fs::read(path).map_err(|err| Error::io(path, err))?;
+    Content::from_buf(ByteBuf::from(buf)).map_err(|err| Error::json(err).with_path(path))
+}
+
+/// Directory of content.json inner path, empty for site root
+pub fn inner_dir(inner_path: &str) -> &str {
+    inner_path
+        .rsplit_once('/')
+        .map(|(dir, _)| dir)
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
-    use chrono::{DateTime, NaiveDateTime};
+    use chrono::DateTime;
 
     #[test]
     fn test_datetime_from_number() {
@@ -34,23 +82,29 @@ mod tests {
         use zerucontent::Number;
 
         let modified = Number::Integer(1610000000000);
-        let datetime = datetime_from_number(modified);
+        let datetime = datetime_from_number(modified).unwrap();
         assert_eq!(datetime.timestamp_millis(), 1610000000000);
 
         let modified = Number::Float(1_610_000_000.000);
-        let datetime = datetime_from_number(modified);
+        let datetime = datetime_from_number(modified).unwrap();
         assert_eq!(datetime.timestamp_millis(), 1610000000000);
     }
 
+    #[test]
+    fn test_datetime_from_number_invalid() {
+        use super::datetime_from_number;
+        use zerucontent::Number;
+
+        let modified = Number::Integer(16100000);
+        assert!(datetime_from_number(modified).is_err());
+    }
+
     #[test]
     fn test_number_from_datetime() {
         use super::number_from_datetime;
         use zerucontent::Number;
 
-        let modified = DateTime::from_naive_utc_and_offset(
-            NaiveDateTime::from_timestamp_millis(1609977600000).unwrap(),
-            chrono::Utc,
-        );
+        let modified = DateTime::from_timestamp_millis(1609977600000).unwrap();
         let number = number_from_datetime(modified);
         if let Number::Integer(number) = number {
             assert_eq!(number, 1609977600);
@@ -58,4 +112,44 @@ mod tests {
             unreachable!("number is not integer");
         }
     }
+
+    #[test]
+    fn test_address_index_to_u32() {
+        use super::address_index_to_u32;
+
+        assert_eq!(address_index_to_u32(42).unwrap(), 42);
+        assert!(address_index_to_u32(u32::MAX as usize + 1).is_err());
+    }
+
+    #[test]
+    fn test_content_files() {
+        use super::content_files;
+        use crate::{
+            error::Error,
+            file::PodFile,
+            hash::{FileHash, HashAlgorithm},
+        };
+
+        let mut file = PodFile {
+            path: "index.html".to_string(),
+            hash: FileHash::digest(HashAlgorithm::Sha512Trunc256, b"index"),
+            size: 5,
+            ..Default::default()
+        };
+        let files = content_files(std::slice::from_ref(&file)).unwrap();
+        assert_eq!(files["index.html"].size, 5);
+        assert_eq!(files["index.html"].sha512.len(), 64);
+
+        file.hash = FileHash::digest(HashAlgorithm::Blake3, b"index");
+        let err = content_files(&[file]).unwrap_err();
+        assert!(matches!(err, Error::InvalidHash { .. }));
+    }
+
+    #[test]
+    fn test_inner_dir() {
+        use super::inner_dir;
+
+        assert_eq!(inner_dir("content.json"), "");
+        assert_eq!(inner_dir("data/users/content.json"), "data/users");
+    }
 }
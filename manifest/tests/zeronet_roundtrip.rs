@@ -0,0 +1,74 @@
+#![cfg(feature = "zeronet")]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use decentnet_manifest::{
+    file::PodFileRoot,
+    internal::PodInternalManifest,
+    io::IO,
+    manifest::{PodManifest, PodManifestFiles},
+    zeronet::json::json_dumps,
+};
+
+const TEST_DATA_DIR: &str = "tests/data/zeronet";
+/// content.json edited by hand, not in the layout ZeroNet writes
+const HAND_EDITED: &str = "talk/data/users/content.json";
+
+fn content_paths(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            content_paths(&path, paths);
+        } else if path.file_name().unwrap() == "content.json" {
+            paths.push(path);
+        }
+    }
+}
+
+/// files.toml is stored beside manifest.toml, so restore it as loading from disk would
+fn restore_file_root(files: &mut Option<PodManifestFiles>, original: &Option<PodManifestFiles>) {
+    if let (Some(files), Some(original)) = (files, original) {
+        files.file_root = PodFileRoot::from_string(&original.to_toml().unwrap()).unwrap();
+    }
+}
+
+/// Convert content.json to manifest.toml and back, through TOML as migration stores it
+fn export(original: &serde_json::Value, path: &Path) -> String {
+    if original["inner_path"] == "content.json" {
+        let manifest = PodManifest::load_from_path(path).unwrap();
+        let mut loaded = PodManifest::from_string(&toml::to_string(&manifest).unwrap()).unwrap();
+        restore_file_root(&mut loaded.files, &manifest.files);
+        loaded.to_content_json().unwrap()
+    } else {
+        let manifest = PodInternalManifest::load_from_path(path).unwrap();
+        let mut loaded =
+            PodInternalManifest::from_string(&toml::to_string(&manifest).unwrap()).unwrap();
+        restore_file_root(&mut loaded.files, &manifest.files);
+        loaded.to_content_json().unwrap()
+    }
+}
+
+#[test]
+fn test_zeronet_corpus_roundtrip() {
+    let mut paths = vec![];
+    content_paths(Path::new(TEST_DATA_DIR), &mut paths);
+    paths.sort();
+    assert!(paths.len() >= 8);
+
+    for path in &paths {
+        let bytes = fs::read_to_string(path).unwrap();
+        let original: serde_json::Value = serde_json::from_str(&bytes).unwrap();
+        let exported = export(&original, path);
+        if path.strip_prefix(TEST_DATA_DIR).unwrap() == Path::new(HAND_EDITED) {
+            // exported in ZeroNet's layout, so only equal as JSON
+            assert_ne!(bytes, json_dumps(&original));
+            let exported: serde_json::Value = serde_json::from_str(&exported).unwrap();
+            assert_eq!(exported, original, "{}", path.display());
+        } else {
+            assert_eq!(exported, bytes, "{}", path.display());
+        }
+    }
+}